@@ -0,0 +1,82 @@
+//! Compares closest polygon and projection queries using the query grid against a linear scan
+use std::{sync::Arc, time::Instant};
+
+use constructive::{
+    brush::{Brush, PositionedBrush},
+    navmesh::{Navmesh, NavmeshSettings},
+};
+use glam::{vec3, Mat4, Vec3};
+
+const SIZE: u32 = 100;
+const QUERIES: usize = 1000;
+
+fn main() {
+    // Separate platforms at varying heights, so that no polygons are merged
+    let brushes = (0..SIZE).flat_map(|x| {
+        (0..SIZE).map(move |z| {
+            let height = ((x * 7 + z * 3) % 4) as f32 * 0.2;
+            PositionedBrush::new(
+                Mat4::from_translation(vec3(x as f32 * 2.0, height, z as f32 * 2.0))
+                    * Mat4::from_scale(vec3(0.8, 0.2, 0.8)),
+                Arc::new(Brush::cube()),
+            )
+        })
+    });
+    let brushes = brushes.collect::<Vec<_>>();
+
+    let mut seed = 0x2545_f491_u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32
+    };
+
+    let extent = SIZE as f32 * 2.0;
+    let points = (0..QUERIES)
+        .map(|_| vec3(random() * extent - 1.0, 0.5, random() * extent - 1.0))
+        .collect::<Vec<Vec3>>();
+
+    let mut results = Vec::new();
+    for (name, cell_size) in [("grid", None), ("linear", Some(f32::INFINITY))] {
+        let settings = NavmeshSettings {
+            query_grid_cell_size: cell_size,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let navmesh = Navmesh::new(settings, brushes.iter().cloned());
+        let generated = start.elapsed();
+
+        let start = Instant::now();
+        let found = points
+            .iter()
+            .map(|&p| std::hint::black_box(navmesh.closest_polygon(p)).map(|v| v.0))
+            .collect::<Vec<_>>();
+        let closest = start.elapsed();
+
+        let start = Instant::now();
+        let projected = points
+            .iter()
+            .map(|&p| std::hint::black_box(navmesh.project_point(p, 1.0)))
+            .collect::<Vec<_>>();
+        let project = start.elapsed();
+
+        println!(
+            "{name}: {} polygons generated in {generated:?}, {:?} per closest polygon query, \
+             {:?} per projection",
+            navmesh.polygons().len(),
+            closest / QUERIES as u32,
+            project / QUERIES as u32,
+        );
+
+        results.push(((found, projected), closest, project));
+    }
+
+    assert_eq!(results[0].0, results[1].0);
+    println!(
+        "speedup: {:.1}x closest polygon, {:.1}x projection",
+        results[1].1.as_secs_f64() / results[0].1.as_secs_f64(),
+        results[1].2.as_secs_f64() / results[0].2.as_secs_f64()
+    );
+}
@@ -0,0 +1,105 @@
+//! Compares navmesh generation against unioning every brush into a single tree, and measures
+//! how generation scales for a connected layout where every brush ends up in the same group
+use std::{sync::Arc, time::Instant};
+
+use constructive::{
+    brush::{Brush, PositionedBrush},
+    navmesh::{Navmesh, NavmeshSettings},
+    tree::BspTree,
+};
+use glam::{vec3, Mat4};
+
+const BRUSHES: usize = 100;
+const TILES: [usize; 3] = [25, 50, 100];
+
+fn main() {
+    let mut seed = 0x2545_f491_u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32
+    };
+
+    // Scattered cubes, some of them overlapping
+    let brushes = (0..BRUSHES)
+        .map(|_| {
+            let translation = vec3(random() * 40.0, random() * 2.0, random() * 40.0);
+            let scale = vec3(0.5 + random() * 2.0, 0.2 + random(), 0.5 + random() * 2.0);
+            PositionedBrush::new(
+                Mat4::from_translation(translation) * Mat4::from_scale(scale),
+                Arc::new(Brush::cube()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let settings = NavmeshSettings {
+        agent_radius: 0.0,
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let tree = brushes
+        .iter()
+        .filter_map(|v| {
+            BspTree::build_with_epsilon(&v.world_faces().collect::<Vec<_>>(), settings.epsilon)
+        })
+        .reduce(|mut tree, other| {
+            tree.union(other);
+            tree
+        })
+        .unwrap();
+    let polygons = tree.into_polygons_cleaned(settings.min_polygon_area);
+    let sequential = start.elapsed();
+    println!(
+        "sequential union: {} polygons in {sequential:?}",
+        polygons.len()
+    );
+
+    let start = Instant::now();
+    let (navmesh, report) = Navmesh::new_with_report(settings, brushes);
+    let grouped = start.elapsed();
+    println!(
+        "navmesh: {} polygons, {} walkable in {grouped:?}",
+        report.polygon_count,
+        navmesh.polygons().len()
+    );
+
+    println!(
+        "speedup: {:.1}x",
+        sequential.as_secs_f64() / grouped.as_secs_f64()
+    );
+
+    // A floor of touching tiles at varying heights, which forms a single group
+    let mut previous = None;
+    for size in TILES {
+        let tiles = (0..size).flat_map(|x| {
+            (0..size).map(move |z| {
+                let height = ((x * 7 + z * 3) % 4) as f32 * 0.1;
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(x as f32 * 2.0, height, z as f32 * 2.0))
+                        * Mat4::from_scale(vec3(1.0, 0.2, 1.0)),
+                    Arc::new(Brush::cube()),
+                )
+            })
+        });
+
+        let start = Instant::now();
+        let (navmesh, report) = Navmesh::new_with_report(settings, tiles);
+        let elapsed = start.elapsed();
+        println!(
+            "{size}x{size} tiles: {} polygons, {} walkable in {elapsed:?}",
+            report.polygon_count,
+            navmesh.polygons().len()
+        );
+
+        // Quadratic generation would take 16 times as long for twice the tiles along each side
+        if let Some((previous, time)) = previous {
+            let tiles = (size * size) as f64 / (previous * previous) as f64;
+            let ratio = elapsed.as_secs_f64() / f64::max(time, f64::EPSILON);
+            println!("  {tiles:.0}x the tiles took {ratio:.1}x as long");
+        }
+
+        previous = Some((size, elapsed.as_secs_f64()));
+    }
+}
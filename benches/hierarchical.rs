@@ -0,0 +1,86 @@
+//! Compares the expanded nodes and throughput of flat and hierarchical astar on a large level
+use std::{sync::Arc, time::Instant};
+
+use constructive::{
+    astar::{AstarOptions, DefaultCostModel, PathfindingContext},
+    brush::{Brush, PositionedBrush},
+    navmesh::{Navmesh, NavmeshSettings},
+};
+use glam::{vec3, Mat4, Vec3};
+
+const SIZE: u32 = 50;
+const QUERIES: usize = 100;
+
+fn main() {
+    // Deterministic pseudo random pillar placement
+    let mut seed = 0x2545_f491_u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    let cube = |translation: Vec3, scale: Vec3| {
+        PositionedBrush::new(
+            Mat4::from_translation(translation) * Mat4::from_scale(scale),
+            Arc::new(Brush::cube()),
+        )
+    };
+
+    let mut brushes = Vec::new();
+    for x in 0..SIZE {
+        for z in 0..SIZE {
+            let center = vec3(x as f32 * 2.0, 0.0, z as f32 * 2.0);
+            brushes.push(cube(center, vec3(1.0, 0.2, 1.0)));
+
+            let corner = (x, z) == (0, 0) || (x, z) == (SIZE - 1, SIZE - 1);
+            if !corner && random() % 6 == 0 {
+                brushes.push(cube(center + Vec3::Y * 1.5, vec3(0.6, 1.5, 0.6)));
+            }
+        }
+    }
+
+    let start = Instant::now();
+    let mut navmesh = Navmesh::new(NavmeshSettings::default(), brushes);
+    navmesh.build_clusters(8.0);
+    println!(
+        "{} polygons, {} clusters, generated in {:?}",
+        navmesh.polygons().len(),
+        navmesh.clusters().unwrap().cluster_count(),
+        start.elapsed()
+    );
+
+    let model = DefaultCostModel::new(|a: Vec3, b: Vec3| a.distance(b));
+    let options = AstarOptions::default();
+    let from = vec3(0.0, 0.5, 0.0);
+    let to = vec3((SIZE - 1) as f32 * 2.0, 0.5, (SIZE - 1) as f32 * 2.0);
+
+    let mut context = PathfindingContext::new();
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        std::hint::black_box(context.find_path_with_model(&navmesh, from, to, &model, &options))
+            .unwrap();
+    }
+
+    println!(
+        "flat: {:?} per query, {} expansions",
+        start.elapsed() / QUERIES as u32,
+        context.expansions()
+    );
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        std::hint::black_box(
+            context.find_path_hierarchical_with_model(&navmesh, from, to, &model, &options),
+        )
+        .unwrap();
+    }
+
+    println!(
+        "hierarchical: {:?} per query, {} expansions",
+        start.elapsed() / QUERIES as u32,
+        context.expansions()
+    );
+}
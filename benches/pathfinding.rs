@@ -0,0 +1,96 @@
+//! Compares allocations and throughput of one-off astar queries and a reused context.
+//!
+//! The queries are short paths across a large terrain, where a one-off query spends most of its
+//! time allocating and clearing buffers sized to the whole navmesh.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use constructive::{
+    astar::{astar, PathfindingContext},
+    brush::Brush,
+    navmesh::{Navmesh, NavmeshSettings},
+};
+use glam::{vec3, Mat4};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const QUERIES: usize = 1000;
+const SIZE: usize = 96;
+
+/// Returns the time and the number of allocations per query
+fn measure(name: &str, mut f: impl FnMut()) -> (Duration, f32) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for _ in 0..QUERIES {
+        f();
+    }
+
+    let elapsed = start.elapsed() / QUERIES as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f32 / QUERIES as f32;
+
+    println!("{name}: {elapsed:?} per query, {allocations} allocations per query");
+    (elapsed, allocations)
+}
+
+fn main() {
+    // Gently rolling terrain, triangulated into many small polygons
+    let heights = (0..SIZE * SIZE)
+        .map(|i| {
+            let (x, z) = ((i % SIZE) as f32, (i / SIZE) as f32);
+            (x * 0.4).sin() * (z * 0.3).cos() * 0.3
+        })
+        .collect::<Vec<_>>();
+
+    let terrain = Brush::from_heightfield(&heights, SIZE, SIZE, 1.0, -2.0).unwrap();
+    let navmesh = Navmesh::new(NavmeshSettings::default(), [(Mat4::IDENTITY, terrain)]);
+    println!(
+        "{} polygons, {} links",
+        navmesh.polygons().len(),
+        navmesh.links().len()
+    );
+
+    let start = vec3(-4.0, 0.5, -3.0);
+    let end = vec3(4.0, 0.5, 3.0);
+    assert!(navmesh.find_path(start, end).is_some());
+
+    let (one_off, _) = measure("astar", || {
+        std::hint::black_box(astar(&navmesh, start, end, |a, b| a.distance(b)));
+    });
+
+    // Warm up the buffers, after which queries no longer allocate
+    let mut context = PathfindingContext::new();
+    context.find_path(&navmesh, start, end, |a, b| a.distance(b));
+
+    let (reused, allocations) = measure("context", || {
+        std::hint::black_box(context.find_path(&navmesh, start, end, |a, b| a.distance(b)));
+    });
+
+    let ratio = one_off.as_secs_f64() / reused.as_secs_f64();
+    println!("reusing the context is {ratio:.1}x faster");
+
+    assert_eq!(allocations, 0.0, "reused context allocated");
+    assert!(
+        ratio >= 2.0,
+        "reusing the context is only {ratio:.1}x faster"
+    );
+}
@@ -0,0 +1,123 @@
+//! Generates a navmesh from a few brushes and finds a path across it, without a renderer.
+//!
+//! Pass a file name to also write the navmesh to a Wavefront OBJ file for inspection:
+//!
+//! ```sh
+//! cargo run --example headless --features io -- navmesh.obj
+//! ```
+
+use std::{fs::File, io::BufWriter, sync::Arc};
+
+use constructive::{
+    astar::astar,
+    brush::{Brush, CsgOp, PositionedBrush},
+    navmesh::{Navmesh, NavmeshSettings},
+};
+use glam::{vec3, Mat4, Quat, Vec3};
+
+fn brush(brush: Brush, rotation: Quat, translation: Vec3, scale: Vec3) -> PositionedBrush {
+    PositionedBrush::new(
+        Mat4::from_scale_rotation_translation(scale, rotation, translation),
+        Arc::new(brush),
+    )
+}
+
+fn main() -> std::io::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let cube = Brush::cube();
+    let ops = [
+        // A floor with a raised platform on it
+        (
+            CsgOp::Union,
+            brush(
+                cube.clone(),
+                Quat::IDENTITY,
+                Vec3::ZERO,
+                vec3(10.0, 0.4, 10.0),
+            ),
+        ),
+        (
+            CsgOp::Union,
+            brush(
+                cube.clone(),
+                Quat::IDENTITY,
+                vec3(-6.0, 0.6, -6.0),
+                vec3(2.0, 0.2, 2.0),
+            ),
+        ),
+        // A wall across the floor, with a doorway carved out of it
+        (
+            CsgOp::Union,
+            brush(
+                cube.clone(),
+                Quat::IDENTITY,
+                vec3(0.0, 1.5, 0.0),
+                vec3(0.25, 1.5, 10.0),
+            ),
+        ),
+        (
+            CsgOp::Subtract,
+            brush(
+                cube.clone(),
+                Quat::IDENTITY,
+                vec3(0.0, 1.5, 4.0),
+                vec3(1.0, 1.2, 1.0),
+            ),
+        ),
+        // A rotated pillar and a sphere to walk around
+        (
+            CsgOp::Union,
+            brush(
+                cube,
+                Quat::from_axis_angle(Vec3::Y, 0.8),
+                vec3(4.0, 1.0, -3.0),
+                vec3(0.8, 1.0, 0.8),
+            ),
+        ),
+        (
+            CsgOp::Union,
+            brush(
+                Brush::uv_sphere(1.0, 16, 12),
+                Quat::IDENTITY,
+                vec3(-4.0, 0.5, 3.0),
+                Vec3::ONE,
+            ),
+        ),
+    ];
+
+    let (navmesh, report) = Navmesh::from_csg_with_report(NavmeshSettings::default(), ops);
+    println!("{report}");
+
+    let (min, max) = navmesh.aabb();
+    println!("walkable bounds: {min} to {max}");
+
+    // From the platform, through the doorway, to the far side of the wall
+    let (start, end) = (vec3(-6.0, 1.0, -6.0), vec3(6.0, 0.6, 6.0));
+    match astar(&navmesh, start, end, |a, b| a.distance(b)) {
+        Some(path) => {
+            println!(
+                "path of {} waypoints, length {:.2}:",
+                path.waypoints().len(),
+                path.length()
+            );
+
+            for waypoint in path.waypoints() {
+                match waypoint.step_height() {
+                    Some(height) if height != 0.0 => {
+                        println!("  {} (step of {height:+.2})", waypoint.point())
+                    }
+                    _ => println!("  {}", waypoint.point()),
+                }
+            }
+        }
+        None => println!("no path from {start} to {end}"),
+    }
+
+    if let Some(path) = std::env::args().nth(1) {
+        navmesh.export_debug_obj(BufWriter::new(File::create(&path)?))?;
+        println!("wrote navmesh to {path}");
+    }
+
+    Ok(())
+}
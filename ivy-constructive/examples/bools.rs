@@ -1,4 +1,4 @@
-use constructive::{brush::Brush, tree::BspTree};
+use constructive::brush::Brush;
 use glam::{vec3, Mat4, Quat, Vec2, Vec3};
 use ivy_engine::{
     flax::{Entity, World},
@@ -74,9 +74,7 @@ impl Plugin for ExamplePlugin {
         assets: &AssetCache,
         _: &mut ivy_engine::ivy_core::update_layer::ScheduleSetBuilder,
     ) -> anyhow::Result<()> {
-        let mut brush = Brush::cube();
-        brush.transform(Mat4::from_scale(vec3(10.0, 0.4, 10.0)));
-        let mut brush = BspTree::build(brush.faces()).unwrap();
+        let floor = Brush::cube().with_transform(Mat4::from_scale(vec3(10.0, 0.4, 10.0)));
 
         let clip_brushes = [
             Brush::cube().with_transform(Mat4::from_rotation_translation(
@@ -91,22 +89,22 @@ impl Plugin for ExamplePlugin {
                 Quat::from_axis_angle(Vec3::Y, 2.0),
                 vec3(3.0, 0.5, 4.0),
             )),
-            Brush::uv_sphere().with_transform(Mat4::from_rotation_translation(
+            Brush::uv_sphere(1.0, 16, 12).with_transform(Mat4::from_rotation_translation(
                 Quat::IDENTITY,
                 vec3(7.0, 0.5, 0.0),
             )),
         ];
 
-        for clip in &clip_brushes {
-            brush.clip_to(&BspTree::build(clip.faces()).unwrap());
-        }
+        let brush = clip_brushes
+            .iter()
+            .fold(floor, |brush, clip| brush.subtract(clip));
 
         let flat_material = PbrMaterialData::new();
 
         Entity::builder()
             .mount(TransformBundle::default())
             .mount(RenderObjectBundle::new(
-                MeshDesc::Content(assets.insert(brush_to_mesh(&Brush::new(brush.polygons())))),
+                MeshDesc::Content(assets.insert(brush_to_mesh(&brush))),
                 &[(
                     forward_pass(),
                     MaterialData::UnlitMaterial(flat_material.clone()),
@@ -135,11 +133,15 @@ impl Plugin for ExamplePlugin {
 }
 
 fn brush_to_mesh(brush: &Brush) -> MeshData {
-    let vertex_count = brush.faces().len() * 3;
+    let vertex_count = brush
+        .faces()
+        .iter()
+        .map(|v| v.triangulate().count() * 3)
+        .sum::<usize>();
     let mut mesh = MeshData::new()
         .with_attribute(
             POSITION_ATTRIBUTE,
-            brush.faces().iter().flat_map(|v| v.points()),
+            brush.faces().iter().flat_map(|v| v.triangulate().flatten()),
         )
         .with_attribute(
             TEX_COORD_ATTRIBUTE,
@@ -147,10 +149,10 @@ fn brush_to_mesh(brush: &Brush) -> MeshData {
         )
         .with_attribute(
             NORMAL_ATTRIBUTE,
-            brush.faces().iter().flat_map(|v| {
-                let n = v.normal();
-                [n, n, n]
-            }),
+            brush
+                .faces()
+                .iter()
+                .flat_map(|v| std::iter::repeat_n(v.normal(), v.triangulate().count() * 3)),
         )
         .with_indices(0..vertex_count as u32);
 
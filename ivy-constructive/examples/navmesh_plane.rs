@@ -1,4 +1,9 @@
-use constructive::{astar::astar, brush::Brush, navmesh::NavmeshSettings};
+use constructive::{
+    astar::astar,
+    brush::Brush,
+    link::LinkKind,
+    navmesh::{NavmeshSettings, RaycastResult},
+};
 use glam::{vec3, Mat4, Quat, Vec2, Vec3};
 use itertools::Itertools;
 use ivy_constructive::{
@@ -68,7 +73,10 @@ pub fn main() -> anyhow::Result<()> {
             ScheduledLayer::new(FixedTimeStep::new(0.02))
                 .with_plugin(OrbitCameraPlugin)
                 .with_plugin(RayPickingPlugin)
-                .with_plugin(NavmeshPlugin::new(NavmeshSettings::default()))
+                .with_plugin(NavmeshPlugin::new(NavmeshSettings {
+                    merge_polygons: true,
+                    ..Default::default()
+                }))
                 .with_plugin(NavmeshDebugPlugin)
                 .with_plugin(ExamplePlugin)
                 .with_plugin(PhysicsPlugin::new().with_gravity(Vec3::ZERO)),
@@ -84,6 +92,8 @@ pub fn main() -> anyhow::Result<()> {
         .run()
 }
 
+const LADDER_TAG: u32 = 1;
+
 struct ExamplePlugin;
 
 impl Plugin for ExamplePlugin {
@@ -173,6 +183,34 @@ impl Plugin for ExamplePlugin {
             .mount(ColliderBundle::new(SharedShape::ball(0.2)))
             .spawn(world);
 
+        // The top of the pillar can only be reached by a ladder, which is slower than walking.
+        //
+        // Authored links are kept when the navmesh is rebuilt, so it only needs to be added once
+        events
+            .fixed_mut()
+            .with_system(System::builder().with_world().build(|world: &World| {
+                let Ok(mut navmesh) = world.get_mut(engine(), navmesh()) else {
+                    return anyhow::Ok(());
+                };
+
+                let has_ladder = navmesh
+                    .links()
+                    .iter()
+                    .any(|(_, v)| matches!(v.kind(), LinkKind::OffMesh { .. }));
+
+                if !has_ladder {
+                    navmesh.add_offmesh_link_with_cost(
+                        vec3(-8.5, 0.9, -10.0),
+                        vec3(-10.0, 5.0, -10.0),
+                        true,
+                        LADDER_TAG,
+                        4.0,
+                    );
+                }
+
+                anyhow::Ok(())
+            }));
+
         events
             .fixed_mut()
             .with_system(System::builder().with_world().build(move |world: &World| {
@@ -191,7 +229,7 @@ impl Plugin for ExamplePlugin {
                     .get(end_point, world_transform())?
                     .transform_point3(Vec3::ZERO);
 
-                if let Some((_, face)) = navmesh.closest_polygon(start_pos) {
+                if let Some((_, face, _)) = navmesh.closest_polygon(start_pos) {
                     for edge in face.edges() {
                         gizmos.draw(Line::from_points(
                             edge.0,
@@ -202,19 +240,46 @@ impl Plugin for ExamplePlugin {
                     }
                 }
 
-                if let Some((_, face)) = navmesh.closest_polygon(end_pos) {
+                if let Some((_, face, _)) = navmesh.closest_polygon(end_pos) {
                     for edge in face.edges() {
                         gizmos.draw(Line::from_points(edge.0, edge.1, 0.02, Color::red()));
                     }
                 }
 
+                // How far the start could walk towards the end in a straight line
+                let ray_end = match navmesh.raycast(start_pos, end_pos) {
+                    RaycastResult::Reached { .. } => Some(end_pos),
+                    RaycastResult::Blocked { point, .. } => Some(point),
+                    RaycastResult::OffMesh => None,
+                };
+
+                if let Some(ray_end) = ray_end {
+                    gizmos.draw(Line::from_points(
+                        start_pos,
+                        ray_end,
+                        0.02,
+                        Color::new(0.8, 0.8, 0.0, 1.0),
+                    ));
+                }
+
                 let path = astar(&navmesh, start_pos, end_pos, |a, b| a.distance(b));
 
                 let color = Color::new(0.5, 0.0, 0.0, 1.0);
+                let step_up_color = Color::new(0.0, 0.0, 0.8, 1.0);
+                let step_down_color = Color::new(0.0, 0.6, 0.8, 1.0);
                 for (from, to) in path.iter().flatten().tuple_windows() {
                     gizmos.draw(Line::from_points(from.point(), to.point(), 0.04, color));
 
-                    gizmos.draw(Sphere::new(to.point(), 0.08, color));
+                    // Steps are where an agent would play a climbing or descending animation,
+                    // scaled by the height of the step
+                    let color = match to.link_kind() {
+                        Some(LinkKind::StepUp(..)) => step_up_color,
+                        Some(LinkKind::StepDown(..)) => step_down_color,
+                        _ => color,
+                    };
+
+                    let radius = 0.08 + to.step_height().unwrap_or_default().abs() * 0.2;
+                    gizmos.draw(Sphere::new(to.point(), radius, color));
                 }
 
                 anyhow::Ok(())
@@ -225,11 +290,15 @@ impl Plugin for ExamplePlugin {
 }
 
 fn brush_to_mesh(brush: &Brush) -> MeshData {
-    let vertex_count = brush.faces().len() * 3;
+    let vertex_count = brush
+        .faces()
+        .iter()
+        .map(|v| v.triangulate().count() * 3)
+        .sum::<usize>();
     let mut mesh = MeshData::new()
         .with_attribute(
             POSITION_ATTRIBUTE,
-            brush.faces().iter().flat_map(|v| v.points()),
+            brush.faces().iter().flat_map(|v| v.triangulate().flatten()),
         )
         .with_attribute(
             TEX_COORD_ATTRIBUTE,
@@ -237,10 +306,10 @@ fn brush_to_mesh(brush: &Brush) -> MeshData {
         )
         .with_attribute(
             NORMAL_ATTRIBUTE,
-            brush.faces().iter().flat_map(|v| {
-                let n = v.normal();
-                [n, n, n]
-            }),
+            brush
+                .faces()
+                .iter()
+                .flat_map(|v| std::iter::repeat_n(v.normal(), v.triangulate().count() * 3)),
         )
         .with_indices(0..vertex_count as u32);
 
@@ -1,12 +1,38 @@
 use constructive::{
-    brush::PositionedBrush,
+    brush::{CsgOp, PositionedBrush},
     navmesh::{Navmesh, NavmeshSettings},
 };
 use ivy_engine::flax::component;
 
+/// How the [`brushes`] of an entity are combined with the brushes of other entities
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BrushOp {
+    #[default]
+    Additive,
+    /// Carves the brushes out of the brushes before them, such as a doorway in a wall
+    Subtractive,
+}
+
+impl From<BrushOp> for CsgOp {
+    fn from(value: BrushOp) -> Self {
+        match value {
+            BrushOp::Additive => CsgOp::Union,
+            BrushOp::Subtractive => CsgOp::Subtract,
+        }
+    }
+}
+
 component! {
     /// A collection of brushes describing the navigable shape of an object
     pub brushes: Vec<PositionedBrush>,
+    /// Defaults to [`BrushOp::Additive`]
+    pub brush_op: BrushOp,
+    /// Brushes are combined by increasing order, and additive brushes before subtractive
+    /// brushes of the same order. Defaults to `0`.
+    pub brush_order: i32,
     pub navmesh: Navmesh,
+    /// Builds a separate navmesh from the brushes of all descendants, in the local space of
+    /// this entity
+    pub navmesh_root: (),
     pub navmesh_settings: NavmeshSettings,
 }
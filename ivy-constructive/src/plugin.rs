@@ -1,10 +1,17 @@
+use std::collections::{HashMap, HashSet};
+
 use constructive::{
-    brush::PositionedBrush,
-    link::LinkKind,
-    navmesh::{Navmesh, NavmeshSettings},
+    brush::{CsgOp, PositionedBrush},
+    debug::{LinkKindTag, NavmeshDebugInfo},
+    navmesh::{BoundaryKind, Navmesh, NavmeshSettings},
+};
+use flax::{
+    components::child_of,
+    entity::EntityKind,
+    entity_ids,
+    fetch::{EntityIds, Opt, OptOr},
 };
-use flax::{components::child_of, entity::EntityKind};
-use glam::{Mat4, Vec2};
+use glam::{Mat4, Vec2, Vec3};
 use itertools::Itertools;
 use ivy_engine::{
     engine,
@@ -33,7 +40,9 @@ use ivy_engine::{
     world_transform, TransformBundle, TransformQuery,
 };
 
-use crate::components::{self, brushes, navmesh, navmesh_settings};
+use crate::components::{
+    self, brush_op, brush_order, brushes, navmesh, navmesh_root, navmesh_settings, BrushOp,
+};
 
 pub struct NavmeshPlugin {
     settings: NavmeshSettings,
@@ -52,6 +61,7 @@ impl Plugin for NavmeshPlugin {
         _: &AssetCache,
         schedules: &mut ivy_engine::ivy_core::update_layer::ScheduleSetBuilder,
     ) -> anyhow::Result<()> {
+        self.settings.validate()?;
         world.set(engine(), navmesh_settings(), self.settings)?;
 
         schedules
@@ -73,26 +83,33 @@ impl Plugin for NavmeshDebugPlugin {
         assets: &AssetCache,
         schedules: &mut ivy_engine::ivy_core::update_layer::ScheduleSetBuilder,
     ) -> anyhow::Result<()> {
-        let mut debug_entity = None;
+        let mut debug_entities = HashMap::new();
         let assets = assets.clone();
 
         let generate_navmesh = System::builder()
             .with_cmd_mut()
             .with_world()
-            .with_query(Query::new(navmesh().modified()))
+            .with_query(Query::new((entity_ids(), navmesh().modified())))
             .build(
                 move |cmd: &mut CommandBuffer,
                       world: &World,
-                      mut query: QueryBorrow<ChangeFilter<Navmesh>>| {
-                    for navmesh in &mut query {
-                        if let Some(id) = debug_entity.take() {
+                      mut query: QueryBorrow<(EntityIds, ChangeFilter<Navmesh>)>| {
+                    for (root, navmesh) in &mut query {
+                        if let Some(id) = debug_entities.remove(&root) {
                             cmd.despawn(id);
                         }
 
                         let id = world.reserve_one(EntityKind::empty());
-                        debug_entity = Some(id);
+                        debug_entities.insert(root, id);
+
+                        // The navmesh is in the local space of its root, so follow it around
+                        let mut debug_entity = Entity::builder();
+                        debug_entity.mount(TransformBundle::default());
+                        if root != engine() {
+                            debug_entity.set_default(child_of(root));
+                        }
 
-                        cmd.append_to(id, Entity::builder().mount(TransformBundle::default()));
+                        cmd.append_to(id, debug_entity);
 
                         let flat_material = PbrMaterialData::new()
                             .with_albedo(TextureData::srgba(Srgba::new(0.5, 0.0, 0.5, 0.1)));
@@ -131,11 +148,10 @@ impl Plugin for NavmeshDebugPlugin {
                         let flat_material = PbrMaterialData::new()
                             .with_albedo(TextureData::srgba(Srgba::new(0.0, 0.5, 1.0, 1.0)));
 
-                        let id = world.reserve_one(EntityKind::empty());
-                        cmd.append_to(
-                            id,
-                            Entity::builder().mount(TransformBundle::default()).mount(
-                                RenderObjectBundle::new(
+                        cmd.spawn(
+                            Entity::builder()
+                                .mount(TransformBundle::default())
+                                .mount(RenderObjectBundle::new(
                                     MeshDesc::Content(
                                         assets.insert(walkable_navmesh_to_mesh(navmesh)),
                                     ),
@@ -143,8 +159,8 @@ impl Plugin for NavmeshDebugPlugin {
                                         forward_pass(),
                                         MaterialData::WireframeMaterial(flat_material.clone()),
                                     )],
-                                ),
-                            ),
+                                ))
+                                .set_default(child_of(id)),
                         );
                     }
                 },
@@ -159,44 +175,60 @@ impl Plugin for NavmeshDebugPlugin {
     }
 }
 
-#[system(with_query(Query::new(navmesh())))]
-fn navmesh_gizmos_system(gizmos: &Gizmos, query: &mut QueryBorrow<Component<Navmesh>>) {
+#[system(with_query(Query::new((navmesh(), world_transform().opt()))))]
+fn navmesh_gizmos_system(
+    gizmos: &Gizmos,
+    query: &mut QueryBorrow<(Component<Navmesh>, Opt<Component<Mat4>>)>,
+) {
     let mut gizmos = gizmos.begin_section("navmesh_gizmos");
-    for navmesh in query {
+    for (navmesh, transform) in query {
         const LINE_THICKNESS: f32 = 0.005;
-        for (_, link) in navmesh.links() {
-            match link.kind() {
-                LinkKind::Walk(_) => {}
-                LinkKind::StepUp(bot, top) => {
-                    let bot_line =
-                        Line::from_points(bot.p1, bot.p2, LINE_THICKNESS, Color::orange());
-
-                    let top_line =
-                        Line::from_points(top.p1, top.p2, LINE_THICKNESS, Color::orange());
-
-                    let support_1 =
-                        Line::from_points(bot.p1, top.p1, LINE_THICKNESS, Color::orange());
-                    let support_2 =
-                        Line::from_points(bot.p2, top.p2, LINE_THICKNESS, Color::orange());
-
-                    gizmos.draw(top_line);
-                    gizmos.draw(bot_line);
-                    gizmos.draw(support_1);
-                    gizmos.draw(support_2);
-                }
-            }
+        let info = NavmeshDebugInfo::capture(navmesh);
+
+        // Navmeshes without a root are in world space
+        let transform = transform.copied().unwrap_or_default();
+        let line = |a: Vec3, b: Vec3, color: Color| {
+            Line::from_points(
+                transform.transform_point3(a),
+                transform.transform_point3(b),
+                LINE_THICKNESS,
+                color,
+            )
+        };
+
+        for &(a, b, tag) in &info.links {
+            let color = match tag {
+                LinkKindTag::Walk => continue,
+                LinkKindTag::StepUp | LinkKindTag::StepDown => Color::orange(),
+                LinkKindTag::Drop => Color::new(0.0, 1.0, 1.0, 1.0),
+                LinkKindTag::OffMesh => Color::new(1.0, 0.0, 1.0, 1.0),
+            };
+
+            gizmos.draw(line(a, b, color));
+        }
+
+        for &(a, b, kind) in &info.boundary {
+            let color = match kind {
+                BoundaryKind::Wall => Color::red(),
+                BoundaryKind::Ledge => Color::new(1.0, 1.0, 0.0, 1.0),
+            };
+
+            gizmos.draw(line(a, b, color));
         }
     }
 }
 
 fn walkable_navmesh_to_mesh(navmesh: &Navmesh) -> MeshData {
     let polygons = navmesh.walkable_polygons().collect_vec();
-    let vertex_count = polygons.len() * 3;
+    let vertex_count = polygons
+        .iter()
+        .map(|(_, v)| v.triangulate().count() * 3)
+        .sum::<usize>();
 
     let mut mesh = MeshData::new()
         .with_attribute(
             POSITION_ATTRIBUTE,
-            polygons.iter().flat_map(|(_, v)| v.points()),
+            polygons.iter().flat_map(|(_, v)| v.triangulate().flatten()),
         )
         .with_attribute(
             TEX_COORD_ATTRIBUTE,
@@ -204,10 +236,9 @@ fn walkable_navmesh_to_mesh(navmesh: &Navmesh) -> MeshData {
         )
         .with_attribute(
             NORMAL_ATTRIBUTE,
-            polygons.iter().flat_map(|(_, v)| {
-                let n = v.normal();
-                [n, n, n]
-            }),
+            polygons
+                .iter()
+                .flat_map(|(_, v)| std::iter::repeat_n(v.normal(), v.triangulate().count() * 3)),
         )
         .with_indices(0..vertex_count as u32);
 
@@ -217,20 +248,25 @@ fn walkable_navmesh_to_mesh(navmesh: &Navmesh) -> MeshData {
 
 fn navmesh_to_mesh(navmesh: &Navmesh) -> MeshData {
     let polygons = navmesh.brush_polygons();
-    let vertex_count = polygons.len() * 3;
+    let vertex_count = polygons
+        .iter()
+        .map(|v| v.triangulate().count() * 3)
+        .sum::<usize>();
 
     let mut mesh = MeshData::new()
-        .with_attribute(POSITION_ATTRIBUTE, polygons.iter().flat_map(|v| v.points()))
+        .with_attribute(
+            POSITION_ATTRIBUTE,
+            polygons.iter().flat_map(|v| v.triangulate().flatten()),
+        )
         .with_attribute(
             TEX_COORD_ATTRIBUTE,
             (0..vertex_count as u32).map(|_| Vec2::ZERO),
         )
         .with_attribute(
             NORMAL_ATTRIBUTE,
-            polygons.iter().flat_map(|v| {
-                let n = v.normal();
-                [n, n, n]
-            }),
+            polygons
+                .iter()
+                .flat_map(|v| std::iter::repeat_n(v.normal(), v.triangulate().count() * 3)),
         )
         .with_indices(0..vertex_count as u32);
 
@@ -238,33 +274,137 @@ fn navmesh_to_mesh(navmesh: &Navmesh) -> MeshData {
     mesh
 }
 
+/// Returns the closest ancestor marked with [`navmesh_root`], or the engine entity if there is
+/// none, which owns the global navmesh
+fn navmesh_root_of(world: &World, id: Entity) -> Entity {
+    let mut current = id;
+    loop {
+        if world.has(current, navmesh_root()) {
+            return current;
+        }
+
+        let parent = world
+            .entity(current)
+            .ok()
+            .and_then(|v| v.relations(child_of).next().map(|(parent, _)| parent));
+
+        match parent {
+            Some(parent) => current = parent,
+            None => return engine(),
+        }
+    }
+}
+
+fn root_transform(world: &World, root: Entity) -> Mat4 {
+    world
+        .get(root, world_transform())
+        .map(|v| *v)
+        .unwrap_or_default()
+}
+
+/// Returns the entity owning the navmesh whose bounds contain the world space point, along with
+/// the transform from the space of the navmesh to world space.
+///
+/// Navmesh roots are preferred over the global navmesh. Query points need to be transformed
+/// into the space of the navmesh before searching for a path.
+pub fn navmesh_at(world: &World, point: Vec3) -> Option<(Entity, Mat4)> {
+    let mut query = Query::new((entity_ids(), navmesh(), world_transform().opt()));
+    let mut query = query.borrow(world);
+
+    query
+        .iter()
+        .map(|(id, navmesh, transform)| (id, navmesh, transform.copied().unwrap_or_default()))
+        .filter(|(_, navmesh, transform)| {
+            navmesh.bounds_contain(transform.inverse().transform_point3(point))
+        })
+        .min_by_key(|v| v.0 == engine())
+        .map(|(id, _, transform)| (id, transform))
+}
+
+/// Builds a navmesh for each [`navmesh_root`] from the brushes of its descendants, and a global
+/// navmesh on the engine entity from the remaining brushes
 fn generate_navmesh_system() -> BoxedSystem {
     System::builder()
-        .with_query(Query::new(TransformQuery::new().modified()).with(brushes()))
-        .with_query(Query::new((brushes(), world_transform())))
-        .with_query(Query::new(navmesh_settings()))
+        .with_world()
+        .with_query(Query::new((entity_ids(), TransformQuery::new().modified())).with(brushes()))
+        .with_query(Query::new((
+            entity_ids(),
+            brushes(),
+            world_transform(),
+            brush_op().opt_or_default(),
+            brush_order().opt_or_default(),
+        )))
         .with_cmd_mut()
         .build(
-            |mut changed: QueryBorrow<_, _>,
-             mut query: QueryBorrow<(Component<Vec<PositionedBrush>>, Component<Mat4>)>,
-             mut settings: QueryBorrow<Component<NavmeshSettings>>,
+            |world: &World,
+             mut changed: QueryBorrow<_, _>,
+             mut query: QueryBorrow<(
+                EntityIds,
+                Component<Vec<PositionedBrush>>,
+                Component<Mat4>,
+                OptOr<Component<BrushOp>, BrushOp>,
+                OptOr<Component<i32>, i32>,
+            )>,
              cmd: &mut CommandBuffer| {
-                if changed.iter().next().is_none() {
+                // Only rebuild the navmeshes of the roots whose brushes moved
+                let dirty: HashSet<Entity> = changed
+                    .iter()
+                    .map(|(id, _)| navmesh_root_of(world, id))
+                    .collect();
+
+                if dirty.is_empty() {
                     return;
                 }
 
-                let brushes = query.iter().flat_map(|(brushes, &transform)| {
-                    brushes.iter().map(move |v| {
-                        PositionedBrush::new(transform * v.transform(), v.brush().clone())
-                    })
-                });
+                let mut roots: HashMap<Entity, Vec<_>> = HashMap::new();
+                for (id, brushes, &transform, &op, &order) in &mut query {
+                    let root = navmesh_root_of(world, id);
+                    if !dirty.contains(&root) {
+                        continue;
+                    }
+
+                    // Brushes are built in the local space of the root, so that the navmesh
+                    // remains valid as the root moves
+                    let transform = root_transform(world, root).inverse() * transform;
+                    roots.entry(root).or_default().extend(
+                        brushes
+                            .iter()
+                            .map(|v| ((order, op), v.transformed(transform))),
+                    );
+                }
+
+                for (root, mut brushes) in roots {
+                    // Additive brushes first, so that subtractive brushes carve out of them
+                    brushes.sort_by_key(|v| v.0);
+                    let brushes = brushes.into_iter().map(|((_, op), v)| (CsgOp::from(op), v));
+
+                    let settings = world
+                        .get(root, navmesh_settings())
+                        .or_else(|_| world.get(engine(), navmesh_settings()))
+                        .map(|v| *v)
+                        .unwrap_or_default();
+
+                    // Settings set on the root are not checked by the plugin
+                    if let Err(err) = settings.validate() {
+                        tracing::error!(?root, %err, "invalid navmesh settings");
+                        continue;
+                    }
 
-                let navmesh = Navmesh::new(
-                    settings.get(engine()).ok().copied().unwrap_or_default(),
-                    brushes,
-                );
+                    // Reuse the existing navmesh to avoid reallocating on every change, unless
+                    // the settings changed since it was built
+                    if let Ok(mut navmesh) = world.get_mut(root, components::navmesh()) {
+                        if *navmesh.settings() == settings {
+                            let report = navmesh.rebuild_csg_with_report(brushes);
+                            tracing::info!(?root, %report, "rebuilt navmesh");
+                            continue;
+                        }
+                    }
 
-                cmd.set(engine(), components::navmesh(), navmesh);
+                    let (navmesh, report) = Navmesh::from_csg_with_report(settings, brushes);
+                    tracing::info!(?root, %report, "generated navmesh");
+
+                    cmd.set(root, components::navmesh(), navmesh);
+                }
             },
         )
         .boxed()
@@ -1,14 +1,25 @@
-use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, BinaryHeap};
+use std::collections::BinaryHeap;
 
-use glam::Vec3;
+use glam::{Vec3, Vec3Swizzles};
+use itertools::Itertools;
 
-use crate::{link::NavmeshLink, navmesh::Navmesh, util::TOLERANCE};
+use crate::{
+    brush::Face,
+    edge::Edge3D,
+    link::{LinkKind, NavmeshLink},
+    navmesh::{Clusters, Navmesh, PolygonId, RaycastResult},
+    util::TOLERANCE,
+};
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy)]
 pub struct Waypoint {
     target_polygon: usize,
     edge: Option<usize>,
     point: Vec3,
+    link_kind: Option<LinkKind>,
+    entry_edge: Option<Edge3D>,
+    polygon_id: Option<PolygonId>,
 }
 
 impl Waypoint {
@@ -17,138 +28,1072 @@ impl Waypoint {
             target_polygon,
             edge,
             point,
+            link_kind: None,
+            entry_edge: None,
+            polygon_id: None,
         }
     }
 
+    /// Attaches the stable id of the target polygon, so that it can be found again after the
+    /// navmesh is regenerated
+    pub fn with_polygon_id(mut self, polygon_id: Option<PolygonId>) -> Self {
+        self.polygon_id = polygon_id;
+        self
+    }
+
+    /// Resolves the link crossed to reach the waypoint, so that it remains available after the
+    /// navmesh is regenerated
+    pub fn with_link(mut self, link: &NavmeshLink) -> Self {
+        self.link_kind = Some(*link.kind());
+        self.entry_edge = Some(link.destination_edge());
+        self
+    }
+
     pub fn edge(&self) -> Option<usize> {
         self.edge
     }
 
+    /// The kind of link crossed to reach the waypoint, such as a step up
+    pub fn link_kind(&self) -> Option<LinkKind> {
+        self.link_kind
+    }
+
+    /// The signed height climbed by the link crossed to reach the waypoint, as given by
+    /// [`LinkKind::height_difference`]
+    pub fn step_height(&self) -> Option<f32> {
+        self.link_kind.map(|v| v.height_difference())
+    }
+
+    /// The portal edge crossed to enter the target polygon
+    pub fn entry_edge(&self) -> Option<Edge3D> {
+        self.entry_edge
+    }
+
     pub fn target_polygon(&self) -> usize {
         self.target_polygon
     }
 
+    /// The stable id of the target polygon, which is set for waypoints of found paths
+    pub fn polygon_id(&self) -> Option<PolygonId> {
+        self.polygon_id
+    }
+
     pub fn point(&self) -> Vec3 {
         self.point
     }
 }
 
-pub fn astar<F>(navmesh: &Navmesh, start: Vec3, end: Vec3, heuristic: F) -> Option<Vec<Waypoint>>
+/// A path found through the navmesh
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    waypoints: Vec<Waypoint>,
+    cost: f32,
+}
+
+impl Path {
+    pub fn new(waypoints: Vec<Waypoint>, cost: f32) -> Self {
+        Self { waypoints, cost }
+    }
+
+    pub fn waypoints(&self) -> &[Waypoint] {
+        &self.waypoints
+    }
+
+    pub fn into_waypoints(self) -> Vec<Waypoint> {
+        self.waypoints
+    }
+
+    /// The total traversal cost according to the cost model used to find the path
+    pub fn cost(&self) -> f32 {
+        self.cost
+    }
+
+    /// Length of the polyline through the waypoints
+    pub fn length(&self) -> f32 {
+        self.waypoints
+            .windows(2)
+            .map(|v| v[0].point.distance(v[1].point))
+            .sum()
+    }
+
+    /// Returns the polygons traversed by the path, in order
+    pub fn polygons(&self) -> impl Iterator<Item = usize> + '_ {
+        self.waypoints.iter().map(|v| v.target_polygon).dedup()
+    }
+}
+
+impl std::ops::Deref for Path {
+    type Target = [Waypoint];
+
+    fn deref(&self) -> &Self::Target {
+        &self.waypoints
+    }
+}
+
+impl<'a> IntoIterator for &'a Path {
+    type Item = &'a Waypoint;
+    type IntoIter = std::slice::Iter<'a, Waypoint>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.waypoints.iter()
+    }
+}
+
+impl From<Path> for Vec<Waypoint> {
+    fn from(path: Path) -> Self {
+        path.waypoints
+    }
+}
+
+/// Outcome of [`Path::repair`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairResult {
+    /// The remaining waypoints are still walkable, and only their polygons were resolved again
+    Unchanged,
+    /// The path was kept up to the first broken segment, and re-planned from there
+    Repaired,
+    /// The first remaining segment was broken, and the whole path was re-planned
+    Replanned,
+    /// The goal can no longer be reached, and the path was left as is
+    Impossible,
+}
+
+impl Path {
+    /// Revalidates the remaining waypoints against a regenerated navmesh, keeping the path up to
+    /// the first segment which can no longer be walked and re-planning from there to the goal.
+    ///
+    /// Waypoints already passed by `current_position` are dropped. Waypoint polygons are
+    /// resolved by their stable id where possible, and by their point otherwise.
+    ///
+    /// The cost of the kept part of the path is approximated by its length.
+    pub fn repair(&mut self, navmesh: &Navmesh, current_position: Vec3) -> RepairResult {
+        let Some(goal) = self.waypoints.last().map(|v| v.point) else {
+            return RepairResult::Impossible;
+        };
+
+        let Some((current, ..)) = navmesh.closest_polygon(current_position) else {
+            return RepairResult::Impossible;
+        };
+
+        // Continue from the segment the agent is closest to, preferring later segments when the
+        // agent stands on a waypoint
+        let passed = self
+            .waypoints
+            .windows(2)
+            .map(|v| Edge3D::new(v[0].point, v[1].point).closest_point(current_position))
+            .enumerate()
+            .min_by_key(|(index, v)| {
+                (
+                    ordered_float::OrderedFloat(v.distance(current_position)),
+                    std::cmp::Reverse(*index),
+                )
+            })
+            .map_or(0, |v| v.0);
+
+        let mut waypoints = vec![crossing(navmesh, current, None, current_position)];
+        waypoints.extend_from_slice(&self.waypoints[passed + 1..]);
+
+        let mut kept = 1;
+        while kept < waypoints.len() {
+            let (from, to) = (waypoints[kept - 1], waypoints[kept]);
+            match repair_segment(navmesh, from, to) {
+                Some(waypoint) => waypoints[kept] = waypoint,
+                None => break,
+            }
+
+            kept += 1;
+        }
+
+        if kept == waypoints.len() {
+            self.waypoints = waypoints;
+            return RepairResult::Unchanged;
+        }
+
+        let start = waypoints[kept - 1];
+        let Some(replanned) = astar(navmesh, start.point, goal, |a, b| a.distance(b)) else {
+            return RepairResult::Impossible;
+        };
+
+        waypoints.truncate(kept - 1);
+        let cost = waypoints
+            .iter()
+            .chain([&start])
+            .tuple_windows()
+            .map(|(a, b)| a.point.distance(b.point))
+            .sum::<f32>();
+
+        self.cost = cost + replanned.cost;
+        waypoints.extend(replanned.waypoints);
+        self.waypoints = waypoints;
+
+        if kept == 1 {
+            RepairResult::Replanned
+        } else {
+            RepairResult::Repaired
+        }
+    }
+}
+
+/// Resolves the waypoint `to` on the navmesh, returning it if it can still be walked to from
+/// `from`
+fn repair_segment(navmesh: &Navmesh, from: Waypoint, to: Waypoint) -> Option<Waypoint> {
+    let polygon = to
+        .polygon_id
+        .and_then(|v| navmesh.find_polygon(v))
+        .or_else(|| navmesh.closest_polygon(to.point).map(|v| v.0))
+        .filter(|&v| !navmesh.is_blocked(v))?;
+
+    let mut waypoint = to;
+    waypoint.target_polygon = polygon;
+    waypoint.polygon_id = navmesh.polygon_id(polygon);
+    waypoint.edge = None;
+
+    let link_into = |source: usize| {
+        navmesh
+            .links_between(source, polygon)
+            .find(|v| traversable_link(navmesh, source, v.0).is_some())
+    };
+
+    // Off-mesh links are traversed from point to point, rather than walked along the surface
+    if let Some(LinkKind::OffMesh { .. }) = to.link_kind {
+        let (link, _) = link_into(from.target_polygon)
+            .filter(|v| matches!(v.1.kind(), LinkKind::OffMesh { .. }))?;
+        waypoint.edge = Some(link);
+        return Some(waypoint);
+    }
+
+    // The ray may stop just short of the waypoint, such as at the edge of a drop
+    let last = match navmesh.raycast(from.point, to.point) {
+        RaycastResult::Reached { polygon } => polygon,
+        RaycastResult::Blocked { point, polygon, .. }
+            if point.xz().distance(to.point.xz()) <= navmesh.settings().epsilon =>
+        {
+            polygon
+        }
+        _ => return None,
+    };
+
+    if last != polygon {
+        waypoint.edge = Some(link_into(last)?.0);
+    } else if let Some(link) = to.link_kind.and(link_into(from.target_polygon)) {
+        waypoint.edge = Some(link.0);
+    }
+
+    Some(waypoint)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PathError {
+    #[error("start point is not on the navmesh")]
+    StartNotFound,
+    #[error("end point is not on the navmesh")]
+    EndNotFound,
+    #[error("no path exists between start and end")]
+    Unreachable,
+    #[error("search exceeded the budget of {0} expansions")]
+    BudgetExceeded(usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AstarOptions {
+    /// Maximum number of polygons to expand before giving up
+    pub max_expansions: Option<usize>,
+    pub smoothing: PathSmoothing,
+    /// Clearance kept from the ends of each crossed portal.
+    ///
+    /// Defaults to the agent radius of the navmesh settings.
+    pub agent_radius: Option<f32>,
+    /// Links narrower than this are not traversed, for agents wider than the navmesh was
+    /// generated for
+    pub agent_width: Option<f32>,
+    /// Maximum number of passes of [`PathSmoothing::Shorten`]. Defaults to `100`.
+    pub shorten_iterations: Option<usize>,
+    /// Multiplier applied to the cost of entering polygons, indexed by their
+    /// [`BrushFlags::area_id`](crate::brush::BrushFlags::area_id).
+    ///
+    /// Areas without an entry cost `1`, and areas with an infinite cost are never entered.
+    /// Multipliers below `1` may cause a more expensive path to be found.
+    pub area_costs: Vec<f32>,
+}
+
+impl AstarOptions {
+    /// Returns the cost multiplier of entering the polygon
+    fn area_cost(&self, navmesh: &Navmesh, polygon: usize) -> f32 {
+        navmesh
+            .polygon_area_id(polygon)
+            .and_then(|v| self.area_costs.get(v as usize))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+/// How the waypoints of a found path are straightened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathSmoothing {
+    /// Pull a string through the traversed portals, keeping waypoints only at corners and where
+    /// the surface changes
+    #[default]
+    Funnel,
+    /// Iteratively move the crossing point of each portal onto the line between its neighbors
+    Shorten,
+    /// Keep a waypoint where the search crossed each portal
+    None,
+}
+
+/// Determines the cost of traversing the navmesh
+pub trait PathCostModel {
+    /// Cost of moving from `from` to `to` by traversing `link`
+    fn cost(&self, navmesh: &Navmesh, link: &NavmeshLink, from: Vec3, to: Vec3) -> f32;
+
+    /// Estimated cost from `from` to `to`.
+    ///
+    /// Must not overestimate the true cost for the found path to be the cheapest.
+    fn heuristic(&self, from: Vec3, to: Vec3) -> f32;
+}
+
+/// Scales the distance by the link cost and the slope penalty of the settings, and adds the
+/// step cost for step links
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultCostModel<F> {
+    heuristic: F,
+}
+
+impl<F> DefaultCostModel<F>
 where
     F: Fn(Vec3, Vec3) -> f32,
 {
-    let mut open = BinaryHeap::new();
-    let (start_node, _) = navmesh.closest_polygon(start)?;
-    let (end_node, _) = navmesh.closest_polygon(end)?;
+    pub fn new(heuristic: F) -> Self {
+        Self { heuristic }
+    }
+}
+
+impl<F> PathCostModel for DefaultCostModel<F>
+where
+    F: Fn(Vec3, Vec3) -> f32,
+{
+    fn cost(&self, navmesh: &Navmesh, link: &NavmeshLink, from: Vec3, to: Vec3) -> f32 {
+        let settings = navmesh.settings();
+        let length = from.distance(to);
 
-    // Information of how a node was reached
-    let mut backtraces: BTreeMap<_, Backtrace> = BTreeMap::new();
-    let start = Backtrace::start(start_node, start, (heuristic)(start, end));
+        let slope = if length > TOLERANCE {
+            (to.y - from.y).abs() / length
+        } else {
+            0.0
+        };
+
+        let step_cost = match link.kind() {
+            LinkKind::Walk(_) => 0.0,
+            LinkKind::StepUp(..) | LinkKind::StepDown(..) | LinkKind::Drop(..) => {
+                settings.step_cost
+            }
+            // Walk to the entry point, and then traverse the connection in a straight line
+            &LinkKind::OffMesh {
+                from: entry,
+                to: exit,
+                ..
+            } => return (from.distance(entry) + entry.distance(exit)) * link.cost(),
+        };
+
+        length * (1.0 + settings.slope_penalty * slope) * link.cost() + step_cost
+    }
+
+    fn heuristic(&self, from: Vec3, to: Vec3) -> f32 {
+        (self.heuristic)(from, to)
+    }
+}
+
+/// Computes the cost of each link from the link and the distance travelled with a closure
+#[derive(Debug, Clone, Copy)]
+pub struct FnCostModel<H, C> {
+    heuristic: H,
+    cost: C,
+}
+
+impl<H, C> FnCostModel<H, C>
+where
+    H: Fn(Vec3, Vec3) -> f32,
+    C: Fn(&NavmeshLink, f32) -> f32,
+{
+    pub fn new(heuristic: H, cost: C) -> Self {
+        Self { heuristic, cost }
+    }
+}
+
+impl<H, C> PathCostModel for FnCostModel<H, C>
+where
+    H: Fn(Vec3, Vec3) -> f32,
+    C: Fn(&NavmeshLink, f32) -> f32,
+{
+    fn cost(&self, _: &Navmesh, link: &NavmeshLink, from: Vec3, to: Vec3) -> f32 {
+        (self.cost)(link, from.distance(to))
+    }
+
+    fn heuristic(&self, from: Vec3, to: Vec3) -> f32 {
+        (self.heuristic)(from, to)
+    }
+}
 
-    // Push the fist node
+pub fn astar<F>(navmesh: &Navmesh, start: Vec3, end: Vec3, heuristic: F) -> Option<Path>
+where
+    F: Fn(Vec3, Vec3) -> f32,
+{
+    astar_with_options(navmesh, start, end, heuristic, AstarOptions::default()).ok()
+}
+
+pub fn astar_with_options<F>(
+    navmesh: &Navmesh,
+    start: Vec3,
+    end: Vec3,
+    heuristic: F,
+    options: AstarOptions,
+) -> Result<Path, PathError>
+where
+    F: Fn(Vec3, Vec3) -> f32,
+{
+    astar_with_model(
+        navmesh,
+        start,
+        end,
+        &DefaultCostModel::new(heuristic),
+        options,
+    )
+}
+
+/// Finds the cheapest path where crossing a link costs `cost(link, distance)`.
+///
+/// The cost should be at least the distance for the heuristic to stay admissible.
+pub fn astar_with_costs<H, C>(
+    navmesh: &Navmesh,
+    start: Vec3,
+    end: Vec3,
+    heuristic: H,
+    cost: C,
+) -> Option<Path>
+where
+    H: Fn(Vec3, Vec3) -> f32,
+    C: Fn(&NavmeshLink, f32) -> f32,
+{
+    astar_with_model(
+        navmesh,
+        start,
+        end,
+        &FnCostModel::new(heuristic, cost),
+        AstarOptions::default(),
+    )
+    .ok()
+}
+
+/// Finds the cheapest path according to the cost model
+pub fn astar_with_model(
+    navmesh: &Navmesh,
+    start: Vec3,
+    end: Vec3,
+    model: &impl PathCostModel,
+    options: AstarOptions,
+) -> Result<Path, PathError> {
+    let mut context = PathfindingContext::new();
+    context.search(navmesh, start, end, model, &options, None)?;
+    Ok(Path::new(context.path, context.cost))
+}
+
+/// Finds a path by planning over the clusters of the navmesh before the polygons.
+///
+/// See [`PathfindingContext::find_path_hierarchical_with_model`].
+pub fn astar_hierarchical<F>(
+    navmesh: &Navmesh,
+    start: Vec3,
+    end: Vec3,
+    heuristic: F,
+) -> Option<Path>
+where
+    F: Fn(Vec3, Vec3) -> f32,
+{
+    let mut context = PathfindingContext::new();
+    context
+        .find_path_hierarchical_with_model(
+            navmesh,
+            start,
+            end,
+            &DefaultCostModel::new(heuristic),
+            &AstarOptions::default(),
+        )
+        .ok()?;
+
+    Some(Path::new(context.path, context.cost))
+}
+
+/// Finds the cheapest path from `start` to each of the targets with a single search.
+///
+/// Targets which are off the navmesh or unreachable yield `None`.
+pub fn dijkstra_multi(navmesh: &Navmesh, start: Vec3, targets: &[Vec3]) -> Vec<Option<Path>> {
+    let model = DefaultCostModel::new(|_, _| 0.0);
+    let radius = navmesh.settings().agent_radius;
+
+    let Some((start_node, ..)) = navmesh.closest_polygon(start) else {
+        return vec![None; targets.len()];
+    };
+
+    let target_nodes = targets
+        .iter()
+        .map(|&v| navmesh.closest_polygon(v).map(|v| v.0))
+        .collect_vec();
+
+    let polygon_count = navmesh.polygons().capacity();
+    let mut open = BinaryHeap::new();
+    let mut backtraces: Vec<Option<Backtrace>> = vec![None; polygon_count];
+    let mut closed = vec![false; polygon_count];
+    let mut buffers = FunnelBuffers::default();
+
+    let start = Backtrace::start(start_node, start, 0.0);
     open.push(start);
-    backtraces.insert(start_node, start);
+    backtraces[start_node] = Some(start);
 
-    let mut closed = BTreeSet::new();
-    let mut path = Vec::new();
+    let mut remaining = target_nodes.iter().flatten().unique().count();
 
-    // Expand the node with the lowest total cost
     while let Some(current) = open.pop() {
-        if closed.contains(&current.node) {
+        if closed[current.node] {
             continue;
         }
 
-        // End found
-        // Generate backtrace and terminate
-        if current.node == end_node {
-            contruct_backtrace(end, current.node, backtraces, &mut path);
-            shorten(navmesh, &mut path);
-            // resolve_clip(portals, path, info.agent_radius);
+        closed[current.node] = true;
+
+        if target_nodes.contains(&Some(current.node)) {
+            remaining -= 1;
+            if remaining == 0 {
+                break;
+            }
+        }
+
+        for &portal in navmesh.polygon_links(current.node) {
+            let Some(link) = traversable_link(navmesh, current.node, portal) else {
+                continue;
+            };
+
+            if link.to() == current.node || closed[link.to()] || navmesh.is_blocked(link.to()) {
+                continue;
+            }
+
+            // Without a single destination to aim for, cross at the nearest point of the edge
+            let p = clip_portal(link.destination_edge(), radius).closest_point(current.point);
+
+            let backtrace = Backtrace::new(
+                portal,
+                link,
+                p,
+                &current,
+                model.cost(navmesh, link, current.point, p),
+                0.0,
+            );
+
+            let entry = &mut backtraces[backtrace.node];
+            match entry {
+                Some(val) if val.total_cost <= backtrace.total_cost => continue,
+                _ => *entry = Some(backtrace),
+            }
+
+            open.push(backtrace);
+        }
+    }
+
+    targets
+        .iter()
+        .zip(target_nodes)
+        .map(|(&target, node)| {
+            let node = node?;
+            if !closed[node] {
+                return None;
+            }
+
+            let end = backtraces[node]?;
+
+            let mut path = Vec::new();
+            if node == start_node {
+                path.push(crossing(navmesh, start_node, None, start.point));
+                path.push(crossing(navmesh, node, None, target));
+            } else {
+                funnel(
+                    navmesh,
+                    start.point,
+                    target,
+                    node,
+                    &backtraces,
+                    radius,
+                    &mut buffers,
+                    &mut path,
+                );
+            }
+
+            Some(Path::new(path, end.start_cost + end.point.distance(target)))
+        })
+        .collect()
+}
+
+/// Reusable buffers for pathfinding.
+///
+/// Reusing a context between queries avoids allocating new search buffers for every path, and
+/// only the polygons visited by the previous search are cleared, so short paths on a large
+/// navmesh stay cheap. The navmesh itself is only borrowed, so each thread can keep its own
+/// context and query a shared navmesh.
+#[derive(Debug, Default, Clone)]
+pub struct PathfindingContext {
+    open: BinaryHeap<Backtrace>,
+    /// Information of how each polygon was reached, indexed by polygon
+    backtraces: Vec<Option<Backtrace>>,
+    closed: Vec<bool>,
+    /// Polygons with a backtrace, so that only those are cleared for the next search
+    visited: Vec<usize>,
+    funnel: FunnelBuffers,
+    path: Vec<Waypoint>,
+    /// Cost of the last found path
+    cost: f32,
+    /// Number of nodes expanded by the last search
+    expansions: usize,
+}
+
+impl PathfindingContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn find_path<F>(
+        &mut self,
+        navmesh: &Navmesh,
+        start: Vec3,
+        end: Vec3,
+        heuristic: F,
+    ) -> Option<&[Waypoint]>
+    where
+        F: Fn(Vec3, Vec3) -> f32,
+    {
+        self.find_path_with_options(navmesh, start, end, heuristic, &AstarOptions::default())
+            .ok()
+    }
+
+    pub fn find_path_with_options<F>(
+        &mut self,
+        navmesh: &Navmesh,
+        start: Vec3,
+        end: Vec3,
+        heuristic: F,
+        options: &AstarOptions,
+    ) -> Result<&[Waypoint], PathError>
+    where
+        F: Fn(Vec3, Vec3) -> f32,
+    {
+        self.find_path_with_model(
+            navmesh,
+            start,
+            end,
+            &DefaultCostModel::new(heuristic),
+            options,
+        )
+    }
+
+    pub fn find_path_with_model(
+        &mut self,
+        navmesh: &Navmesh,
+        start: Vec3,
+        end: Vec3,
+        model: &impl PathCostModel,
+        options: &AstarOptions,
+    ) -> Result<&[Waypoint], PathError> {
+        self.search(navmesh, start, end, model, options, None)?;
+        Ok(&self.path)
+    }
+
+    /// Plans the path over the clusters of the navmesh first, and then only searches the
+    /// polygons of the clusters along the way and their neighbors.
+    ///
+    /// The polygon search is guided towards the next cluster of the route rather than straight
+    /// towards the end, so obstacles are not rediscovered polygon by polygon. This expands far
+    /// fewer polygons for long paths, at the cost of the path possibly being slightly longer.
+    ///
+    /// Falls back to searching all polygons if the navmesh has no clusters, or no path is found
+    /// inside the clusters. See [`Navmesh::build_clusters`].
+    pub fn find_path_hierarchical_with_model(
+        &mut self,
+        navmesh: &Navmesh,
+        start: Vec3,
+        end: Vec3,
+        model: &impl PathCostModel,
+        options: &AstarOptions,
+    ) -> Result<&[Waypoint], PathError> {
+        let Some(clusters) = navmesh.clusters() else {
+            return self.find_path_with_model(navmesh, start, end, model, options);
+        };
+
+        let cluster = |point| -> Option<usize> {
+            navmesh
+                .closest_polygon(point)
+                .and_then(|(polygon, ..)| clusters.cluster(polygon))
+        };
+
+        let from = cluster(start).ok_or(PathError::StartNotFound)?;
+        let to = cluster(end).ok_or(PathError::EndNotFound)?;
+
+        let (route, cluster_expansions) = clusters.route(from, to, start, end);
+        let route = route.ok_or(PathError::Unreachable)?;
+        let corridor = Corridor::new(navmesh, clusters, &route, end);
+
+        let result = match self.search(navmesh, start, end, model, options, Some(&corridor)) {
+            Err(PathError::Unreachable) => {
+                let expansions = self.expansions;
+                let result = self.search(navmesh, start, end, model, options, None);
+                self.expansions += expansions;
+                result
+            }
+            result => result,
+        };
+
+        self.expansions += cluster_expansions;
+        result?;
+        Ok(&self.path)
+    }
+
+    /// Returns the number of polygons expanded by the last search, including the clusters of
+    /// hierarchical searches
+    pub fn expansions(&self) -> usize {
+        self.expansions
+    }
+
+    /// Clears the buffers for a new search, touching only the polygons visited by the last one
+    fn reset(&mut self, polygon_count: usize) {
+        self.open.clear();
+        for node in self.visited.drain(..) {
+            self.backtraces[node] = None;
+            self.closed[node] = false;
+        }
+
+        if self.backtraces.len() < polygon_count {
+            self.backtraces.resize(polygon_count, None);
+            self.closed.resize(polygon_count, false);
+        }
+
+        self.path.clear();
+        self.cost = 0.0;
+        self.expansions = 0;
+    }
+
+    fn search(
+        &mut self,
+        navmesh: &Navmesh,
+        start: Vec3,
+        end: Vec3,
+        model: &impl PathCostModel,
+        options: &AstarOptions,
+        corridor: Option<&Corridor>,
+    ) -> Result<(), PathError> {
+        let (start_node, start_face, _) = navmesh
+            .closest_polygon(start)
+            .ok_or(PathError::StartNotFound)?;
+        let (end_node, end_face, _) = navmesh.closest_polygon(end).ok_or(PathError::EndNotFound)?;
+
+        // Points off the navmesh are moved onto the nearest polygon
+        let clamp = |face: &Face, point: Vec3| {
+            if face.contains_point(point) {
+                point
+            } else {
+                face.closest_point(point)
+            }
+        };
+
+        let start = clamp(&start_face, start);
+        let end = clamp(&end_face, end);
+        let radius = options
+            .agent_radius
+            .unwrap_or(navmesh.settings().agent_radius);
+
+        self.reset(navmesh.polygons().capacity());
+
+        // No link leads out of a region, so avoid flooding the whole region of the start
+        if navmesh.region(start_node) != navmesh.region(end_node) {
+            return Err(PathError::Unreachable);
+        }
 
-            return Some(path);
+        if start_node == end_node {
+            self.path.push(crossing(navmesh, start_node, None, start));
+            self.path.push(crossing(navmesh, end_node, None, end));
+            self.cost = start.distance(end);
+            return Ok(());
         }
 
-        // Add all edges to the open list and update backtraces
-        let portals = navmesh
-            .polygon_links()
-            .get(&current.node)
-            .into_iter()
-            .flatten()
-            .filter_map(|&portal| {
-                let link = &navmesh.links()[portal];
-                if link.to() == current.node || closed.contains(&link.to()) {
-                    return None;
+        let Self {
+            open,
+            backtraces,
+            closed,
+            visited,
+            funnel: buffers,
+            path,
+            cost,
+            expansions,
+        } = self;
+
+        let start = Backtrace::start(start_node, start, model.heuristic(start, end));
+
+        // Push the fist node
+        open.push(start);
+        backtraces[start_node] = Some(start);
+        visited.push(start_node);
+
+        // Expand the node with the lowest total cost
+        while let Some(current) = open.pop() {
+            if closed[current.node] {
+                continue;
+            }
+
+            *expansions += 1;
+            if let Some(max_expansions) = options.max_expansions {
+                if *expansions > max_expansions {
+                    return Err(PathError::BudgetExceeded(max_expansions));
                 }
+            }
 
-                assert_eq!(link.from(), current.node);
-
-                // Distance to each of the nodes
-                let (p1, p2) = (link.destination_edge().p1, link.destination_edge().p2);
-                let p1_dist = (heuristic)(p1, end);
-                let p2_dist = (heuristic)(p2, end);
-
-                let p = if let Some(p) = link
-                    .destination_edge()
-                    .intersect_ray_clipped(current.point, end - current.point)
-                {
-                    p
-                } else if p1_dist < p2_dist {
-                    p1
-                } else {
-                    p2
-                };
-
-                // let p = midpoint;
-                let backtrace = Backtrace::new(portal, link, p, &current, (heuristic)(p, end));
-
-                // Update backtrace
-                // If the cost to this node is lower than previosuly found,
-                // overwrite with the new backtrace.
-                match backtraces.entry(backtrace.node) {
-                    Entry::Occupied(mut val) => {
-                        if val.get().total_cost > backtrace.total_cost {
-                            val.insert(backtrace);
-                        } else {
-                            return None;
-                        }
+            // End found
+            // Generate backtrace and terminate
+            if current.node == end_node {
+                *cost = current.start_cost + current.point.distance(end);
+                match options.smoothing {
+                    PathSmoothing::Funnel => funnel(
+                        navmesh,
+                        start.point,
+                        end,
+                        current.node,
+                        backtraces,
+                        radius,
+                        buffers,
+                        path,
+                    ),
+                    PathSmoothing::Shorten => {
+                        contruct_backtrace(navmesh, end, current.node, backtraces, path);
+                        let iterations = options.shorten_iterations.unwrap_or(100);
+                        shorten(navmesh, radius, iterations, path);
                     }
-                    Entry::Vacant(entry) => {
-                        entry.insert(backtrace);
+                    PathSmoothing::None => {
+                        contruct_backtrace(navmesh, end, current.node, backtraces, path)
                     }
                 }
 
-                Some(backtrace)
-            });
+                return Ok(());
+            }
+
+            // Add all edges to the open list and update backtraces
+            let portals = navmesh
+                .polygon_links(current.node)
+                .iter()
+                .filter_map(|&portal| {
+                    let link = traversable_link(navmesh, current.node, portal)?;
+                    if link.to() == current.node
+                        || closed[link.to()]
+                        || navmesh.is_blocked(link.to())
+                        || corridor.is_some_and(|v| v.position(link.to()).is_none())
+                        || options.agent_width.is_some_and(|v| link.width() < v)
+                    {
+                        return None;
+                    }
 
-        // Add the edges
-        open.extend(portals);
+                    let area_cost = options.area_cost(navmesh, link.to());
+                    if area_cost == f32::INFINITY {
+                        return None;
+                    }
+
+                    let edge = clip_portal(link.destination_edge(), radius);
+
+                    // Distance to each of the nodes
+                    let (p1, p2) = (edge.p1, edge.p2);
+                    let p1_dist = model.heuristic(p1, end);
+                    let p2_dist = model.heuristic(p2, end);
+
+                    let p = if let Some(p) =
+                        edge.intersect_ray_clipped(current.point, end - current.point)
+                    {
+                        p
+                    } else if p1_dist < p2_dist {
+                        p1
+                    } else {
+                        p2
+                    };
+
+                    // let p = midpoint;
+                    let backtrace = Backtrace::new(
+                        portal,
+                        link,
+                        p,
+                        &current,
+                        model.cost(navmesh, link, current.point, p) * area_cost,
+                        match corridor {
+                            Some(corridor) => corridor.heuristic(model, link.to(), p),
+                            None => model.heuristic(p, end),
+                        },
+                    );
+
+                    // Update backtrace
+                    // If the cost to this node is lower than previosuly found,
+                    // overwrite with the new backtrace.
+                    let entry = &mut backtraces[backtrace.node];
+                    match entry {
+                        Some(val) if val.total_cost <= backtrace.total_cost => return None,
+                        Some(_) => *entry = Some(backtrace),
+                        None => {
+                            *entry = Some(backtrace);
+                            visited.push(backtrace.node);
+                        }
+                    }
 
-        // The current node is now done and won't be revisited
-        assert!(closed.insert(current.node))
+                    Some(backtrace)
+                });
+
+            // Add the edges
+            open.extend(portals);
+
+            // The current node is now done and won't be revisited
+            closed[current.node] = true;
+        }
+
+        Err(PathError::Unreachable)
     }
+}
 
-    None
+/// The clusters along a route planned over the clusters, which restricts and guides the
+/// polygon search
+struct Corridor<'a> {
+    clusters: &'a Clusters,
+    /// Position of each cluster along the route, indexed by cluster
+    order: Vec<Option<usize>>,
+    /// The point to head for in each cluster of the route, which is the middle of the links
+    /// into the next cluster or the end
+    targets: Vec<Vec3>,
+    /// Distance along the route from each target to the end
+    remaining: Vec<f32>,
+}
+
+impl<'a> Corridor<'a> {
+    fn new(navmesh: &Navmesh, clusters: &'a Clusters, route: &[usize], end: Vec3) -> Self {
+        // Head for the middle of the links into the next cluster
+        let portal = |(from, to): (usize, usize)| {
+            let links = clusters
+                .neighbors(from)
+                .find(|v| v.0 == to)
+                .map(|v| v.1)
+                .unwrap_or_default();
+
+            links
+                .iter()
+                .map(|&v| navmesh.links()[v].destination_edge().midpoint())
+                .sum::<Vec3>()
+                / links.len().max(1) as f32
+        };
+
+        let targets = route
+            .iter()
+            .copied()
+            .tuple_windows()
+            .map(portal)
+            .chain([end])
+            .collect_vec();
+
+        let mut remaining = vec![0.0; targets.len()];
+        for i in (0..targets.len().saturating_sub(1)).rev() {
+            remaining[i] = remaining[i + 1] + targets[i].distance(targets[i + 1]);
+        }
+
+        // Neighboring clusters are also searched, so that the path is not confined to the
+        // exact route, and head for the same target as the furthest adjacent route cluster
+        let mut order = vec![None; clusters.cluster_count()];
+        for (i, &cluster) in route.iter().enumerate() {
+            order[cluster] = Some(i);
+        }
+
+        for (i, &cluster) in route.iter().enumerate().rev() {
+            for (neighbor, _) in clusters.neighbors(cluster) {
+                order[neighbor].get_or_insert(i);
+            }
+        }
+
+        Self {
+            clusters,
+            order,
+            targets,
+            remaining,
+        }
+    }
+
+    /// Returns the position along the route of the cluster of `polygon`, if it is part of the
+    /// corridor
+    fn position(&self, polygon: usize) -> Option<usize> {
+        self.order[self.clusters.cluster(polygon)?]
+    }
+
+    /// Estimates the cost to the end by following the route through the remaining clusters
+    fn heuristic(&self, model: &impl PathCostModel, polygon: usize, point: Vec3) -> f32 {
+        let index = self
+            .position(polygon)
+            .expect("polygon to be within the corridor");
+        model.heuristic(point, self.targets[index]) + self.remaining[index]
+    }
+}
+
+/// Returns the link if it leaves `node`, leads to an existing polygon and is not blocked by an
+/// obstacle.
+///
+/// Links of a navmesh which was modified inconsistently are skipped rather than panicking in
+/// the middle of a query.
+fn traversable_link(navmesh: &Navmesh, node: usize, link: usize) -> Option<&NavmeshLink> {
+    let value = navmesh.links().get(link);
+    match value {
+        // Links crossing an obstacle are valid, and only skipped until the obstacle is removed
+        Some(v) if v.from() == node && navmesh.polygons().contains(v.to()) => {
+            value.filter(|_| !navmesh.is_link_disabled(link))
+        }
+        _ => {
+            tracing::debug!(node, link, ?value, "skipping invalid link");
+            None
+        }
+    }
+}
+
+/// Creates a waypoint on `polygon`, resolving the stable id of the polygon and the link crossed
+/// to reach it
+fn crossing(navmesh: &Navmesh, polygon: usize, link: Option<usize>, point: Vec3) -> Waypoint {
+    let waypoint = Waypoint::new(polygon, link, point).with_polygon_id(navmesh.polygon_id(polygon));
+    match link {
+        Some(link) => waypoint.with_link(&navmesh.links()[link]),
+        None => waypoint,
+    }
 }
 
 fn contruct_backtrace(
+    navmesh: &Navmesh,
     end: Vec3,
     mut current: usize,
-    backtraces: BTreeMap<usize, Backtrace>,
+    backtraces: &[Option<Backtrace>],
     path: &mut Vec<Waypoint>,
 ) {
     path.clear();
-    path.push(Waypoint::new(current, None, end));
+    path.push(crossing(navmesh, current, None, end));
     let mut prev = end;
     loop {
         // Backtrace backwards
-        let node = backtraces[&current];
+        let node = backtraces[current].expect("backtrace for visited node");
 
         if path.len() < 2 || prev.distance_squared(node.point) > TOLERANCE {
-            path.push(Waypoint::new(node.node, node.portal, node.point));
+            path.push(crossing(navmesh, node.node, node.portal, node.point));
         }
 
         prev = node.point;
 
+        // The agent must walk to the entry of an off-mesh link before traversing it
+        if let Some(&LinkKind::OffMesh { from, .. }) =
+            node.portal.map(|v| navmesh.links()[v].kind())
+        {
+            path.push(crossing(
+                navmesh,
+                node.prev.expect("link to have a source"),
+                None,
+                from,
+            ));
+            prev = from;
+        }
+
         // Continue up the backtrace
         if let Some(prev) = node.prev {
             current = prev;
@@ -160,6 +1105,222 @@ fn contruct_backtrace(
     path.reverse();
 }
 
+/// Scratch buffers of [`funnel`], kept between queries
+#[derive(Debug, Default, Clone)]
+struct FunnelBuffers {
+    portals: Vec<Portal>,
+    /// Indices of the portals at which the string turns, and the corner point
+    corners: Vec<(usize, Vec3)>,
+}
+
+/// A link crossed by the path, with its endpoints ordered as seen when walking through it
+#[derive(Debug, Clone, Copy)]
+struct Portal {
+    left: Vec3,
+    right: Vec3,
+    polygon: usize,
+    link: Option<usize>,
+    /// The string must pass through the portal, such as at the ends of an off-mesh link
+    fixed: bool,
+}
+
+/// Straightens the path through the portals of the backtrace using the simple stupid funnel
+/// algorithm, operating on the horizontal plane.
+///
+/// Waypoints are emitted at the corners of the string, and where it crosses onto a polygon of a
+/// different slope or height so that the path follows the surface.
+#[allow(clippy::too_many_arguments)]
+fn funnel(
+    navmesh: &Navmesh,
+    start: Vec3,
+    end: Vec3,
+    end_node: usize,
+    backtraces: &[Option<Backtrace>],
+    radius: f32,
+    buffers: &mut FunnelBuffers,
+    path: &mut Vec<Waypoint>,
+) {
+    let FunnelBuffers { portals, corners } = buffers;
+
+    portals.clear();
+    portals.push(Portal {
+        left: end,
+        right: end,
+        polygon: end_node,
+        link: None,
+        fixed: false,
+    });
+
+    let mut current = end_node;
+    loop {
+        let node = backtraces[current].expect("backtrace for visited node");
+
+        let Some(link) = node.portal else {
+            portals.push(Portal {
+                left: start,
+                right: start,
+                polygon: node.node,
+                link: None,
+                fixed: false,
+            });
+            break;
+        };
+
+        let prev = node.prev.expect("backtrace to have a predecessor");
+
+        // Off-mesh links are walked to and traversed from point to point
+        if let &LinkKind::OffMesh { from, to, .. } = navmesh.links()[link].kind() {
+            portals.push(Portal {
+                left: to,
+                right: to,
+                polygon: node.node,
+                link: Some(link),
+                fixed: true,
+            });
+
+            portals.push(Portal {
+                left: from,
+                right: from,
+                polygon: prev,
+                link: None,
+                fixed: true,
+            });
+
+            current = prev;
+            continue;
+        }
+
+        // The portal is entered towards the center of the polygon
+        let edge = clip_portal(navmesh.links()[link].destination_edge(), radius);
+        let face = &navmesh.polygons()[node.node];
+        let center = face.points().iter().sum::<Vec3>() / face.points().len() as f32;
+        let mid = (edge.p1 + edge.p2) * 0.5;
+
+        let (left, right) = if (center - mid).xz().perp_dot((edge.p1 - mid).xz()) > 0.0 {
+            (edge.p1, edge.p2)
+        } else {
+            (edge.p2, edge.p1)
+        };
+
+        portals.push(Portal {
+            left,
+            right,
+            polygon: node.node,
+            link: Some(link),
+            fixed: false,
+        });
+
+        current = prev;
+    }
+
+    portals.reverse();
+
+    // Twice the signed area of the triangle, positive when `c` is left of `a -> b`
+    let area = |a: Vec3, b: Vec3, c: Vec3| (b - a).xz().perp_dot((c - a).xz());
+    let same = |a: Vec3, b: Vec3| a.xz().distance_squared(b.xz()) < TOLERANCE * TOLERANCE;
+
+    corners.clear();
+    corners.push((0, start));
+
+    let mut apex = start;
+    let (mut left, mut right) = (start, start);
+    let (mut left_index, mut right_index) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let portal = &portals[i];
+
+        // Tighten the right side
+        if area(apex, right, portal.right) >= 0.0 {
+            if same(apex, right) || area(apex, left, portal.right) < 0.0 {
+                right = portal.right;
+                right_index = i;
+            } else {
+                // The right side crossed over the left, which becomes a corner
+                corners.push((left_index, left));
+                apex = left;
+                right = apex;
+                right_index = left_index;
+                i = left_index + 1;
+                continue;
+            }
+        }
+
+        // Tighten the left side
+        if area(apex, left, portal.left) <= 0.0 {
+            if same(apex, left) || area(apex, right, portal.left) > 0.0 {
+                left = portal.left;
+                left_index = i;
+            } else {
+                corners.push((right_index, right));
+                apex = right;
+                left = apex;
+                left_index = right_index;
+                i = right_index + 1;
+                continue;
+            }
+        }
+
+        // Both sides were tightened onto the fixed point, which becomes the new apex
+        if portal.fixed {
+            corners.push((i, portal.left));
+            apex = portal.left;
+            (left, right) = (apex, apex);
+            (left_index, right_index) = (i, i);
+        }
+
+        i += 1;
+    }
+
+    if corners.last().is_some_and(|v| v.0 != portals.len() - 1) {
+        corners.push((portals.len() - 1, end));
+    }
+
+    // Moving onto a polygon of another plane changes the height profile of the segment
+    let surface_changes = |portal: &Portal| {
+        let Some(link) = portal.link else {
+            return false;
+        };
+
+        let link = &navmesh.links()[link];
+        let from = &navmesh.polygons()[link.from()];
+        let to = &navmesh.polygons()[link.to()];
+
+        !matches!(link.kind(), LinkKind::Walk(_))
+            || from.normal().dot(to.normal()) < 1.0 - TOLERANCE
+            || from.distance_to_plane(to.points()[0]).abs() > TOLERANCE
+    };
+
+    path.clear();
+    path.push(crossing(navmesh, portals[0].polygon, None, start));
+
+    for (&(from_index, from), &(to_index, to)) in corners.iter().tuple_windows() {
+        for portal in &portals[from_index + 1..to_index] {
+            if !surface_changes(portal) {
+                continue;
+            }
+
+            let link = portal.link.expect("portal to have a link");
+            let edge = clip_portal(navmesh.links()[link].destination_edge(), radius);
+            let point = edge
+                .intersect_ray_clipped(from, to - from)
+                .unwrap_or_else(|| edge.closest_point(from));
+
+            path.push(crossing(navmesh, portal.polygon, Some(link), point));
+        }
+
+        let portal = &portals[to_index];
+        if path
+            .last()
+            .is_some_and(|v| v.point.distance_squared(to) < TOLERANCE)
+        {
+            continue;
+        }
+
+        path.push(crossing(navmesh, portal.polygon, portal.link, to));
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct Backtrace {
     // Index to the portal
@@ -189,9 +1350,10 @@ impl Backtrace {
         edge: &NavmeshLink,
         point: Vec3,
         prev: &Backtrace,
+        cost: f32,
         heuristic: f32,
     ) -> Self {
-        let start_cost = prev.start_cost + point.distance(prev.point);
+        let start_cost = prev.start_cost + cost;
         Self {
             node: edge.to(),
             portal: Some(edge_index),
@@ -221,43 +1383,21 @@ impl Ord for Backtrace {
     }
 }
 
-// fn resolve_clip(navmesh: &Navmesh, path: &mut [Waypoint], margin: f32) {
-//     if path.len() < 3 {
-//         return;
-//     }
-
-//     let a = &path[0];
-//     let c = &path[2];
-//     let b = &mut path[1];
-
-//     if let Some(portal) = b.edge {
-//         let link = &navmesh.links()[portal];
-//         let edge = link.destination_edge();
-//         let p = edge.p1;
-//         let q = edge.p2;
-
-//         if (b.point.distance(p) < margin + TOLERANCE) || (b.point.distance(q) < margin + TOLERANCE)
-//         {
-//             // let normal = portal.normal();
-//             // let a_inc = (a.point - b.point)
-//             //     .normalize_or_zero()
-//             //     .perp_dot(normal)
-//             //     .abs();
-
-//             // let c_inc = (c.point - b.point)
-//             //     .normalize_or_zero()
-//             //     .perp_dot(normal)
-//             //     .abs();
-
-//             // b.point += normal * margin * (c_inc - a_inc)
-//         }
-//     }
+/// Shrinks the portal by the agent radius from both ends so that the agent does not clip the
+/// corners of walls. Portals too narrow to shrink are reduced to their midpoint.
+fn clip_portal(edge: Edge3D, radius: f32) -> Edge3D {
+    let length = edge.length();
+    if length <= 2.0 * radius {
+        let mid = (edge.p1 + edge.p2) * 0.5;
+        return Edge3D::new(mid, mid);
+    }
 
-//     // resolve_clip(portals, &mut path[1..], margin)
-// }
+    let dir = (edge.p2 - edge.p1) / length;
+    Edge3D::new(edge.p1 + dir * radius, edge.p2 - dir * radius)
+}
 
-fn shorten(navmesh: &Navmesh, path: &mut [Waypoint]) {
-    for _ in 0..100 {
+fn shorten(navmesh: &Navmesh, radius: f32, iterations: usize, path: &mut [Waypoint]) {
+    for _ in 0..iterations {
         let mut shortened = 0;
         for i in 0..path.len() {
             let [a, b, c, ..] = &mut path[i..] else {
@@ -271,7 +1411,7 @@ fn shorten(navmesh: &Navmesh, path: &mut [Waypoint]) {
             if let Some(edge) = b.edge {
                 let portal = navmesh.links()[edge];
                 // c was directly visible from a
-                let edge = portal.destination_edge();
+                let edge = clip_portal(portal.destination_edge(), radius);
                 if let Some(p) = edge.intersect_ray_clipped(a.point, c.point - a.point) {
                     let prev = b.point;
                     if (prev.distance_squared(p)) > TOLERANCE {
@@ -300,3 +1440,745 @@ fn shorten(navmesh: &Navmesh, path: &mut [Waypoint]) {
     // shorten(navmesh, &mut path[1..]);
     // return false;
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4};
+    use itertools::Itertools;
+
+    use crate::{
+        brush::{Brush, BrushFlags, PositionedBrush},
+        navmesh::{Navmesh, NavmeshSettings},
+        test_util::{cube, navmesh_from, slab},
+    };
+
+    use super::*;
+
+    /// Two adjacent floor slabs meeting at `x = 0`
+    fn floor() -> Navmesh {
+        let slab = |x: f32| cube(vec3(x, 0.0, 0.0), vec3(5.0, 0.4, 10.0));
+        navmesh_from([slab(-5.0), slab(5.0)])
+    }
+
+    #[test]
+    fn same_polygon() {
+        let navmesh = floor();
+        let start = vec3(-5.0, 0.5, 4.0);
+        let end = vec3(-4.0, 0.5, 5.0);
+        assert_eq!(
+            navmesh.closest_polygon(start).unwrap().0,
+            navmesh.closest_polygon(end).unwrap().0
+        );
+
+        let path = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        assert_eq!(path.len(), 2);
+        assert_eq!(path[0].point(), start);
+        assert_eq!(path[1].point(), end);
+    }
+
+    #[test]
+    fn budget_exceeded() {
+        let navmesh = floor();
+        let start = vec3(-5.0, 0.5, 4.0);
+        let end = vec3(5.0, 0.5, -4.0);
+
+        let result = astar_with_options(
+            &navmesh,
+            start,
+            end,
+            |a, b| a.distance(b),
+            AstarOptions {
+                max_expansions: Some(1),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(result.unwrap_err(), PathError::BudgetExceeded(1));
+        assert!(astar(&navmesh, start, end, |a, b| a.distance(b)).is_some());
+    }
+
+    #[test]
+    fn reuse_context() {
+        let navmesh = floor();
+        let mut context = PathfindingContext::new();
+
+        let queries = [
+            (vec3(-5.0, 0.5, 4.0), vec3(5.0, 0.5, -4.0)),
+            (vec3(-5.0, 0.5, 4.0), vec3(-4.0, 0.5, 5.0)),
+            (vec3(5.0, 0.5, -4.0), vec3(-5.0, 0.5, 4.0)),
+        ];
+
+        for _ in 0..2 {
+            for (start, end) in queries {
+                let expected = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+                let path = context
+                    .find_path(&navmesh, start, end, |a, b| a.distance(b))
+                    .unwrap();
+
+                assert_eq!(
+                    path.iter().map(|v| v.point()).collect::<Vec<_>>(),
+                    expected.iter().map(|v| v.point()).collect::<Vec<_>>()
+                );
+            }
+        }
+
+        assert!(context
+            .find_path(&navmesh, vec3(50.0, 0.0, 0.0), Vec3::ZERO, |a, b| a
+                .distance(b))
+            .is_none());
+
+        // An aborted search leaves nothing behind for the next one
+        let (start, end) = queries[0];
+        let options = AstarOptions {
+            max_expansions: Some(1),
+            ..Default::default()
+        };
+        assert!(context
+            .find_path_with_options(&navmesh, start, end, |a, b| a.distance(b), &options)
+            .is_err());
+
+        let expected = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        let path = context
+            .find_path(&navmesh, start, end, |a, b| a.distance(b))
+            .unwrap();
+        assert_eq!(
+            path.iter().map(|v| v.point()).collect::<Vec<_>>(),
+            expected.iter().map(|v| v.point()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parallel_queries() {
+        let navmesh = floor();
+        let (start, end) = (vec3(-5.0, 0.5, 4.0), vec3(5.0, 0.5, -4.0));
+        let expected = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut context = PathfindingContext::new();
+                    for _ in 0..16 {
+                        let path = context
+                            .find_path(&navmesh, start, end, |a, b| a.distance(b))
+                            .unwrap();
+
+                        assert!(path
+                            .iter()
+                            .map(|v| v.point())
+                            .eq(expected.iter().map(|v| v.point())));
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn path_cost() {
+        let navmesh = floor();
+        let top = navmesh.closest_polygon(Vec3::ZERO).unwrap().1.points()[0].y;
+        let start = vec3(-5.0, top, 4.0);
+        let end = vec3(5.0, top, -4.0);
+
+        let path = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+
+        // Flat ground costs exactly the distance walked
+        assert!(path.length() >= start.distance(end) - 1e-4);
+        assert!((path.cost() - path.length()).abs() < 1e-3);
+
+        let polygons = path.polygons().collect_vec();
+        assert_eq!(
+            polygons,
+            [
+                navmesh.closest_polygon(start).unwrap().0,
+                navmesh.closest_polygon(end).unwrap().0
+            ]
+        );
+
+        // Slopes cost more than their length
+        let path = ridge(1.0)
+            .find_path(vec3(-4.0, 0.1, -3.0), vec3(4.0, 0.1, -3.0))
+            .unwrap();
+        assert!(path.cost() > path.length() + 0.1);
+    }
+
+    #[test]
+    fn multiple_targets() {
+        // Three connected platforms and one isolated
+        let navmesh = navmesh_from([
+            slab(-2.0, 0.0),
+            slab(0.0, 0.3),
+            slab(2.0, 0.0),
+            slab(10.0, 0.0),
+        ]);
+
+        let start = vec3(-2.0, 0.5, 0.0);
+        let targets = [
+            vec3(2.0, 0.5, 0.5),
+            vec3(0.0, 0.8, 0.0),
+            vec3(2.0, 0.5, -0.5),
+            vec3(10.0, 0.5, 0.0),
+            vec3(50.0, 0.5, 0.0),
+        ];
+
+        let paths = dijkstra_multi(&navmesh, start, &targets);
+        assert_eq!(paths.len(), targets.len());
+
+        for (&target, path) in targets.iter().zip(&paths).take(3) {
+            let path = path.as_ref().unwrap();
+            let last = path.last().unwrap();
+
+            assert_eq!(path[0].point(), start);
+            assert_eq!(last.point(), target);
+            assert_eq!(
+                last.target_polygon(),
+                navmesh.closest_polygon(target).unwrap().0
+            );
+            assert!(path.cost() >= start.distance(target) - 1e-4);
+        }
+
+        assert!(paths[3].is_none());
+        assert!(paths[4].is_none());
+    }
+
+    #[test]
+    fn multiple_targets_offmesh() {
+        let island = |x: f32, y: f32| cube(vec3(x, y, 0.0), vec3(2.0, 0.2, 2.0));
+        let mut navmesh = navmesh_from([island(-4.0, 0.0), island(4.0, 3.0)]);
+
+        let (from, to) = (vec3(-3.0, 0.4, 1.0), vec3(3.0, 3.4, 1.0));
+        navmesh
+            .add_offmesh_link_with_cost(from, to, false, 0, 2.0)
+            .unwrap();
+
+        let start = vec3(-5.0, 0.4, 0.0);
+        let target = vec3(5.0, 3.4, 0.0);
+        let paths = dijkstra_multi(&navmesh, start, &[target]);
+        let path = paths[0].as_ref().unwrap();
+
+        // The jump is paid for in full, regardless of the heuristic
+        let jump = from.distance(to) * 2.0;
+        assert!(path.cost() >= start.distance(from) * 2.0 + jump - 1e-3);
+
+        let expected = astar(&navmesh, start, target, |a, b| a.distance(b)).unwrap();
+        assert!((path.cost() - expected.cost()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn off_mesh() {
+        let navmesh = floor();
+        let top = navmesh.closest_polygon(Vec3::ZERO).unwrap().1.points()[0].y;
+
+        // Hovering above the mesh
+        let start = vec3(-5.0, top + 0.5, 4.0);
+        let end = vec3(5.0, top + 0.5, -4.0);
+        let path = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        assert!(path.len() >= 2);
+
+        // Just past the edge of the mesh, the start is moved onto the nearest polygon
+        let start = vec3(-10.5, top, 0.0);
+        let path = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        let first = path[0].point();
+        assert!((first.x - -10.2).abs() < 1e-3, "{first}");
+        assert_eq!(
+            navmesh.closest_polygon(first).unwrap().0,
+            path[0].target_polygon()
+        );
+
+        // Too far away
+        let start = vec3(-15.0, top, 0.0);
+        assert_eq!(
+            astar_with_options(
+                &navmesh,
+                start,
+                end,
+                |a, b| a.distance(b),
+                Default::default()
+            )
+            .unwrap_err(),
+            PathError::StartNotFound
+        );
+    }
+
+    /// A floor crossed by a sloped ridge along the z axis, with a flat detour past its end
+    fn ridge(slope_penalty: f32) -> Navmesh {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            slope_penalty,
+            ..Default::default()
+        };
+
+        let floor = PositionedBrush::new(
+            Mat4::from_translation(vec3(0.0, -0.5, 0.0)) * Mat4::from_scale(vec3(6.0, 0.5, 6.0)),
+            Arc::new(Brush::cube()),
+        );
+
+        let ridge = PositionedBrush::new(
+            Mat4::from_translation(vec3(0.0, 0.0, -2.5))
+                * Mat4::from_scale(vec3(1.0, 0.4, 1.0))
+                * Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4)
+                * Mat4::from_scale(vec3(1.5, 1.5, 4.5)),
+            Arc::new(Brush::cube()),
+        );
+
+        Navmesh::new(settings, [floor, ridge])
+    }
+
+    #[test]
+    fn slope_penalty() {
+        let start = vec3(-4.0, 0.1, -3.0);
+        let end = vec3(4.0, 0.1, -3.0);
+
+        // Without a penalty the path goes straight over the ridge
+        let path = ridge(0.0).find_path(start, end).unwrap();
+        assert!(path.iter().any(|v| v.point().y > 0.5));
+
+        // A steep penalty makes the flat detour around the end of the ridge cheaper
+        let path = ridge(10.0).find_path(start, end).unwrap();
+        assert!(path.iter().all(|v| v.point().y < 0.2));
+        assert!(path.iter().any(|v| v.point().z > 1.5));
+    }
+
+    #[test]
+    fn funnel_corners() {
+        // An L-shaped wall with a pocket on the far side
+        let navmesh = navmesh_from([
+            cube(Vec3::ZERO, vec3(5.0, 0.2, 5.0)),
+            cube(vec3(0.0, 1.0, -1.875), vec3(0.25, 1.0, 3.125)),
+            cube(vec3(1.125, 1.0, 1.0), vec3(1.375, 1.0, 0.25)),
+        ]);
+
+        let r = navmesh.settings().agent_radius;
+        let top = 0.2 + r;
+        let corners = [
+            vec3(-0.25 - r, top, 1.25 + r),
+            vec3(2.5 + r, top, 1.25 + r),
+            vec3(2.5 + r, top, 0.75 - r),
+        ];
+
+        let start = vec3(-3.0, top, -3.0);
+        let end = vec3(1.5, top, -1.0);
+
+        let path = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        assert!(navmesh.polygons().len() > corners.len());
+
+        let points = path.iter().map(|v| v.point()).collect_vec();
+        assert_eq!(points.len(), corners.len() + 2, "{points:?}");
+        assert_eq!(points[0], start);
+        assert_eq!(points[points.len() - 1], end);
+
+        // The crossed portals keep the agent radius of clearance from the corners
+        for (point, corner) in points[1..points.len() - 1].iter().zip(corners) {
+            assert!(
+                (point.distance(corner) - r).abs() < 0.01,
+                "{point} {corner}"
+            );
+        }
+
+        // The iterative shortening keeps a waypoint at every crossed polygon edge
+        let shortened = astar_with_options(
+            &navmesh,
+            start,
+            end,
+            |a, b| a.distance(b),
+            AstarOptions {
+                smoothing: PathSmoothing::Shorten,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(shortened.len() >= points.len());
+        assert!(path.length() <= shortened.length() + 1e-3);
+    }
+
+    #[test]
+    fn narrow_doorway() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let slab = |x: f32, depth: f32| cube(vec3(x, 0.0, 0.0), vec3(1.0, 0.2, depth));
+
+        // Two rooms joined by a corridor exactly as wide as the agent
+        let radius = 0.2;
+        let navmesh = Navmesh::new(
+            settings,
+            [slab(-2.0, 3.0), slab(0.0, radius), slab(2.0, 3.0)],
+        );
+
+        let start = vec3(-2.5, 0.2, 2.0);
+        let end = vec3(2.5, 0.2, -2.0);
+
+        for smoothing in [PathSmoothing::Funnel, PathSmoothing::Shorten] {
+            let path = astar_with_options(
+                &navmesh,
+                start,
+                end,
+                |a, b| a.distance(b),
+                AstarOptions {
+                    smoothing,
+                    agent_radius: Some(radius),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            for doorway in [vec3(-1.0, 0.2, 0.0), vec3(1.0, 0.2, 0.0)] {
+                assert!(
+                    path.iter().any(|v| v.point().distance(doorway) < 0.01),
+                    "{smoothing:?} {:?}",
+                    path.waypoints()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn step_cost_multiplier() {
+        // A raised platform with a flat passage around its end
+        let navmesh = navmesh_from([
+            cube(Vec3::ZERO, vec3(6.0, 0.2, 6.0)),
+            cube(vec3(0.0, 0.3, -2.0), vec3(1.0, 0.2, 5.0)),
+        ]);
+
+        let top = 0.2 + navmesh.settings().agent_radius;
+        let start = vec3(-4.0, top, -2.0);
+        let end = vec3(4.0, top, -2.0);
+
+        let costs = |multiplier: f32| {
+            move |link: &NavmeshLink, distance: f32| match link.kind() {
+                LinkKind::StepUp(..) | LinkKind::StepDown(..) => distance * multiplier,
+                _ => distance,
+            }
+        };
+
+        let path = astar_with_costs(&navmesh, start, end, |a, b| a.distance(b), costs(1.0));
+        let path = path.unwrap();
+        assert!(path.iter().any(|v| v.point().y > top + 0.1));
+
+        let path = astar_with_costs(&navmesh, start, end, |a, b| a.distance(b), costs(10.0));
+        let path = path.unwrap();
+        assert!(path.iter().all(|v| v.point().y < top + 0.1));
+        assert!(path.iter().any(|v| v.point().z > 3.0));
+        assert!(path.length() > start.distance(end) + 1.0);
+    }
+
+    #[test]
+    fn waypoint_link_kind() {
+        let navmesh = navmesh_from([slab(-2.0, 0.0), slab(0.0, 0.3)]);
+
+        let start = vec3(-2.0, 0.4, 0.0);
+        let end = vec3(0.0, 0.7, 0.0);
+
+        for smoothing in [PathSmoothing::Funnel, PathSmoothing::Shorten] {
+            let options = AstarOptions {
+                smoothing,
+                ..Default::default()
+            };
+
+            let path =
+                astar_with_options(&navmesh, start, end, |a, b| a.distance(b), options.clone());
+            let path = path.unwrap();
+
+            let step = path
+                .iter()
+                .find(|v| matches!(v.link_kind(), Some(LinkKind::StepUp(..))))
+                .unwrap_or_else(|| panic!("{smoothing:?} {:?}", path.waypoints()));
+
+            // The step is entered at the edge of the inflated upper slab
+            let edge_x = -1.0 - navmesh.settings().agent_radius;
+            let edge = step.entry_edge().unwrap();
+            assert!((edge.p1.x - edge_x).abs() < 0.01 && (edge.p2.x - edge_x).abs() < 0.01);
+            assert_eq!(
+                edge,
+                navmesh.links()[step.edge().unwrap()].destination_edge()
+            );
+
+            assert!(path.waypoints()[0].link_kind().is_none());
+            assert!((step.step_height().unwrap() - 0.3).abs() < 0.01);
+
+            // Stepping back down is told apart from stepping up
+            let path = astar_with_options(&navmesh, end, start, |a, b| a.distance(b), options);
+            let step = path
+                .unwrap()
+                .iter()
+                .find(|v| v.link_kind().is_some())
+                .copied()
+                .unwrap();
+
+            assert!(matches!(step.link_kind(), Some(LinkKind::StepDown(..))));
+            assert!((step.step_height().unwrap() + 0.3).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn narrow_link() {
+        // The raised platform only overlaps the lower one by 0.05 along their shared edge
+        let brushes = [
+            cube(vec3(-1.0, 0.0, 0.0), vec3(1.0, 0.2, 1.0)),
+            cube(vec3(1.0, 0.3, 1.95), vec3(1.0, 0.2, 1.0)),
+        ];
+
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let start = vec3(-1.0, 0.2, 0.0);
+        let end = vec3(1.0, 0.5, 2.0);
+
+        let navmesh = Navmesh::new(settings, brushes.clone());
+        let width = navmesh
+            .links()
+            .iter()
+            .map(|v| v.1.width())
+            .fold(0.0, f32::max);
+        assert!((width - 0.05).abs() < 1e-4, "{width}");
+        assert!(navmesh.find_path(start, end).is_some());
+
+        let options = AstarOptions {
+            agent_width: Some(0.3),
+            ..Default::default()
+        };
+
+        let path = astar_with_options(&navmesh, start, end, |a, b| a.distance(b), options);
+        assert!(matches!(path, Err(PathError::Unreachable)), "{path:?}");
+
+        let settings = NavmeshSettings {
+            min_link_width: 0.3,
+            ..settings
+        };
+
+        let navmesh = Navmesh::new(settings, brushes);
+        assert_eq!(navmesh.links().len(), 0);
+        assert!(navmesh.find_path(start, end).is_none());
+    }
+
+    /// A floor of tiles with pillars scattered across it
+    fn pillars(size: i32) -> Navmesh {
+        let tiles = (0..size).flat_map(|x| (0..size).map(move |z| (x, z)));
+        let brushes = tiles.flat_map(|(x, z)| {
+            let center = vec3(x as f32 * 2.0, 0.0, z as f32 * 2.0);
+            let pillar =
+                (x * 7 + z * 3) % 5 == 0 && (x, z) != (0, 0) && (x, z) != (size - 1, size - 1);
+
+            [cube(center, vec3(1.0, 0.2, 1.0))]
+                .into_iter()
+                .chain(pillar.then(|| cube(center + Vec3::Y * 1.5, vec3(0.5, 1.5, 0.5))))
+        });
+
+        navmesh_from(brushes)
+    }
+
+    #[test]
+    fn hierarchical() {
+        let mut navmesh = pillars(10);
+        let start = vec3(0.0, 0.5, 0.0);
+        let end = vec3(18.0, 0.5, 18.0);
+        let heuristic = |a: Vec3, b: Vec3| a.distance(b);
+
+        // Without clusters the search is not restricted
+        let mut context = PathfindingContext::new();
+        let model = DefaultCostModel::new(heuristic);
+        let options = AstarOptions::default();
+        let flat = context
+            .find_path_with_model(&navmesh, start, end, &model, &options)
+            .unwrap()
+            .to_vec();
+        assert!(context.expansions() > 0);
+
+        navmesh.build_clusters(4.0);
+        let clusters = navmesh.clusters().unwrap();
+        assert!(clusters.cluster_count() >= 25);
+        for (index, _) in navmesh.polygons() {
+            assert!(clusters.cluster(index).is_some());
+        }
+
+        let path = context
+            .find_path_hierarchical_with_model(&navmesh, start, end, &model, &options)
+            .unwrap()
+            .to_vec();
+
+        let length = |path: &[Waypoint]| {
+            path.iter()
+                .tuple_windows()
+                .map(|(a, b)| a.point().distance(b.point()))
+                .sum::<f32>()
+        };
+
+        let (flat_length, length) = (length(&flat), length(&path));
+        assert!(length <= flat_length * 1.1, "{length} vs {flat_length}");
+        assert!(path.last().unwrap().point().distance(end) < 1e-3);
+
+        assert!(astar_hierarchical(&navmesh, start, end, heuristic).is_some());
+
+        // Regenerating the links discards the clusters
+        navmesh.generate_links();
+        assert!(navmesh.clusters().is_none());
+    }
+
+    #[test]
+    fn repair_path() {
+        let floor = cube(Vec3::ZERO, vec3(10.0, 0.4, 10.0));
+        let pillar = |x: f32| cube(vec3(x, 2.0, 0.0), vec3(1.0, 2.0, 3.0));
+
+        // The path bends around the first pillar
+        let mut navmesh = navmesh_from([floor.clone(), pillar(-3.0)]);
+        let (start, end) = (vec3(-8.0, 0.4, 0.0), vec3(8.0, 0.4, 0.0));
+        let original = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        assert!(original.len() > 2);
+
+        let walkable = |navmesh: &Navmesh, path: &Path| {
+            path.windows(2)
+                .all(|v| navmesh.raycast(v[0].point(), v[1].point()).is_reached())
+        };
+
+        // Regenerating the same level keeps the path
+        navmesh.rebuild([floor.clone(), pillar(-3.0)]);
+        let mut path = original.clone();
+        assert_eq!(path.repair(&navmesh, start), RepairResult::Unchanged);
+        assert!(path
+            .iter()
+            .map(|v| v.point())
+            .eq(original.iter().map(|v| v.point())));
+
+        // A second pillar dropped onto the end of the path
+        navmesh.rebuild([floor.clone(), pillar(-3.0), pillar(4.0)]);
+        assert!(!walkable(&navmesh, &original));
+
+        let mut path = original.clone();
+        assert_eq!(path.repair(&navmesh, start), RepairResult::Repaired);
+        assert!(walkable(&navmesh, &path));
+        assert_eq!(path[0].point(), start);
+        assert_eq!(path[1].point(), original[1].point());
+        assert_eq!(path.last().unwrap().point(), end);
+        assert!(path
+            .iter()
+            .all(|v| v.point().z.abs() >= 3.0 || v.point().x.abs() >= 5.0));
+
+        // Polygons are resolved on the new navmesh
+        for waypoint in &path {
+            assert!(navmesh.polygons().contains(waypoint.target_polygon()));
+            assert_eq!(
+                waypoint.polygon_id(),
+                navmesh.polygon_id(waypoint.target_polygon())
+            );
+        }
+
+        // Halfway to the goal, only the blocked remainder is left
+        let mut path = original.clone();
+        let position = original[original.len() - 2].point();
+        assert_eq!(path.repair(&navmesh, position), RepairResult::Replanned);
+        assert_eq!(path[0].point(), position);
+        assert!(walkable(&navmesh, &path));
+
+        // A wall across the whole level
+        navmesh.rebuild([floor, cube(vec3(0.0, 2.0, 0.0), vec3(0.5, 2.0, 10.0))]);
+        let mut path = original.clone();
+        assert_eq!(path.repair(&navmesh, start), RepairResult::Impossible);
+        assert!(path
+            .iter()
+            .map(|v| v.point())
+            .eq(original.iter().map(|v| v.point())));
+    }
+
+    #[test]
+    fn smoothing_skinny_polygons() {
+        // Long strips crossed at an angle, as left behind by the BSP splits
+        let strips = (0..8).map(|i| {
+            PositionedBrush::new(
+                Mat4::from_translation(vec3(i as f32 * 0.5, 0.0, 0.0))
+                    * Mat4::from_scale(vec3(0.25, 0.2, 5.0)),
+                Arc::new(Brush::cube()),
+            )
+        });
+
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(settings, strips);
+        assert!(navmesh.polygons().len() >= 8);
+
+        let (start, end) = (vec3(-0.1, 0.2, -4.0), vec3(3.6, 0.2, 4.0));
+        let path = |smoothing, shorten_iterations| {
+            let options = AstarOptions {
+                smoothing,
+                shorten_iterations,
+                ..Default::default()
+            };
+
+            navmesh.find_path_with_options(start, end, options).unwrap()
+        };
+
+        let raw = path(PathSmoothing::None, None);
+        let shortened = path(PathSmoothing::Shorten, None);
+        let funnel = path(PathSmoothing::Funnel, None);
+        assert!(raw.len() > 2);
+        assert_eq!(raw.first().unwrap().point(), start);
+        assert_eq!(raw.last().unwrap().point(), end);
+
+        // The string is pulled straight through all portals
+        assert_eq!(funnel.len(), 2);
+        assert!((funnel.length() - start.distance(end)).abs() < 1e-3);
+        assert!(shortened.length() <= raw.length() + 1e-4);
+        assert!(funnel.length() <= shortened.length() + 1e-4);
+
+        // Shortening without any passes leaves the crossing points of the search
+        let unshortened = path(PathSmoothing::Shorten, Some(0));
+        assert!(unshortened
+            .iter()
+            .map(|v| v.point())
+            .eq(raw.iter().map(|v| v.point())));
+    }
+
+    #[test]
+    fn area_costs() {
+        let mud = BrushFlags {
+            walkable: true,
+            area_id: 2,
+        };
+
+        // A strip of mud across the direct route, with a passage around its end
+        let navmesh = navmesh_from([
+            cube(Vec3::ZERO, vec3(6.0, 0.2, 6.0)),
+            cube(vec3(0.0, 0.05, -1.5), vec3(1.0, 0.2, 4.5)).with_flags(mud),
+        ]);
+
+        let top = 0.2 + navmesh.settings().agent_radius;
+        let start = vec3(-4.0, top, -2.0);
+        let end = vec3(4.0, top, -2.0);
+
+        let path = |area_costs: Vec<f32>| {
+            let options = AstarOptions {
+                area_costs,
+                ..Default::default()
+            };
+
+            navmesh.find_path_with_options(start, end, options).unwrap()
+        };
+
+        let enters_mud = |path: &Path| {
+            path.iter()
+                .any(|v| navmesh.polygon_area_id(v.target_polygon()) == Some(2))
+        };
+
+        let direct = path(Vec::new());
+        assert!(enters_mud(&direct));
+
+        // Walks around the mud once it is expensive enough, or impassable
+        for cost in [10.0, f32::INFINITY] {
+            let around = path(vec![1.0, 1.0, cost]);
+            assert!(!enters_mud(&around), "{cost}");
+            assert!(around.iter().any(|v| v.point().z > 3.0));
+            assert!(around.length() > direct.length() + 1.0);
+        }
+
+        // Costs of other areas do not affect the mud
+        assert!(enters_mud(&path(vec![1.0, 10.0])));
+    }
+}
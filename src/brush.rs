@@ -1,67 +1,456 @@
-use std::{f32::consts::PI, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    f32::consts::PI,
+    sync::Arc,
+};
 
-use glam::{Mat4, Vec3};
+use glam::{vec2, Mat4, Vec2, Vec3};
 use itertools::Itertools;
+use smallvec::SmallVec;
 
-#[derive(Debug, Clone, Copy)]
+use crate::{edge::Edge3D, plane::Plane, tree::BspTree, util::TOLERANCE};
+
+/// Properties of the brush a face originates from
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BrushFlags {
+    /// Agents may stand on the upward facing faces of the brush.
+    ///
+    /// Brushes which are not walkable still carve out space, such as water or rubble.
+    pub walkable: bool,
+    /// Identifies the kind of surface, so that it may be traversed at a different cost
+    pub area_id: u8,
+}
+
+impl Default for BrushFlags {
+    fn default() -> Self {
+        Self {
+            walkable: true,
+            area_id: 0,
+        }
+    }
+}
+
+/// How a brush is combined with the brushes before it when generating a navmesh
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CsgOp {
+    /// Adds the volume of the brush
+    #[default]
+    Union,
+    /// Carves the volume of the brush out of the brushes before it
+    Subtract,
+}
+
+/// A planar convex polygon
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Face {
-    pub p1: Vec3,
-    pub p2: Vec3,
-    pub p3: Vec3,
+    points: SmallVec<[Vec3; 4]>,
+    flags: BrushFlags,
 }
 
 impl Face {
-    pub fn new(p1: Vec3, p2: Vec3, p3: Vec3) -> Self {
-        assert!(p1.is_finite());
-        assert!(p2.is_finite());
-        assert!(p3.is_finite());
-        let f = Self { p1, p2, p3 };
-        assert!(f.normal().is_finite());
-        f
+    /// Creates a face from the vertices of a convex polygon in counter-clockwise order.
+    ///
+    /// The points may be collinear, in which case the normal is not finite. Use
+    /// [`Face::try_new`] to reject such faces.
+    pub fn new(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let points: SmallVec<[Vec3; 4]> = points.into_iter().collect();
+        assert!(points.len() >= 3);
+        assert!(points.iter().all(|v| v.is_finite()));
+        Self {
+            points,
+            flags: BrushFlags::default(),
+        }
+    }
+
+    /// Creates a face, returning `None` if there are fewer than three points, or the points are
+    /// not finite or do not span a plane
+    pub fn try_new(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let points: SmallVec<[Vec3; 4]> = points.into_iter().collect();
+        if points.len() < 3 || !points.iter().all(|v| v.is_finite()) {
+            return None;
+        }
+
+        let face = Self {
+            points,
+            flags: BrushFlags::default(),
+        };
+
+        face.normal().is_finite().then_some(face)
+    }
+
+    /// Returns true if the face has no usable normal or an area below `epsilon` squared
+    pub fn is_degenerate(&self, epsilon: f32) -> bool {
+        !self.normal().is_finite() || self.area() < epsilon * epsilon
+    }
+
+    /// Sets the flags of the brush the face originates from
+    pub fn with_flags(mut self, flags: BrushFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn flags(&self) -> BrushFlags {
+        self.flags
+    }
+
+    pub fn triangle(p1: Vec3, p2: Vec3, p3: Vec3) -> Self {
+        Self::new([p1, p2, p3])
+    }
+
+    fn area_vector(&self) -> Vec3 {
+        let origin = self.points[0];
+        self.points[1..]
+            .iter()
+            .tuple_windows()
+            .map(|(&a, &b)| (a - origin).cross(b - origin))
+            .sum()
     }
 
     pub fn normal(&self) -> Vec3 {
-        (self.p1 - self.p3).cross(self.p2 - self.p3).normalize()
+        self.area_vector().normalize()
     }
 
-    pub fn points(&self) -> [Vec3; 3] {
-        [self.p1, self.p2, self.p3]
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
     }
 
-    pub fn edges(&self) -> [(Vec3, Vec3); 3] {
-        [(self.p1, self.p2), (self.p2, self.p3), (self.p3, self.p1)]
+    pub fn area(&self) -> f32 {
+        self.area_vector().length() * 0.5
+    }
+
+    /// Returns the center of mass of the polygon
+    pub fn centroid(&self) -> Vec3 {
+        let (weighted, area) = self
+            .triangulate()
+            .map(|[a, b, c]| {
+                let area = (b - a).cross(c - a).length();
+                ((a + b + c) * area, area)
+            })
+            .fold((Vec3::ZERO, 0.0), |acc, v| (acc.0 + v.0, acc.1 + v.1));
+
+        if area > f32::EPSILON {
+            weighted / (3.0 * area)
+        } else {
+            self.points.iter().sum::<Vec3>() / self.points.len() as f32
+        }
+    }
+
+    /// Returns the edges of the polygon in winding order
+    pub fn edges(&self) -> impl Iterator<Item = (Vec3, Vec3)> + '_ {
+        self.points.iter().copied().circular_tuple_windows()
+    }
+
+    /// Splits the polygon into a triangle fan
+    pub fn triangulate(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        let origin = self.points[0];
+        self.points[1..]
+            .iter()
+            .tuple_windows()
+            .map(move |(&a, &b)| [origin, a, b])
     }
 
     pub fn transform(&self, transform: Mat4) -> Face {
-        Self::new(
-            transform.transform_point3(self.p1),
-            transform.transform_point3(self.p2),
-            transform.transform_point3(self.p3),
-        )
+        self.map(|p| transform.transform_point3(p))
     }
 
     pub fn distance_to_plane(&self, point: Vec3) -> f32 {
         let normal = self.normal();
-        normal.dot(point) - self.p1.dot(normal)
+        normal.dot(point) - self.points[0].dot(normal)
+    }
+
+    /// Returns the height of the point above the face plane, measured along the y axis
+    pub fn vertical_distance(&self, point: Vec3) -> f32 {
+        let normal = self.normal();
+        let origin = self.points[0];
+        let plane_y = origin.y
+            - (normal.x * (point.x - origin.x) + normal.z * (point.z - origin.z)) / normal.y;
+
+        point.y - plane_y
+    }
+
+    /// Projects the point onto the face along the y axis.
+    ///
+    /// Returns `None` for vertical faces, which have no footprint.
+    pub fn vertical_projection(&self, point: Vec3) -> Option<VerticalProjection> {
+        let normal = self.normal();
+        if normal.y.abs() < TOLERANCE {
+            return None;
+        }
+
+        // Faces pointing down wind the other way when seen from above
+        let up = Vec3::Y * normal.y.signum();
+        let flat = |v: Vec3| vec2(v.x, v.z);
+
+        let inside = self
+            .edges()
+            .all(|(a, b)| (point - a).dot((b - a).cross(up)) <= 0.0);
+
+        let horizontal_distance = if inside {
+            0.0
+        } else {
+            self.edges()
+                .map(|(a, b)| {
+                    let (a, b, p) = (flat(a), flat(b), flat(point));
+                    let ab = b - a;
+                    let t =
+                        ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+                    p.distance(a + ab * t)
+                })
+                .fold(f32::MAX, f32::min)
+        };
+
+        Some(VerticalProjection {
+            height: self.vertical_distance(point),
+            horizontal_distance,
+        })
     }
 
     pub fn contains_point(&self, point: Vec3) -> bool {
         let normal = self.normal();
 
-        let ab = (point - self.p1).dot((self.p2 - self.p1).cross(normal));
-        let bc = (point - self.p2).dot((self.p3 - self.p2).cross(normal));
-        let ca = (point - self.p3).dot((self.p1 - self.p3).cross(normal));
+        self.edges()
+            .all(|(a, b)| (point - a).dot((b - a).cross(normal)) <= 0.0)
+    }
+
+    /// Returns the point on the polygon closest to `point`
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
+        let projected = point - self.normal() * self.distance_to_plane(point);
+        if self.contains_point(projected) {
+            return projected;
+        }
+
+        self.edges()
+            .map(|(a, b)| Edge3D::new(a, b).closest_point(point))
+            .min_by(|a, b| {
+                a.distance_squared(point)
+                    .total_cmp(&b.distance_squared(point))
+            })
+            .unwrap()
+    }
+
+    pub(crate) fn map(&self, f: impl FnMut(Vec3) -> Vec3) -> Face {
+        Self::new(self.points.iter().copied().map(f)).with_flags(self.flags)
+    }
+
+    /// Reverses the winding of the polygon, flipping its normal
+    pub fn flip(&self) -> Self {
+        Self::new(self.points.iter().rev().copied()).with_flags(self.flags)
+    }
+
+    /// Returns the points snapped to the tolerance grid, starting from the smallest.
+    ///
+    /// Equal polygons have equal keys regardless of which vertex they start at.
+    pub(crate) fn canonical_key(&self) -> Vec<[i32; 3]> {
+        let mut points = self
+            .points
+            .iter()
+            .map(|p| (p / TOLERANCE).round().as_ivec3().to_array())
+            .collect_vec();
 
-        ab <= 0.0 && bc <= 0.0 && ca <= 0.0
+        if let Some(min) = points.iter().position_min() {
+            points.rotate_left(min);
+        }
+
+        points
     }
+}
 
-    pub(crate) fn map(&self, mut f: impl FnMut(Vec3) -> Vec3) -> Face {
-        Self::new(f(self.p1), f(self.p2), f(self.p3))
+/// Returns the triangles of the convex hull of the points as indices, wound counter clockwise
+/// seen from outside.
+///
+/// Returns `None` if the points do not span a volume.
+fn quickhull(points: &[Vec3]) -> Option<Vec<[usize; 3]>> {
+    struct Triangle {
+        points: [usize; 3],
+        plane: Plane,
+        /// Points in front of the triangle which have not been added to the hull yet
+        outside: Vec<usize>,
+        alive: bool,
     }
 
-    pub(crate) fn flip(&self) -> Self {
-        Self::new(self.p3, self.p2, self.p1)
+    let farthest = |distance: &dyn Fn(Vec3) -> f32| {
+        (0..points.len()).max_by(|&a, &b| distance(points[a]).total_cmp(&distance(points[b])))
+    };
+
+    // The initial tetrahedron is spanned by the extremes along x, the point farthest from the
+    // line between them, and the point farthest from the plane through all three
+    let a = farthest(&|p| -p.x)?;
+    let b = farthest(&|p| (p - points[a]).length_squared())?;
+    let dir = (points[b] - points[a]).normalize_or_zero();
+    let c = farthest(&|p| (p - points[a]).cross(dir).length_squared())?;
+    let normal = (points[b] - points[a])
+        .cross(points[c] - points[a])
+        .normalize_or_zero();
+    let d = farthest(&|p| (p - points[a]).dot(normal).abs())?;
+
+    // Coplanar input has no volume
+    if normal == Vec3::ZERO || (points[d] - points[a]).dot(normal).abs() <= TOLERANCE {
+        return None;
     }
+
+    // Stays inside the hull as it grows, and orients each new triangle
+    let interior = (points[a] + points[b] + points[c] + points[d]) * 0.25;
+    let triangle = |mut v: [usize; 3]| {
+        let (p1, p2, p3) = (points[v[0]], points[v[1]], points[v[2]]);
+        let mut normal = (p2 - p1).cross(p3 - p1).normalize_or_zero();
+        if (interior - p1).dot(normal) > 0.0 {
+            v.swap(1, 2);
+            normal = -normal;
+        }
+
+        Triangle {
+            points: v,
+            plane: Plane::new(normal, p1.dot(normal)),
+            outside: Vec::new(),
+            alive: true,
+        }
+    };
+
+    let mut triangles = vec![
+        triangle([a, b, c]),
+        triangle([a, b, d]),
+        triangle([a, c, d]),
+        triangle([b, c, d]),
+    ];
+
+    // Assigns each point to the first triangle it lies in front of, discarding interior points
+    let assign = |triangles: &mut [Triangle], first: usize, candidates: &[usize]| {
+        for &point in candidates {
+            if let Some(triangle) = triangles[first..]
+                .iter_mut()
+                .find(|v| v.plane.distance_to_point(points[point]) > TOLERANCE)
+            {
+                triangle.outside.push(point);
+            }
+        }
+    };
+
+    assign(&mut triangles, 0, &(0..points.len()).collect_vec());
+
+    let mut edge_owners = HashMap::new();
+    for (index, triangle) in triangles.iter().enumerate() {
+        let [p1, p2, p3] = triangle.points;
+        for edge in [(p1, p2), (p2, p3), (p3, p1)] {
+            edge_owners.insert(edge, index);
+        }
+    }
+
+    while let Some(index) = triangles
+        .iter()
+        .position(|v| v.alive && !v.outside.is_empty())
+    {
+        let eye = *triangles[index]
+            .outside
+            .iter()
+            .max_by(|&&a, &&b| {
+                let distance = |v: usize| triangles[index].plane.distance_to_point(points[v]);
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap();
+
+        // Flood from the triangle the eye was assigned to, so the visible region stays connected
+        // and is bounded by a single horizon. Any neighbor the eye is in front of is replaced,
+        // however slightly, as keeping it would leave a sliver folded back over it
+        let mut visible = vec![index];
+        let mut seen = BTreeSet::from([index]);
+        let mut cursor = 0;
+        while let Some(&v) = visible.get(cursor) {
+            cursor += 1;
+            let [p1, p2, p3] = triangles[v].points;
+            for (p1, p2) in [(p1, p2), (p2, p3), (p3, p1)] {
+                let neighbor = edge_owners[&(p2, p1)];
+                if triangles[neighbor].plane.distance_to_point(points[eye]) > 0.0
+                    && seen.insert(neighbor)
+                {
+                    visible.push(neighbor);
+                }
+            }
+        }
+
+        // Edges of the visible triangles which are not shared with another visible triangle
+        // outline the hole to be filled from the eye point
+        let edges = visible
+            .iter()
+            .flat_map(|&v| {
+                let [p1, p2, p3] = triangles[v].points;
+                [(p1, p2), (p2, p3), (p3, p1)]
+            })
+            .collect::<BTreeSet<_>>();
+
+        let mut orphans = Vec::new();
+        for &v in &visible {
+            triangles[v].alive = false;
+            orphans.append(&mut triangles[v].outside);
+        }
+
+        let first = triangles.len();
+        for &(p1, p2) in &edges {
+            if edges.contains(&(p2, p1)) {
+                edge_owners.remove(&(p1, p2));
+            } else {
+                let new = triangle([p1, p2, eye]);
+                let [p1, p2, p3] = new.points;
+                for edge in [(p1, p2), (p2, p3), (p3, p1)] {
+                    edge_owners.insert(edge, triangles.len());
+                }
+                triangles.push(new);
+            }
+        }
+
+        orphans.retain(|&v| v != eye);
+        assign(&mut triangles, first, &orphans);
+    }
+
+    Some(
+        triangles
+            .into_iter()
+            .filter(|v| v.alive)
+            .map(|v| v.points)
+            .collect(),
+    )
+}
+
+/// Returns the convex hull of points lying in a plane, wound counter clockwise around `normal`
+fn convex_polygon(points: impl Iterator<Item = Vec3>, normal: Vec3) -> Vec<Vec3> {
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+
+    let mut points = points.map(|p| (vec2(p.dot(u), p.dot(v)), p)).collect_vec();
+    points.sort_by(|a, b| a.0.x.total_cmp(&b.0.x).then(a.0.y.total_cmp(&b.0.y)));
+    points.dedup_by(|a, b| a.0.distance(b.0) <= TOLERANCE);
+
+    // Monotone chain, keeping only strictly convex corners
+    let turn = |o: Vec2, a: Vec2, b: Vec2| (a - o).perp_dot(b - o);
+    let mut hull: Vec<(Vec2, Vec3)> = Vec::new();
+    for pass in [&points[..], &points.iter().rev().copied().collect_vec()[..]] {
+        let start = hull.len();
+        for &point in pass {
+            while hull.len() >= start + 2
+                && turn(hull[hull.len() - 2].0, hull[hull.len() - 1].0, point.0) <= TOLERANCE
+            {
+                hull.pop();
+            }
+
+            hull.push(point);
+        }
+
+        hull.pop();
+    }
+
+    hull.into_iter().map(|v| v.1).collect_vec()
+}
+
+/// Position of a point relative to the footprint of a face, as returned by
+/// [`Face::vertical_projection`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerticalProjection {
+    /// Height of the point above the face along the y axis
+    pub height: f32,
+    /// Horizontal distance from the point to the footprint of the face, zero when inside
+    pub horizontal_distance: f32,
 }
 
 pub enum FaceIntersect {
@@ -77,11 +466,25 @@ pub enum FaceIntersect {
 pub struct PositionedBrush {
     transform: Mat4,
     brush: Arc<Brush>,
+    flags: BrushFlags,
 }
 
 impl PositionedBrush {
-    pub fn new(transform: Mat4, brush: Arc<Brush>) -> Self {
-        Self { transform, brush }
+    pub fn new(transform: Mat4, brush: impl Into<Arc<Brush>>) -> Self {
+        Self {
+            transform,
+            brush: brush.into(),
+            flags: BrushFlags::default(),
+        }
+    }
+
+    pub fn with_flags(mut self, flags: BrushFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn flags(&self) -> BrushFlags {
+        self.flags
     }
 
     pub fn brush(&self) -> &Arc<Brush> {
@@ -91,6 +494,55 @@ impl PositionedBrush {
     pub fn transform(&self) -> Mat4 {
         self.transform
     }
+
+    /// Returns the brush positioned relative to `parent`
+    pub fn transformed(&self, parent: Mat4) -> Self {
+        Self {
+            transform: parent * self.transform,
+            brush: self.brush.clone(),
+            flags: self.flags,
+        }
+    }
+
+    /// Returns the faces of the brush in world space, carrying the flags of the brush
+    pub fn world_faces(&self) -> impl Iterator<Item = Face> + '_ {
+        self.brush
+            .faces()
+            .iter()
+            .map(|face| face.transform(self.transform).with_flags(self.flags))
+    }
+
+    /// Returns the world space bounding box as `(min, max)`.
+    ///
+    /// An empty brush yields an inverted box with `min > max`.
+    pub fn aabb(&self) -> (Vec3, Vec3) {
+        self.brush
+            .faces()
+            .iter()
+            .flat_map(|face| face.points())
+            .map(|&p| self.transform.transform_point3(p))
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+                (min.min(p), max.max(p))
+            })
+    }
+}
+
+impl From<(Mat4, Brush)> for PositionedBrush {
+    fn from((transform, brush): (Mat4, Brush)) -> Self {
+        Self::new(transform, brush)
+    }
+}
+
+impl From<(Mat4, &Brush)> for PositionedBrush {
+    fn from((transform, brush): (Mat4, &Brush)) -> Self {
+        Self::new(transform, brush.clone())
+    }
+}
+
+impl From<(Mat4, Arc<Brush>)> for PositionedBrush {
+    fn from((transform, brush): (Mat4, Arc<Brush>)) -> Self {
+        Self::new(transform, brush)
+    }
 }
 
 /// Basic primitive describing a convex shape
@@ -104,28 +556,29 @@ impl Brush {
         Self { faces }
     }
 
+    /// Returns a key which is equal for brushes of equal faces, regardless of their order
+    pub(crate) fn canonical_key(&self) -> Vec<Vec<[i32; 3]>> {
+        let mut key = self.faces.iter().map(Face::canonical_key).collect_vec();
+        key.sort();
+        key
+    }
+
     pub fn to_triangle_list(&self) -> Vec<Vec3> {
         self.faces
             .iter()
-            .flat_map(|v| [v.p1, v.p2, v.p3])
+            .flat_map(|v| v.triangulate().flatten())
             .collect_vec()
     }
 
     pub fn translate(&mut self, translation: Vec3) {
         for face in &mut self.faces {
-            face.p1 += translation;
-            face.p2 += translation;
-            face.p3 += translation;
+            *face = face.map(|p| p + translation);
         }
     }
 
     pub fn transform(&mut self, transform: Mat4) {
         for face in &mut self.faces {
-            *face = Face::new(
-                transform.transform_point3(face.p1),
-                transform.transform_point3(face.p2),
-                transform.transform_point3(face.p3),
-            );
+            *face = face.transform(transform);
         }
     }
 
@@ -135,13 +588,258 @@ impl Brush {
         self
     }
 
+    /// Returns the combined volume of both brushes
+    pub fn union(&self, other: &Brush) -> Brush {
+        match (BspTree::build(&self.faces), BspTree::build(&other.faces)) {
+            (Some(mut a), Some(b)) => {
+                a.union(b);
+                Brush::from_tree(a)
+            }
+            (Some(_), None) => self.clone(),
+            (None, _) => other.clone(),
+        }
+    }
+
+    /// Returns the volume of this brush which is not covered by `other`
+    pub fn subtract(&self, other: &Brush) -> Brush {
+        match (BspTree::build(&self.faces), BspTree::build(&other.faces)) {
+            (Some(mut a), Some(b)) => {
+                a.subtract(b);
+                Brush::from_tree(a)
+            }
+            (Some(_), None) => self.clone(),
+            (None, _) => Brush::new(Vec::new()),
+        }
+    }
+
+    /// Returns the volume shared by both brushes, which is empty if they do not overlap
+    pub fn intersect(&self, other: &Brush) -> Brush {
+        match (BspTree::build(&self.faces), BspTree::build(&other.faces)) {
+            (Some(mut a), Some(b)) => {
+                a.intersect(b);
+                Brush::from_tree(a)
+            }
+            _ => Brush::new(Vec::new()),
+        }
+    }
+
+    /// Returns the combined volume of all brushes, or `None` if there are none.
+    ///
+    /// The brushes are unioned into a single tree, rather than rebuilding a tree from the
+    /// result for every pair as repeated calls to [`Brush::union`] would.
+    pub fn union_all(brushes: impl IntoIterator<Item = Brush>) -> Option<Brush> {
+        let mut brushes = brushes.into_iter().peekable();
+        brushes.peek()?;
+
+        let tree = brushes
+            .filter_map(|v| BspTree::build(&v.faces))
+            .reduce(|mut tree, other| {
+                tree.union(other);
+                tree
+            });
+
+        Some(
+            tree.map(Brush::from_tree)
+                .unwrap_or_else(|| Brush::new(Vec::new())),
+        )
+    }
+
+    /// Collects the faces of a tree, without the seams and slivers left by the boolean
+    /// operations.
+    ///
+    /// Faces are split wherever the operands intersected, so the result is closed but may have
+    /// T-junctions.
+    fn from_tree(tree: BspTree) -> Brush {
+        Brush::new(tree.into_polygons_cleaned(TOLERANCE * TOLERANCE))
+    }
+
+    /// Returns true if no point of the brush lies in front of any of its faces
+    pub fn is_convex(&self) -> bool {
+        self.faces.iter().all(|face| {
+            let plane = Plane::from_face(face);
+            self.faces
+                .iter()
+                .flat_map(|v| v.points())
+                .all(|&p| plane.distance_to_point(p) <= TOLERANCE)
+        })
+    }
+
+    /// Returns true if every edge is shared with another face in the opposite direction
+    pub fn is_closed(&self) -> bool {
+        let quantize = |p: Vec3| (p / TOLERANCE).round().as_ivec3().to_array();
+
+        let mut edges = HashMap::new();
+        for (a, b) in self.faces.iter().flat_map(|v| v.edges()) {
+            *edges.entry((quantize(a), quantize(b))).or_insert(0i32) += 1;
+        }
+
+        edges
+            .iter()
+            .all(|(&(a, b), &count)| edges.get(&(b, a)) == Some(&count))
+    }
+
+    /// Checks the brush for authoring errors which would make the boolean operations produce
+    /// garbage.
+    ///
+    /// The winding of the faces is only checked for convex brushes, as the outward direction is
+    /// otherwise unknown.
+    pub fn validate(&self) -> Result<(), Vec<BrushDefect>> {
+        let quantize = |p: Vec3| (p / TOLERANCE).round().as_ivec3().to_array();
+
+        let mut edges = HashMap::new();
+        for (a, b) in self.faces.iter().flat_map(|v| v.edges()) {
+            *edges.entry((quantize(a), quantize(b))).or_insert(0i32) += 1;
+        }
+
+        let mut defects = Vec::new();
+        for (index, face) in self.faces.iter().enumerate() {
+            if face.is_degenerate(TOLERANCE) {
+                defects.push(BrushDefect::Degenerate { face: index });
+                continue;
+            }
+
+            for (edge, (a, b)) in face.edges().enumerate() {
+                let (a, b) = (quantize(a), quantize(b));
+                if edges.get(&(b, a)) != edges.get(&(a, b)) {
+                    defects.push(BrushDefect::OpenEdge { face: index, edge });
+                }
+            }
+        }
+
+        defects.extend(
+            self.inverted_faces()
+                .map(|face| BrushDefect::InvertedFace { face }),
+        );
+
+        if defects.is_empty() {
+            Ok(())
+        } else {
+            Err(defects)
+        }
+    }
+
+    /// Flips the faces of a convex brush which face towards its center.
+    ///
+    /// Brushes which are not convex regardless of winding are left as is.
+    pub fn fix_winding(&mut self) {
+        for index in self.inverted_faces().collect_vec() {
+            self.faces[index] = self.faces[index].flip();
+        }
+    }
+
+    /// Returns the indices of the faces facing towards the center of a convex brush
+    fn inverted_faces(&self) -> impl Iterator<Item = usize> + '_ {
+        let faces = || self.faces.iter().filter(|v| !v.is_degenerate(TOLERANCE));
+
+        // Convex regardless of winding if all points lie on the same side of every face
+        let convex = faces().all(|face| {
+            let plane = Plane::from_face(face);
+            let distances = || {
+                faces()
+                    .flat_map(|v| v.points())
+                    .map(|&p| plane.distance_to_point(p))
+            };
+            distances().all(|v| v <= TOLERANCE) || distances().all(|v| v >= -TOLERANCE)
+        });
+
+        let (weighted, area) = faces().fold((Vec3::ZERO, 0.0), |(weighted, area), face| {
+            (weighted + face.centroid() * face.area(), area + face.area())
+        });
+
+        let center = weighted / area;
+
+        self.faces
+            .iter()
+            .enumerate()
+            .filter(move |(_, face)| {
+                convex
+                    && !face.is_degenerate(TOLERANCE)
+                    && face.normal().dot(face.centroid() - center) < 0.0
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the brush grown by moving each face outwards along its normal by `radius`.
+    ///
+    /// The faces are rebuilt by intersecting the offset planes, which is only valid for closed
+    /// convex brushes. `None` is returned for any other brush. A negative radius shrinks the
+    /// brush instead.
+    pub fn inflated(&self, radius: f32) -> Option<Brush> {
+        if !self.is_closed() || !self.is_convex() {
+            return None;
+        }
+
+        let mut planes: Vec<Plane> = Vec::new();
+        let mut flags = Vec::new();
+        for face in &self.faces {
+            let plane = Plane::from_face(face);
+            let plane = Plane::new(plane.normal, plane.distance + radius);
+
+            // Coplanar faces produce a single inflated face
+            let duplicate = planes.iter().any(|v| {
+                v.normal.dot(plane.normal) > 1.0 - TOLERANCE
+                    && (v.distance - plane.distance).abs() < TOLERANCE
+            });
+
+            if !duplicate {
+                planes.push(plane);
+                flags.push(face.flags());
+            }
+        }
+
+        let (min, max) = self
+            .faces
+            .iter()
+            .flat_map(|v| v.points())
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+                (min.min(p), max.max(p))
+            });
+
+        let center = (min + max) * 0.5;
+        let extent = (max - min).length() + radius.abs() * 4.0 + 1.0;
+
+        let mut faces = Vec::new();
+        for (i, plane) in planes.iter().enumerate() {
+            // Start with a quad covering the whole brush and cut away everything in front of the
+            // other planes
+            let origin = center - plane.normal * plane.distance_to_point(center);
+            let u = plane.normal.any_orthonormal_vector() * extent;
+            let v = plane.normal.cross(u);
+
+            let mut face = Some(
+                Face::new([
+                    origin - u - v,
+                    origin + u - v,
+                    origin + u + v,
+                    origin - u + v,
+                ])
+                .with_flags(flags[i]),
+            );
+
+            for (_, other) in planes.iter().enumerate().filter(|&(j, _)| j != i) {
+                let Some(current) = face.take() else {
+                    break;
+                };
+
+                let mut front = Vec::new();
+                let mut back = Vec::new();
+                other.split_face(&current, &mut front, &mut back);
+                face = back.pop();
+            }
+
+            faces.extend(face);
+        }
+
+        Some(Brush::new(faces))
+    }
+
     pub fn plane() -> Self {
         let p1 = Vec3::new(-1.0, 0.0, -1.0);
         let p2 = Vec3::new(1.0, 0.0, -1.0);
         let p3 = Vec3::new(-1.0, 0.0, 1.0);
         let p4 = Vec3::new(1.0, 0.0, 1.0);
 
-        Self::new(vec![Face::new(p3, p2, p1), Face::new(p3, p4, p2)])
+        Self::new(vec![Face::new([p3, p4, p2, p1])])
     }
 
     pub fn cube() -> Self {
@@ -158,40 +856,80 @@ impl Brush {
         let p8 = Vec3::new(1.0, 1.0, -1.0);
 
         let faces = vec![
-            Face::new(p3, p2, p1),
-            Face::new(p1, p4, p3),
-            Face::new(p5, p6, p7),
-            Face::new(p7, p8, p5),
-            Face::new(p6, p5, p1),
-            Face::new(p1, p2, p6),
-            Face::new(p7, p6, p2),
-            Face::new(p2, p3, p7),
-            Face::new(p8, p7, p3),
-            Face::new(p3, p4, p8),
-            Face::new(p5, p8, p4),
-            Face::new(p4, p1, p5),
-            //
-            // Face::new(p1, p2, p3),
-            // Face::new(p3, p4, p1),
-            // Face::new(p5, p6, p7),
-            // Face::new(p7, p8, p5),
-            // Face::new(p1, p5, p6),
-            // Face::new(p6, p2, p1),
-            // Face::new(p2, p6, p7),
-            // Face::new(p7, p3, p2),
-            // Face::new(p3, p7, p8),
-            // Face::new(p8, p4, p3),
-            // Face::new(p4, p8, p5),
-            // Face::new(p5, p1, p4),
+            Face::new([p3, p2, p1, p4]),
+            Face::new([p5, p6, p7, p8]),
+            Face::new([p6, p5, p1, p2]),
+            Face::new([p7, p6, p2, p3]),
+            Face::new([p8, p7, p3, p4]),
+            Face::new([p5, p8, p4, p1]),
         ];
 
         Self::new(faces)
     }
 
-    pub fn uv_sphere() -> Self {
-        let radius = 1.0;
-        let slices = 16;
-        let stacks = 12;
+    /// Creates a cylinder around the y axis, centered at the origin
+    pub fn cylinder(radius: f32, height: f32, segments: usize) -> Self {
+        assert!(segments >= 3, "a cylinder requires at least 3 segments");
+
+        let half_height = height * 0.5;
+        let ring = |y: f32| {
+            (0..segments).map(move |i| {
+                let angle = i as f32 / segments as f32 * 2.0 * PI;
+                Vec3::new(angle.cos() * radius, y, angle.sin() * radius)
+            })
+        };
+
+        let bottom = ring(-half_height).collect_vec();
+        let top = ring(half_height).collect_vec();
+
+        let mut faces = Vec::with_capacity(segments + 2);
+
+        // Increasing angles wind clockwise when viewed from above
+        faces.push(Face::new(bottom.iter().copied()));
+        faces.push(Face::new(top.iter().rev().copied()));
+
+        for i in 0..segments {
+            let j = (i + 1) % segments;
+            faces.push(Face::new([bottom[i], top[i], top[j], bottom[j]]));
+        }
+
+        Self::new(faces)
+    }
+
+    /// Creates a cone around the y axis, centered at the origin with the apex at the top
+    pub fn cone(radius: f32, height: f32, segments: usize) -> Self {
+        assert!(segments >= 3, "a cone requires at least 3 segments");
+
+        let half_height = height * 0.5;
+        let bottom = (0..segments)
+            .map(|i| {
+                let angle = i as f32 / segments as f32 * 2.0 * PI;
+                Vec3::new(angle.cos() * radius, -half_height, angle.sin() * radius)
+            })
+            .collect_vec();
+
+        let apex = Vec3::Y * half_height;
+
+        let mut faces = Vec::with_capacity(segments + 1);
+        faces.push(Face::new(bottom.iter().copied()));
+
+        for i in 0..segments {
+            let j = (i + 1) % segments;
+            faces.push(Face::triangle(bottom[i], apex, bottom[j]));
+        }
+
+        Self::new(faces)
+    }
+
+    /// Creates a prism with `sides` sides, fitting within the same bounds as [`Brush::cube`]
+    pub fn prism(sides: usize) -> Self {
+        Self::cylinder(1.0, 2.0, sides)
+    }
+
+    /// Creates a sphere centered at the origin, made of `slices` segments around the y axis and
+    /// `stacks` rings from pole to pole
+    pub fn uv_sphere(radius: f32, slices: usize, stacks: usize) -> Self {
+        assert!(slices >= 3 && stacks >= 2);
 
         let mut faces = Vec::new();
 
@@ -227,10 +965,13 @@ impl Brush {
                     radius * theta1.sin() * phi2.sin(),
                 );
 
+                // The quads touching the poles collapse into a single triangle
                 if j != 0 {
-                    faces.push(Face::new(p1, p2, p3));
-                } else if j != stacks - 1 {
-                    faces.push(Face::new(p3, p4, p1));
+                    faces.push(Face::triangle(p1, p2, p3));
+                }
+
+                if j != stacks - 1 {
+                    faces.push(Face::triangle(p3, p4, p1));
                 }
             }
         }
@@ -238,18 +979,265 @@ impl Brush {
         Self::new(faces)
     }
 
+    /// Creates the convex hull of the points.
+    ///
+    /// Coplanar points on the surface of the hull are merged into a single convex face, and
+    /// points inside the hull are ignored. Returns `None` if the points do not enclose a
+    /// volume, such as fewer than four points or coplanar input.
+    ///
+    /// The hull is built incrementally by quickhull, so large point clouds such as the vertices
+    /// of a mesh are fine.
+    pub fn from_convex_hull(points: &[Vec3]) -> Option<Brush> {
+        let triangles = quickhull(points)?;
+
+        // Coplanar triangles are merged into a single face. Nearly coplanar triangles are kept
+        // apart, as merging them would leave their vertices on the edges of the neighbouring
+        // faces.
+        let mut groups: Vec<(Plane, Vec<[usize; 3]>)> = Vec::new();
+        for triangle in triangles {
+            let [a, b, c] = triangle.map(|v| points[v]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            let plane = Plane::new(normal, a.dot(normal));
+
+            let group = groups.iter_mut().find(|(v, _)| {
+                v.normal.dot(plane.normal) > 1.0 - TOLERANCE * TOLERANCE
+                    && (v.distance - plane.distance).abs() < TOLERANCE
+            });
+
+            match group {
+                Some((_, group)) => group.push(triangle),
+                None => groups.push((plane, vec![triangle])),
+            }
+        }
+
+        if groups.len() < 4 {
+            return None;
+        }
+
+        let faces = groups
+            .into_iter()
+            .map(|(plane, group)| match group[..] {
+                [triangle] => Face::new(triangle.map(|v| points[v])),
+                _ => Face::new(convex_polygon(
+                    group.iter().flatten().map(|&v| points[v]),
+                    plane.normal,
+                )),
+            })
+            .collect_vec();
+
+        Some(Brush::new(faces))
+    }
+
+    /// Creates a brush of triangles from an indexed triangle list, such as a mesh exported from a
+    /// modelling tool.
+    ///
+    /// The mesh is used as is, and [`Brush::validate`] can be used to check that it is closed
+    /// and consistently wound. Trailing indices which do not form a whole triangle are ignored.
+    ///
+    /// # Panics
+    ///
+    /// If an index is out of bounds of `positions`.
+    pub fn from_indexed_mesh(positions: &[Vec3], indices: &[u32]) -> Brush {
+        let faces = indices
+            .chunks_exact(3)
+            .map(|v| {
+                Face::triangle(
+                    positions[v[0] as usize],
+                    positions[v[1] as usize],
+                    positions[v[2] as usize],
+                )
+            })
+            .collect_vec();
+
+        Brush::new(faces)
+    }
+
+    /// Creates a brush of triangles from an indexed triangle list, rejecting malformed input
+    /// rather than panicking like [`Brush::from_indexed_mesh`].
+    ///
+    /// Degenerate triangles, such as those with coincident vertices, are dropped, and the
+    /// winding of convex meshes is fixed with [`Brush::fix_winding`]. Open meshes are accepted
+    /// for navmesh-only use, while [`Brush::is_closed`] tells whether the boolean operations
+    /// will behave.
+    pub fn from_mesh(positions: &[Vec3], indices: &[u32]) -> Result<Brush, BrushError> {
+        if !indices.len().is_multiple_of(3) {
+            return Err(BrushError::InvalidIndexCount(indices.len()));
+        }
+
+        if let Some(index) = positions.iter().position(|v| !v.is_finite()) {
+            return Err(BrushError::NonFinitePosition(index));
+        }
+
+        if let Some(&index) = indices.iter().find(|&&v| v as usize >= positions.len()) {
+            return Err(BrushError::IndexOutOfBounds {
+                index,
+                len: positions.len(),
+            });
+        }
+
+        let mut brush = Self::from_indexed_mesh(positions, indices);
+        brush.faces.retain(|v| !v.is_degenerate(TOLERANCE));
+        brush.fix_winding();
+
+        Ok(brush)
+    }
+
+    /// Constructs a closed solid from a grid of heights.
+    ///
+    /// `heights` is laid out row by row with `width` samples along x and `depth` samples along z,
+    /// centered around the origin. The solid extends from the surface down to `base_y`.
+    pub fn from_heightfield(
+        heights: &[f32],
+        width: usize,
+        depth: usize,
+        cell_size: f32,
+        base_y: f32,
+    ) -> Result<Self, BrushError> {
+        if width < 2 || depth < 2 || heights.len() != width * depth {
+            return Err(BrushError::InvalidDimensions {
+                width,
+                depth,
+                len: heights.len(),
+            });
+        }
+
+        if !cell_size.is_finite() || cell_size <= 0.0 {
+            return Err(BrushError::InvalidCellSize(cell_size));
+        }
+
+        if let Some(index) = heights.iter().position(|v| !v.is_finite()) {
+            return Err(BrushError::NonFiniteHeight(index));
+        }
+
+        if !base_y.is_finite() || heights.iter().any(|&v| v <= base_y) {
+            return Err(BrushError::InvalidBase(base_y));
+        }
+
+        let offset = Vec3::new(
+            (width - 1) as f32 * cell_size * 0.5,
+            0.0,
+            (depth - 1) as f32 * cell_size * 0.5,
+        );
+
+        let point = |x: usize, z: usize| {
+            Vec3::new(
+                x as f32 * cell_size,
+                heights[z * width + x],
+                z as f32 * cell_size,
+            ) - offset
+        };
+
+        let base = |p: Vec3| Vec3::new(p.x, base_y, p.z);
+
+        let mut faces = Vec::new();
+
+        // Top surface, alternating the diagonal to avoid long slivers
+        for z in 0..depth - 1 {
+            for x in 0..width - 1 {
+                let a = point(x, z);
+                let b = point(x, z + 1);
+                let c = point(x + 1, z);
+                let d = point(x + 1, z + 1);
+
+                if (x + z) % 2 == 0 {
+                    faces.push(Face::triangle(a, b, c));
+                    faces.push(Face::triangle(c, b, d));
+                } else {
+                    faces.push(Face::triangle(a, b, d));
+                    faces.push(Face::triangle(a, d, c));
+                }
+            }
+        }
+
+        // Skirt walls
+        let mut push_wall = |p: Vec3, q: Vec3, outward: Vec3| {
+            let wall = Face::new([p, q, base(q), base(p)]);
+
+            if wall.normal().dot(outward) < 0.0 {
+                faces.push(wall.flip());
+            } else {
+                faces.push(wall);
+            }
+        };
+
+        for x in 0..width - 1 {
+            push_wall(point(x, 0), point(x + 1, 0), -Vec3::Z);
+            push_wall(point(x, depth - 1), point(x + 1, depth - 1), Vec3::Z);
+        }
+
+        for z in 0..depth - 1 {
+            push_wall(point(0, z), point(0, z + 1), -Vec3::X);
+            push_wall(point(width - 1, z), point(width - 1, z + 1), Vec3::X);
+        }
+
+        // Bottom cap, including the base of every wall to avoid t-junctions
+        let perimeter = (0..depth - 1)
+            .map(|z| (width - 1, z))
+            .chain((1..width).rev().map(|x| (x, depth - 1)))
+            .chain((1..depth).rev().map(|z| (0, z)))
+            .chain((0..width - 1).map(|x| (x, 0)));
+
+        faces.push(Face::new(perimeter.map(|(x, z)| base(point(x, z)))));
+
+        Ok(Self::new(faces))
+    }
+
     pub fn faces(&self) -> &[Face] {
         &self.faces
     }
 }
 
+/// An authoring error found by [`Brush::validate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum BrushDefect {
+    #[error(
+        "edge {edge} of face {face} is not shared with another face in the opposite direction"
+    )]
+    OpenEdge { face: usize, edge: usize },
+    #[error("face {face} points towards the center of the brush")]
+    InvertedFace { face: usize },
+    #[error("face {face} has no area")]
+    Degenerate { face: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum BrushError {
+    #[error("expected {width}x{depth} heights, found {len}")]
+    InvalidDimensions {
+        width: usize,
+        depth: usize,
+        len: usize,
+    },
+    #[error("invalid cell size {0}")]
+    InvalidCellSize(f32),
+    #[error("height at index {0} is not finite")]
+    NonFiniteHeight(usize),
+    #[error("base {0} must be finite and below all heights")]
+    InvalidBase(f32),
+    #[error("expected a whole number of triangles, found {0} indices")]
+    InvalidIndexCount(usize),
+    #[error("index {index} is out of bounds of {len} positions")]
+    IndexOutOfBounds { index: u32, len: usize },
+    #[error("position at index {0} is not finite")]
+    NonFinitePosition(usize),
+}
+
 #[cfg(test)]
 mod test {
-    use glam::vec3;
+    use std::{collections::HashMap, f32::consts::PI};
+
+    use glam::{vec3, Mat4, Vec3};
+    use itertools::Itertools;
 
-    use crate::tree::BspTree;
+    use crate::{
+        link::LinkKind,
+        navmesh::{Navmesh, NavmeshSettings},
+        plane::Plane,
+        tree::BspTree,
+        util::TOLERANCE,
+    };
 
-    use super::{Brush, Face};
+    use super::{Brush, BrushDefect, BrushError, Face, PositionedBrush};
 
     #[test]
     fn test_bsp() {
@@ -260,13 +1248,526 @@ mod test {
         let p4 = vec3(-1.0, 1.0, 1.0);
 
         let brush = Brush::new(vec![
-            Face::new(p1, p2, p4),
-            Face::new(p2, p3, p4),
-            Face::new(p3, p1, p4),
+            Face::triangle(p1, p2, p4),
+            Face::triangle(p2, p3, p4),
+            Face::triangle(p3, p1, p4),
         ]);
 
         let tree = BspTree::build(brush.faces());
 
         eprintln!("{tree:#?}");
     }
+
+    #[test]
+    fn heightfield_step() {
+        // Two flat terrains side by side, the right one raised by a step
+        let low = Brush::from_heightfield(&[0.0; 16], 4, 4, 1.0, -1.0).unwrap();
+        let high = Brush::from_heightfield(&[0.3; 16], 4, 4, 1.0, -1.0).unwrap();
+
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(
+            settings,
+            [
+                (Mat4::IDENTITY, low),
+                (Mat4::from_translation(vec3(3.0, 0.0, 0.0)), high),
+            ],
+        );
+
+        let (start, end) = (vec3(-1.0, 0.1, 0.0), vec3(4.0, 0.4, 0.0));
+        let low = navmesh.closest_polygon(start).unwrap();
+        let high = navmesh.closest_polygon(end).unwrap();
+        assert!((low.1.points()[0].y).abs() < 0.01);
+        assert!((high.1.points()[0].y - 0.3).abs() < 0.01);
+
+        // The polygons on either side of the seam are connected by stepping up
+        let below = navmesh.closest_polygon(vec3(1.4, 0.1, 0.0)).unwrap().0;
+        let above = navmesh.closest_polygon(vec3(1.6, 0.4, 0.0)).unwrap().0;
+        let step = navmesh
+            .links_between(below, above)
+            .map(|v| v.1)
+            .find(|v| matches!(v.kind(), LinkKind::StepUp(..)))
+            .unwrap();
+        assert!((step.source_edge().p1.y).abs() < 0.01);
+        assert!((step.destination_edge().p1.y - 0.3).abs() < 0.01);
+        assert!(step.source_edge().p1.x > 1.49 && step.source_edge().p2.x > 1.49);
+
+        let path = navmesh.find_path(start, end).unwrap();
+        assert_eq!(path.waypoints().last().unwrap().target_polygon(), high.0);
+    }
+
+    #[test]
+    fn heightfield_invalid() {
+        assert_eq!(
+            Brush::from_heightfield(&[0.0, f32::NAN, 0.0, 0.0], 2, 2, 1.0, -1.0).unwrap_err(),
+            BrushError::NonFiniteHeight(1)
+        );
+
+        assert!(Brush::from_heightfield(&[0.0; 3], 2, 2, 1.0, -1.0).is_err());
+        assert!(Brush::from_heightfield(&[0.0; 4], 2, 2, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn positioned_brush() {
+        let transform =
+            Mat4::from_translation(vec3(5.0, 1.0, 0.0)) * Mat4::from_scale(vec3(2.0, 0.5, 1.0));
+        let brush = PositionedBrush::from((transform, Brush::cube()));
+
+        let (min, max) = brush.aabb();
+        assert!(min.distance(vec3(3.0, 0.5, -1.0)) < 1e-5);
+        assert!(max.distance(vec3(7.0, 1.5, 1.0)) < 1e-5);
+
+        for (world, local) in brush.world_faces().zip(Brush::cube().faces()) {
+            for (&world, &local) in world.points().iter().zip(local.points()) {
+                assert!(world.distance(transform.transform_point3(local)) < 1e-5);
+            }
+        }
+
+        let moved = brush.transformed(Mat4::from_translation(vec3(0.0, 2.0, 0.0)));
+        assert!(moved.aabb().0.distance(vec3(3.0, 2.5, -1.0)) < 1e-5);
+
+        // Borrowed brushes are accepted by the navmesh as well
+        let cube = Brush::cube();
+        let navmesh = Navmesh::new(NavmeshSettings::default(), [(transform, &cube)]);
+        let expected = Navmesh::new(NavmeshSettings::default(), [brush]);
+        assert_eq!(navmesh.polygons().len(), expected.polygons().len());
+    }
+
+    #[test]
+    fn union_edge_adjacent() {
+        let a = BspTree::build(Brush::cube().faces()).unwrap();
+        let b = BspTree::build(
+            Brush::cube()
+                .with_transform(Mat4::from_translation(vec3(2.0, 2.0, 0.0)))
+                .faces(),
+        )
+        .unwrap();
+
+        let mut tree = a;
+        tree.union(b);
+
+        // The cubes only share an edge, so no surface area is removed
+        let area: f32 = tree.polygons().iter().map(|v| v.area()).sum();
+        assert!((area - 48.0).abs() < 0.01, "{area}");
+    }
+
+    #[test]
+    fn centroid() {
+        let triangle = Face::triangle(Vec3::ZERO, vec3(3.0, 0.0, 0.0), vec3(0.0, 0.0, -3.0));
+        assert!(triangle.centroid().distance(vec3(1.0, 0.0, -1.0)) < 1e-5);
+
+        // Not the average of the points, which is pulled towards the densely sampled side
+        let face = Face::new([
+            Vec3::ZERO,
+            vec3(1.0, 0.0, 0.0),
+            vec3(2.0, 0.0, 0.0),
+            vec3(2.0, 0.0, -2.0),
+            vec3(0.0, 0.0, -2.0),
+        ]);
+        assert!(face.centroid().distance(vec3(1.0, 0.0, -1.0)) < 1e-5);
+        assert!((face.area() - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn vertical_projection() {
+        // A ramp rising along x
+        let face = Face::new([
+            vec3(0.0, 0.0, 1.0),
+            vec3(2.0, 1.0, 1.0),
+            vec3(2.0, 1.0, -1.0),
+            vec3(0.0, 0.0, -1.0),
+        ]);
+
+        let projection = face.vertical_projection(vec3(1.0, 3.5, 0.0)).unwrap();
+        assert!((projection.height - 3.0).abs() < 1e-5);
+        assert_eq!(projection.horizontal_distance, 0.0);
+
+        // Inside the prism along the normal, but outside the footprint
+        let point = vec3(-0.1, 0.5, 0.0);
+        assert!(face.contains_point(point));
+        let projection = face.vertical_projection(point).unwrap();
+        assert!((projection.horizontal_distance - 0.1).abs() < 1e-5);
+
+        let projection = face
+            .flip()
+            .vertical_projection(vec3(1.0, -1.0, 0.0))
+            .unwrap();
+        assert!((projection.height + 1.5).abs() < 1e-5);
+        assert_eq!(projection.horizontal_distance, 0.0);
+
+        let wall = Face::new([Vec3::ZERO, Vec3::X, vec3(1.0, 1.0, 0.0), Vec3::Y]);
+        assert_eq!(wall.vertical_projection(Vec3::ZERO), None);
+    }
+
+    #[test]
+    fn split_polygon() {
+        let face = Face::new([
+            vec3(-1.0, 0.0, 1.0),
+            vec3(1.0, 0.0, 1.0),
+            vec3(1.0, 0.0, -1.0),
+            vec3(-1.0, 0.0, -1.0),
+        ]);
+
+        assert!((face.area() - 4.0).abs() < 1e-5);
+        assert!(face.normal().distance(Vec3::Y) < 1e-5);
+        assert!(face.contains_point(vec3(0.9, 0.0, -0.9)));
+        assert!(!face.contains_point(vec3(1.1, 0.0, 0.0)));
+        assert_eq!(face.triangulate().count(), 2);
+
+        let flipped = face.flip();
+        assert!(flipped.normal().distance(-Vec3::Y) < 1e-5);
+        assert!(flipped.contains_point(vec3(0.9, 0.0, -0.9)));
+        assert_eq!(flipped.edges().count(), 4);
+
+        // Cuts off a corner
+        let plane = Plane::new(vec3(1.0, 0.0, 1.0).normalize(), 0.5);
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        plane.split_face(&face, &mut front, &mut back);
+
+        assert_eq!(front.len(), 1);
+        assert_eq!(back.len(), 1);
+        assert_eq!(front[0].points().len(), 3);
+        assert_eq!(back[0].points().len(), 5);
+        assert!((front[0].area() + back[0].area() - 4.0).abs() < 1e-4);
+        assert!(front[0].normal().distance(Vec3::Y) < 1e-5);
+        assert!(back[0].normal().distance(Vec3::Y) < 1e-5);
+
+        // Brushes are built from polygons rather than triangles
+        let cube = Brush::cube();
+        assert_eq!(cube.faces().len(), 6);
+        assert!(cube.faces().iter().all(|v| v.points().len() == 4));
+    }
+
+    #[test]
+    fn cylinder_union() {
+        for brush in [
+            Brush::cylinder(0.5, 3.0, 12),
+            Brush::prism(3),
+            Brush::cone(1.0, 2.0, 12),
+        ] {
+            assert_eq!(brush.validate(), Ok(()));
+
+            // All faces point away from the center
+            for face in brush.faces() {
+                let center = face.points().iter().sum::<Vec3>() / face.points().len() as f32;
+                assert!(face.normal().dot(center) > 0.0);
+            }
+
+            let mut tree = BspTree::build(brush.faces()).unwrap();
+            tree.union(
+                BspTree::build(
+                    PositionedBrush::new(
+                        Mat4::from_translation(vec3(0.5, 0.5, 0.0)),
+                        Brush::cube(),
+                    )
+                    .world_faces()
+                    .collect::<Vec<_>>()
+                    .as_slice(),
+                )
+                .unwrap(),
+            );
+
+            let polygons = tree.polygons();
+            assert!(!polygons.is_empty());
+            assert!(polygons.iter().all(|v| v.normal().is_finite()));
+
+            // The area vectors of a closed surface cancel out, even across the T-junctions left
+            // by the splits
+            let area = polygons.iter().map(|v| v.normal() * v.area()).sum::<Vec3>();
+            assert!(area.length() < 1e-3, "{area}");
+        }
+    }
+
+    #[test]
+    fn cone_union() {
+        let segments = 16;
+        let cone = Brush::cone(1.0, 2.0, segments);
+        assert_eq!(cone.faces().len(), segments + 1);
+        assert!(cone.is_convex() && cone.is_closed());
+
+        let base = segments as f32 * 0.5 * (2.0 * PI / segments as f32).sin();
+        assert!((volume(&cone) - base * 2.0 / 3.0).abs() < 1e-4);
+
+        // A cube below the cone, overlapping the bottom quarter of it
+        let cube = Brush::cube().with_transform(Mat4::from_translation(vec3(0.0, -1.5, 0.0)));
+        let union = cone.union(&cube);
+        let overlap = base * 2.0 / 3.0 * (1.0 - 0.75f32.powi(3));
+        let expected = volume(&cone) + 8.0 - overlap;
+        assert!(
+            (volume(&union) - expected).abs() < 1e-3,
+            "{}",
+            volume(&union)
+        );
+    }
+
+    #[test]
+    fn uv_sphere_watertight() {
+        let sphere = Brush::uv_sphere(2.0, 16, 12);
+        assert_eq!(sphere.faces().len(), 16 * (12 - 1) * 2);
+
+        let quantize = |p: Vec3| (p / TOLERANCE).round().as_ivec3().to_array();
+
+        let mut edges = HashMap::new();
+        for face in sphere.faces() {
+            assert!(face.normal().dot(face.points()[0]) > 0.0);
+
+            for (a, b) in face.edges() {
+                *edges.entry((quantize(a), quantize(b))).or_insert(0) += 1;
+            }
+        }
+
+        // Every edge is shared with exactly one other face, in the opposite direction
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1);
+            assert_eq!(edges.get(&(b, a)), Some(&1));
+        }
+    }
+
+    #[test]
+    fn inflate() {
+        let cube = Brush::cube().with_transform(Mat4::from_rotation_x(0.3));
+        assert!(cube.is_closed() && cube.is_convex());
+
+        let inflated = cube.inflated(0.5).unwrap();
+        assert_eq!(inflated.faces().len(), 6);
+        assert!(inflated.is_closed() && inflated.is_convex());
+
+        let area: f32 = inflated.faces().iter().map(|v| v.area()).sum();
+        assert!((area - 6.0 * 9.0).abs() < 0.01, "{area}");
+
+        // Shrinking almost down to a point keeps every face
+        let cube = Brush::cube().with_transform(Mat4::from_scale(Vec3::splat(4.0)));
+        let shrunk = cube.inflated(-3.7).unwrap();
+        assert_eq!(shrunk.faces().len(), 6);
+        assert!(shrunk.is_closed() && shrunk.is_convex());
+
+        let area: f32 = shrunk.faces().iter().map(|v| v.area()).sum();
+        assert!((area - 6.0 * 0.36).abs() < 0.01, "{area}");
+
+        assert!(Brush::plane().inflated(0.5).is_none());
+
+        // A pit in the middle
+        let heights = [1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+        let terrain = Brush::from_heightfield(&heights, 3, 3, 1.0, -1.0).unwrap();
+        assert!(terrain.is_closed());
+        assert!(!terrain.is_convex());
+        assert!(terrain.inflated(0.5).is_none());
+    }
+
+    #[test]
+    fn validate_broken_cube() {
+        assert_eq!(Brush::cube().validate(), Ok(()));
+        assert_eq!(Brush::cylinder(1.0, 2.0, 12).validate(), Ok(()));
+
+        // The bottom face is missing, leaving the edges of the sides open
+        let mut faces = Brush::cube().faces().to_vec();
+        faces.remove(0);
+        let defects = Brush::new(faces).validate().unwrap_err();
+        assert_eq!(defects.len(), 4);
+        assert!(defects
+            .iter()
+            .all(|v| matches!(v, BrushDefect::OpenEdge { .. })));
+
+        // A side is wound the wrong way
+        let mut faces = Brush::cube().faces().to_vec();
+        faces[2] = faces[2].flip();
+        let mut brush = Brush::new(faces);
+
+        let defects = brush.validate().unwrap_err();
+        assert!(defects.contains(&BrushDefect::InvertedFace { face: 2 }));
+        assert!(defects.contains(&BrushDefect::OpenEdge { face: 2, edge: 0 }));
+
+        brush.fix_winding();
+        assert_eq!(brush.validate(), Ok(()));
+        assert_eq!(brush.faces()[2].normal(), Brush::cube().faces()[2].normal());
+
+        // A collapsed face
+        let mut faces = Brush::cube().faces().to_vec();
+        faces.push(Face::new([Vec3::ZERO, Vec3::X, Vec3::X * 2.0]));
+        let defects = Brush::new(faces).validate().unwrap_err();
+        assert_eq!(defects, [BrushDefect::Degenerate { face: 6 }]);
+    }
+
+    #[test]
+    fn convex_hull() {
+        let corners = Brush::cube()
+            .faces()
+            .iter()
+            .flat_map(|v| v.points().iter().copied())
+            .collect_vec();
+
+        // Duplicated corners and an interior point
+        let mut points = corners.clone();
+        points.push(vec3(0.2, -0.3, 0.5));
+
+        let hull = Brush::from_convex_hull(&points).unwrap();
+        assert_eq!(hull.canonical_key(), Brush::cube().canonical_key());
+        assert_eq!(hull.validate(), Ok(()));
+
+        let octahedron = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        let hull = Brush::from_convex_hull(&octahedron).unwrap();
+        assert_eq!(hull.faces().len(), 8);
+        assert!(hull.is_convex());
+        assert_eq!(hull.validate(), Ok(()));
+
+        let coplanar = [Vec3::ZERO, Vec3::X, Vec3::Z, vec3(1.0, 0.0, 1.0)];
+        assert!(Brush::from_convex_hull(&coplanar).is_none());
+        assert!(Brush::from_convex_hull(&[Vec3::ZERO, Vec3::X, Vec3::Y]).is_none());
+    }
+
+    #[test]
+    fn convex_hull_many_points() {
+        let mut seed = 0x1234_5678_u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+        };
+
+        // Points on a sphere, with more inside of it
+        let mut points = (0..300)
+            .map(|_| vec3(random(), random(), random()).normalize_or(Vec3::X) * 2.0)
+            .collect_vec();
+        points.extend((0..200).map(|_| vec3(random(), random(), random())));
+
+        let hull = Brush::from_convex_hull(&points).unwrap();
+        assert_eq!(hull.validate(), Ok(()));
+        assert!(hull.is_convex());
+        assert!(hull.faces().len() > 100);
+
+        // Every point is enclosed, and the hull is close to the sphere
+        for face in hull.faces() {
+            let plane = Plane::from_face(face);
+            assert!(points
+                .iter()
+                .all(|&p| plane.distance_to_point(p) <= TOLERANCE * 10.0));
+        }
+
+        let sphere = 4.0 / 3.0 * PI * 8.0;
+        assert!(volume(&hull) < sphere && volume(&hull) > sphere * 0.9);
+
+        // A cube with many points inside
+        let mut points = Brush::cube()
+            .faces()
+            .iter()
+            .flat_map(|v| v.points().iter().copied())
+            .collect_vec();
+        points.extend((0..300).map(|_| vec3(random(), random(), random()) * 0.99));
+
+        let hull = Brush::from_convex_hull(&points).unwrap();
+        assert_eq!(hull.canonical_key(), Brush::cube().canonical_key());
+    }
+
+    #[test]
+    fn indexed_mesh() {
+        let cube = Brush::cube();
+        let positions = cube.to_triangle_list();
+        let indices = (0..positions.len() as u32).collect_vec();
+
+        let brush = Brush::from_indexed_mesh(&positions, &indices);
+        assert_eq!(brush.faces().len(), 12);
+        assert_eq!(brush.validate(), Ok(()));
+        assert!(brush.is_convex());
+    }
+
+    #[test]
+    fn from_mesh() {
+        // A tessellated cube sharing its corners between triangles, as exported from glTF
+        let positions = (0..8)
+            .map(|v| vec3((v & 1) as f32, (v >> 1 & 1) as f32, (v >> 2 & 1) as f32) * 2.0 - 1.0)
+            .collect_vec();
+
+        let mut indices = vec![
+            0, 4, 6, 0, 6, 2, // -x
+            1, 3, 7, 1, 7, 5, // +x
+            0, 1, 5, 0, 5, 4, // -y
+            2, 6, 7, 2, 7, 3, // +y
+            0, 2, 3, 0, 3, 1, // -z
+            4, 5, 7, 4, 7, 6, // +z
+        ];
+
+        // An inverted triangle, and a degenerate one
+        indices.swap(0, 1);
+        indices.extend([0, 0, 7]);
+
+        let brush = Brush::from_mesh(&positions, &indices).unwrap();
+        assert_eq!(brush.faces().len(), 12);
+        assert_eq!(brush.validate(), Ok(()));
+        assert!(brush.is_closed());
+        assert!((volume(&brush) - 8.0).abs() < 1e-4);
+
+        // Unions like the equivalent primitive
+        let other = Brush::cube().with_transform(Mat4::from_translation(vec3(1.0, 0.0, 0.0)));
+        assert!((volume(&brush.union(&other)) - 12.0).abs() < 1e-3);
+
+        // Open meshes are accepted, but flagged
+        let open = Brush::from_mesh(&positions, &indices[3..]).unwrap();
+        assert!(!open.is_closed());
+
+        assert_eq!(
+            Brush::from_mesh(&positions, &[0, 1]).unwrap_err(),
+            BrushError::InvalidIndexCount(2)
+        );
+        assert_eq!(
+            Brush::from_mesh(&positions, &[0, 1, 8]).unwrap_err(),
+            BrushError::IndexOutOfBounds { index: 8, len: 8 }
+        );
+        assert_eq!(
+            Brush::from_mesh(&[Vec3::NAN], &[0, 0, 0]).unwrap_err(),
+            BrushError::NonFinitePosition(0)
+        );
+    }
+
+    /// Signed volume by the divergence theorem, which is only positive for outward winding
+    fn volume(brush: &Brush) -> f32 {
+        brush
+            .faces()
+            .iter()
+            .flat_map(|v| v.triangulate())
+            .map(|[a, b, c]| a.dot(b.cross(c)) / 6.0)
+            .sum()
+    }
+
+    #[test]
+    fn brush_booleans() {
+        let cube = Brush::cube();
+        let moved = Brush::cube().with_transform(Mat4::from_translation(vec3(1.0, 0.0, 0.0)));
+
+        let union = cube.union(&moved);
+        assert!((volume(&union) - 12.0).abs() < 1e-3, "{}", volume(&union));
+        assert!((6..=20).contains(&union.faces().len()));
+        assert!(union.faces().iter().all(|v| !v.is_degenerate(TOLERANCE)));
+
+        let intersection = cube.intersect(&moved);
+        assert!((volume(&intersection) - 4.0).abs() < 1e-3);
+        assert_eq!(intersection.canonical_key(), {
+            let expected = Brush::cube().with_transform(
+                Mat4::from_translation(vec3(0.5, 0.0, 0.0)) * Mat4::from_scale(vec3(0.5, 1.0, 1.0)),
+            );
+            expected.canonical_key()
+        });
+
+        let sphere = Brush::uv_sphere(0.5, 16, 8).with_transform(Mat4::from_translation(Vec3::X));
+        let carved = cube.subtract(&sphere);
+        let expected = 8.0 - volume(&sphere) * 0.5;
+        assert!(
+            (volume(&carved) - expected).abs() < 1e-3,
+            "{}",
+            volume(&carved)
+        );
+        assert!(carved.faces().len() > 6);
+
+        // Disjoint
+        let far = Brush::cube().with_transform(Mat4::from_translation(vec3(5.0, 0.0, 0.0)));
+        assert!(cube.intersect(&far).faces().is_empty());
+        assert_eq!(cube.subtract(&far).canonical_key(), cube.canonical_key());
+
+        let all = Brush::union_all([cube.clone(), moved, far]).unwrap();
+        assert!((volume(&all) - 20.0).abs() < 1e-3);
+        assert!(Brush::union_all([]).is_none());
+    }
 }
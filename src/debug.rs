@@ -0,0 +1,140 @@
+//! Plain data snapshots of a navmesh for drawing it with any renderer
+
+use glam::Vec3;
+
+use crate::{
+    link::LinkKind,
+    navmesh::{BoundaryKind, Navmesh},
+};
+
+/// The kind of link a debug line belongs to, without the link geometry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkKindTag {
+    Walk,
+    StepUp,
+    StepDown,
+    Drop,
+    OffMesh,
+}
+
+impl From<&LinkKind> for LinkKindTag {
+    fn from(kind: &LinkKind) -> Self {
+        match kind {
+            LinkKind::Walk(_) => Self::Walk,
+            LinkKind::StepUp(_, _) => Self::StepUp,
+            LinkKind::StepDown(_, _) => Self::StepDown,
+            LinkKind::Drop(_, _) => Self::Drop,
+            LinkKind::OffMesh { .. } => Self::OffMesh,
+        }
+    }
+}
+
+/// Geometry needed to visualize a navmesh, such as with debug gizmos.
+///
+/// Walk and step links are only included once, even though they are stored in both directions.
+/// Steps are drawn as [`LinkKindTag::StepUp`].
+#[derive(Debug, Clone, Default)]
+pub struct NavmeshDebugInfo {
+    /// Triangles of the walkable polygons, excluding polygons blocked by obstacles
+    pub triangles: Vec<[Vec3; 3]>,
+    /// The region of the polygon each triangle belongs to, for coloring disconnected parts
+    pub triangle_regions: Vec<usize>,
+    pub region_count: usize,
+    /// Lines outlining each link.
+    ///
+    /// Steps and drops are drawn as the two edges and the lines connecting their ends.
+    pub links: Vec<(Vec3, Vec3, LinkKindTag)>,
+    pub boundary: Vec<(Vec3, Vec3, BoundaryKind)>,
+    /// Polygon indices and the centroid to place their label at
+    pub labels: Vec<(usize, Vec3)>,
+}
+
+impl NavmeshDebugInfo {
+    pub fn capture(navmesh: &Navmesh) -> Self {
+        let mut info = Self {
+            region_count: navmesh.region_count(),
+            ..Default::default()
+        };
+
+        for (index, face) in navmesh.walkable_polygons() {
+            let region = navmesh.region(index).unwrap_or(0);
+            for triangle in face.triangulate() {
+                info.triangles.push(triangle);
+                info.triangle_regions.push(region);
+            }
+
+            info.labels.push((index, face.centroid()));
+        }
+
+        for (_, link) in navmesh.links() {
+            let tag = LinkKindTag::from(link.kind());
+            match *link.kind() {
+                LinkKind::Walk(edge) => {
+                    if link.from() < link.to() {
+                        info.links.push((edge.p1, edge.p2, tag));
+                    }
+                }
+                // Drawn through the step up it is the reverse of
+                LinkKind::StepDown(..) => {}
+                LinkKind::StepUp(a, b) | LinkKind::Drop(a, b) => {
+                    info.links.extend([
+                        (a.p1, a.p2, tag),
+                        (b.p1, b.p2, tag),
+                        (a.p1, b.p1, tag),
+                        (a.p2, b.p2, tag),
+                    ]);
+                }
+                LinkKind::OffMesh { from, to, .. } => info.links.push((from, to, tag)),
+            }
+        }
+
+        info.boundary = navmesh
+            .boundary_edges()
+            .into_iter()
+            .map(|v| (v.edge.p1, v.edge.p2, v.kind))
+            .collect();
+
+        info
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{navmesh::NavmeshSettings, test_util::slab};
+
+    use super::*;
+
+    #[test]
+    fn capture_step() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(settings, [slab(0.0, 0.0), slab(2.0, 0.3), slab(8.0, 0.0)]);
+
+        let info = NavmeshDebugInfo::capture(&navmesh);
+
+        assert_eq!(info.triangles.len(), info.triangle_regions.len());
+        assert_eq!(info.labels.len(), navmesh.walkable_polygons().count());
+        assert_eq!(info.region_count, 2);
+        assert!(info.triangle_regions.iter().all(|&v| v < info.region_count));
+
+        let area: f32 = info
+            .triangles
+            .iter()
+            .map(|[a, b, c]| (*b - *a).cross(*c - *a).length() * 0.5)
+            .sum();
+        assert!((area - navmesh.total_walkable_area()).abs() < 0.01);
+
+        // A single step, drawn as four lines
+        let steps = info
+            .links
+            .iter()
+            .filter(|v| v.2 == LinkKindTag::StepUp)
+            .count();
+        assert_eq!(steps, 4);
+
+        assert!(!info.boundary.is_empty());
+    }
+}
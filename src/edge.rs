@@ -1,5 +1,8 @@
 use glam::Vec3;
 
+use crate::util::TOLERANCE;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Edge3D {
     pub p1: Vec3,
@@ -11,33 +14,225 @@ impl Edge3D {
         Self { p1, p2 }
     }
 
-    pub fn intersect_ray(&self, ray_origin: Vec3, ray_direction: Vec3) -> (f32, Vec3) {
+    pub fn length(&self) -> f32 {
+        self.p1.distance(self.p2)
+    }
+
+    /// Returns the point on the edge segment closest to `point`
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
         let edge_dir = self.p2 - self.p1;
-        let length = (ray_origin - self.p1).dot(ray_direction) / (ray_direction.dot(edge_dir));
+        let length_squared = edge_dir.length_squared();
+        if length_squared < f32::EPSILON {
+            return self.p1;
+        }
 
-        (length, self.p1 * edge_dir * length)
+        let t = ((point - self.p1).dot(edge_dir) / length_squared).clamp(0.0, 1.0);
+        self.p1 + edge_dir * t
     }
 
-    pub fn intersect_ray_clipped(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<Vec3> {
+    pub fn midpoint(&self) -> Vec3 {
+        (self.p1 + self.p2) * 0.5
+    }
+
+    /// Returns the parameters of the closest points of the infinite lines through the ray and
+    /// the edge, as `(t_ray, t_edge)`.
+    ///
+    /// Returns `None` if the lines are parallel or degenerate.
+    fn closest_line_parameters(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<(f32, f32)> {
         let edge_dir = self.p2 - self.p1;
 
-        let normal = edge_dir.cross(Vec3::Y);
+        let a = ray_direction.dot(ray_direction);
+        let b = ray_direction.dot(edge_dir);
+        let c = edge_dir.dot(edge_dir);
 
-        let denom = ray_direction.dot(normal);
+        let denom = a * c - b * b;
+        if a < f32::EPSILON || c < f32::EPSILON || denom <= f32::EPSILON * a * c {
+            return None;
+        }
+
+        let w = ray_origin - self.p1;
+        let d = ray_direction.dot(w);
+        let e = edge_dir.dot(w);
+
+        Some(((b * e - c * d) / denom, (a * e - b * d) / denom))
+    }
+
+    /// Returns the closest points between the ray and the edge segment as
+    /// `(t_ray, t_edge, point_on_edge)`.
+    ///
+    /// `t_ray` is the non-negative parametric distance along the ray, and `t_edge` is in
+    /// `0..=1` from `p1` to `p2`. For a ray parallel to the edge the point closest to the ray
+    /// origin is returned.
+    ///
+    /// Returns `None` if the edge or ray is degenerate.
+    pub fn intersect_ray(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<(f32, f32, Vec3)> {
+        let edge_dir = self.p2 - self.p1;
 
-        if denom.abs() < f32::EPSILON {
+        let a = ray_direction.dot(ray_direction);
+        let c = edge_dir.dot(edge_dir);
+        if a < f32::EPSILON || c < f32::EPSILON {
             return None;
         }
 
-        let t = (self.p1 - ray_origin).dot(normal) / denom;
-        if t < 0.0 {
+        let project = |p: Vec3| ((p - self.p1).dot(edge_dir) / c).clamp(0.0, 1.0);
+
+        let s = match self.closest_line_parameters(ray_origin, ray_direction) {
+            Some((_, s)) => s.clamp(0.0, 1.0),
+            None => project(ray_origin),
+        };
+
+        let t = (self.p1 + edge_dir * s - ray_origin).dot(ray_direction) / a;
+
+        // The closest point of the line is behind the ray
+        let (t, s) = if t < 0.0 {
+            (0.0, project(ray_origin))
+        } else {
+            (t, s)
+        };
+
+        Some((t, s, self.p1 + edge_dir * s))
+    }
+
+    /// Returns the point on the edge where the ray crosses it, as seen from above.
+    ///
+    /// The crossing is clamped to the ends of the edge, so a ray passing beside the edge yields
+    /// the nearest end. This is what path smoothing wants when pulling a path around the corner
+    /// of a portal; use [`Self::intersect_ray_segment`] to only accept actual crossings.
+    ///
+    /// Returns `None` if the edge is vertical, or the ray is parallel to or points away from it.
+    pub fn intersect_ray_clipped(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<Vec3> {
+        let s = self.flat_crossing(ray_origin, ray_direction)?;
+        Some(self.p1 + (self.p2 - self.p1) * s.clamp(0.0, 1.0))
+    }
+
+    /// Returns the point on the edge where the ray crosses it, as seen from above.
+    ///
+    /// Like [`Self::intersect_ray_clipped`], but returns `None` if the ray passes beside either
+    /// end of the edge.
+    pub fn intersect_ray_segment(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<Vec3> {
+        let s = self.flat_crossing(ray_origin, ray_direction)?;
+
+        // Allow for rounding at the ends, measured along the edge
+        let tolerance = TOLERANCE / self.length().max(TOLERANCE);
+        if s < -tolerance || s > 1.0 + tolerance {
             return None;
         }
 
-        let intersection = ray_origin + t * ray_direction;
+        Some(self.p1 + (self.p2 - self.p1) * s.clamp(0.0, 1.0))
+    }
+
+    /// Returns the parameter along the edge of the horizontal crossing of the ray with the
+    /// infinite line through the edge
+    fn flat_crossing(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<f32> {
+        let flat = |p: Vec3| p * Vec3::new(1.0, 0.0, 1.0);
+
+        let (t, s) = Edge3D::new(flat(self.p1), flat(self.p2))
+            .closest_line_parameters(flat(ray_origin), flat(ray_direction))?;
+
+        (t >= 0.0).then_some(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{vec3, Vec3};
+
+    use super::Edge3D;
+
+    #[test]
+    fn intersect_ray_perpendicular() {
+        let edge = Edge3D::new(vec3(-1.0, 0.0, 2.0), vec3(1.0, 0.0, 2.0));
+        let (t, s, p) = edge.intersect_ray(Vec3::ZERO, Vec3::Z).unwrap();
+
+        assert!((t - 2.0).abs() < 1e-5);
+        assert!((s - 0.5).abs() < 1e-5);
+        assert!(p.distance(vec3(0.0, 0.0, 2.0)) < 1e-5);
+        assert_eq!(edge.midpoint(), p);
+    }
+
+    #[test]
+    fn intersect_ray_skew() {
+        let edge = Edge3D::new(vec3(-1.0, 1.0, 2.0), vec3(1.0, 1.0, 2.0));
+        let (t, s, p) = edge.intersect_ray(vec3(0.5, 0.0, 0.0), Vec3::Z).unwrap();
+
+        assert!((t - 2.0).abs() < 1e-5);
+        assert!((s - 0.75).abs() < 1e-5);
+        assert!(p.distance(vec3(0.5, 1.0, 2.0)) < 1e-5);
+
+        // Closest point lies beyond the end of the segment
+        let (t, s, p) = edge.intersect_ray(vec3(3.0, 0.0, 0.0), Vec3::Z).unwrap();
+        assert!((t - 2.0).abs() < 1e-5);
+        assert_eq!(s, 1.0);
+        assert!(p.distance(edge.p2) < 1e-5);
+
+        // Closest point of the line lies behind the ray
+        let (t, _, p) = edge.intersect_ray(vec3(0.5, 0.0, 3.0), Vec3::Z).unwrap();
+        assert_eq!(t, 0.0);
+        assert!(p.distance(vec3(0.5, 1.0, 2.0)) < 1e-5);
+    }
+
+    #[test]
+    fn intersect_ray_endpoint() {
+        let edge = Edge3D::new(vec3(-1.0, 0.0, 2.0), vec3(1.0, 0.0, 2.0));
+        let (t, s, p) = edge.intersect_ray(vec3(1.0, 0.0, 0.0), Vec3::Z).unwrap();
+
+        assert!((t - 2.0).abs() < 1e-5);
+        assert!((s - 1.0).abs() < 1e-5);
+        assert!(p.distance(edge.p2) < 1e-5);
+    }
+
+    #[test]
+    fn intersect_ray_parallel() {
+        let edge = Edge3D::new(vec3(-1.0, 0.0, 2.0), vec3(1.0, 0.0, 2.0));
+        let (t, s, p) = edge.intersect_ray(Vec3::ZERO, Vec3::X).unwrap();
+
+        assert_eq!(t, 0.0);
+        assert!((s - 0.5).abs() < 1e-5);
+        assert!(p.distance(vec3(0.0, 0.0, 2.0)) < 1e-5);
+
+        assert_eq!(edge.intersect_ray(Vec3::ZERO, Vec3::ZERO), None);
+        assert_eq!(
+            Edge3D::new(Vec3::X, Vec3::X).intersect_ray(Vec3::ZERO, Vec3::Z),
+            None
+        );
+    }
+
+    #[test]
+    fn intersect_ray_clipped_vertical() {
+        let edge = Edge3D::new(vec3(0.0, 0.0, 2.0), vec3(0.0, 1.0, 2.0));
+        assert_eq!(edge.intersect_ray_clipped(Vec3::ZERO, Vec3::Z), None);
+
+        let edge = Edge3D::new(vec3(-1.0, 0.0, 2.0), vec3(1.0, 0.0, 2.0));
+        let p = edge.intersect_ray_clipped(Vec3::ZERO, Vec3::Z).unwrap();
+        assert!(p.distance(vec3(0.0, 0.0, 2.0)) < 1e-5);
+    }
+
+    #[test]
+    fn intersect_ray_segment() {
+        let edge = Edge3D::new(vec3(-1.0, 0.0, 2.0), vec3(1.0, 0.5, 2.0));
+
+        // Heights are ignored, and the crossing lies on the edge
+        let p = edge
+            .intersect_ray_segment(vec3(0.5, 3.0, 0.0), Vec3::Z)
+            .unwrap();
+        assert!(p.distance(vec3(0.5, 0.375, 2.0)) < 1e-5);
+
+        // Through the end of the edge
+        let p = edge
+            .intersect_ray_segment(vec3(1.0, 0.0, 0.0), Vec3::Z)
+            .unwrap();
+        assert!(p.distance(edge.p2) < 1e-5);
 
-        let edge_coord = (intersection - self.p1).dot(edge_dir) / edge_dir.dot(edge_dir);
+        // Beside the edge, where the clamped crossing bends towards the end instead
+        let origin = vec3(3.0, 0.0, 0.0);
+        assert_eq!(edge.intersect_ray_segment(origin, Vec3::Z), None);
+        let p = edge.intersect_ray_clipped(origin, Vec3::Z).unwrap();
+        assert!(p.distance(edge.p2) < 1e-5);
 
-        Some(edge_coord.clamp(0.0, 1.0) * edge_dir + self.p1)
+        // Parallel, pointing away, and vertical
+        assert_eq!(edge.intersect_ray_segment(Vec3::ZERO, Vec3::X), None);
+        assert_eq!(edge.intersect_ray_segment(Vec3::ZERO, -Vec3::Z), None);
+        let vertical = Edge3D::new(vec3(0.0, 0.0, 2.0), vec3(0.0, 1.0, 2.0));
+        assert_eq!(vertical.intersect_ray_segment(Vec3::ZERO, Vec3::Z), None);
     }
 }
@@ -1,8 +1,8 @@
 use glam::{vec2, vec3, Vec2, Vec3};
 
-use crate::{span::Span, util::TOLERANCE};
+use crate::{edge::Edge3D, span::Span};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PolygonEdge {
     p1: Vec3,
     p2: Vec3,
@@ -22,6 +22,10 @@ impl PolygonEdge {
         VerticalPlane::new(normal, normal.dot(self.p1))
     }
 
+    pub fn edge(&self) -> Edge3D {
+        Edge3D::new(self.p1, self.p2)
+    }
+
     pub fn polygon(&self) -> usize {
         self.polygon
     }
@@ -70,19 +74,21 @@ impl VerticalPlane {
         Span::new(e1.x.min(e2.x), e1.x.max(e2.x))
     }
 
-    pub fn canonicalize(&self) -> Self {
+    /// Flips the plane to a canonical orientation, snapping normal components within `epsilon`
+    /// of zero
+    pub fn canonicalize(&self, epsilon: f32) -> Self {
         let mut x = self.normal.x;
         let mut y = self.normal.y;
         let mut z = self.normal.z;
         let d = self.distance;
 
-        if x.abs() < TOLERANCE {
+        if x.abs() < epsilon {
             x = 0.0
         }
-        if y.abs() < TOLERANCE {
+        if y.abs() < epsilon {
             y = 0.0
         }
-        if z.abs() < TOLERANCE {
+        if z.abs() < epsilon {
             z = 0.0
         }
 
@@ -0,0 +1,342 @@
+//! Wavefront OBJ export for inspecting brushes and navmeshes in external tools
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use glam::Vec3;
+
+use crate::{
+    brush::{Brush, Face},
+    link::LinkKind,
+    navmesh::Navmesh,
+    util::TOLERANCE,
+};
+
+/// Writes vertices on demand, welding points which snap to the same point on the tolerance grid
+#[derive(Default)]
+struct Vertices {
+    indices: BTreeMap<[i32; 3], usize>,
+}
+
+impl Vertices {
+    /// Returns the one-based OBJ index of the point, writing it if it has not been seen before
+    fn index(&mut self, writer: &mut impl Write, p: Vec3) -> io::Result<usize> {
+        let key = (p / TOLERANCE).round().as_ivec3().to_array();
+        if let Some(&index) = self.indices.get(&key) {
+            return Ok(index);
+        }
+
+        writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+        let index = self.indices.len() + 1;
+        self.indices.insert(key, index);
+        Ok(index)
+    }
+
+    /// Writes the polygon as a fan of triangles.
+    ///
+    /// Triangles which collapse when welded are skipped.
+    fn write_face(&mut self, writer: &mut impl Write, face: &Face) -> io::Result<()> {
+        for [a, b, c] in face.triangulate() {
+            let a = self.index(writer, a)?;
+            let b = self.index(writer, b)?;
+            let c = self.index(writer, c)?;
+
+            if a != b && b != c && c != a {
+                writeln!(writer, "f {a} {b} {c}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the faces as a single OBJ object, welding vertices within [`TOLERANCE`]
+pub fn export_obj(faces: impl IntoIterator<Item = Face>, mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "o faces")?;
+
+    let mut vertices = Vertices::default();
+    for face in faces {
+        vertices.write_face(&mut writer, &face)?;
+    }
+
+    Ok(())
+}
+
+fn link_kind_name(kind: &LinkKind) -> &'static str {
+    match kind {
+        LinkKind::Walk(_) => "walk",
+        LinkKind::StepUp(_, _) => "step_up",
+        LinkKind::StepDown(_, _) => "step_down",
+        LinkKind::Drop(_, _) => "drop",
+        LinkKind::OffMesh { .. } => "offmesh",
+    }
+}
+
+impl Brush {
+    /// Writes the brush faces as a single OBJ object
+    pub fn export_obj(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "o brush")?;
+
+        let mut vertices = Vertices::default();
+        for (index, face) in self.faces().iter().enumerate() {
+            writeln!(writer, "g face_{index}")?;
+            vertices.write_face(&mut writer, face)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Navmesh {
+    /// Writes the walkable polygons and links as OBJ objects.
+    ///
+    /// Polygons are triangulated and share welded vertices. Links are written as line elements
+    /// along their source edge in a separate object.
+    ///
+    /// Each polygon and link is written to a group named after its slab key, such as
+    /// `polygon_12` or `link_4_step_up`.
+    pub fn export_obj(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "o walkable")?;
+
+        let mut vertices = Vertices::default();
+        for (index, face) in self.walkable_polygons() {
+            writeln!(writer, "g polygon_{index}")?;
+            vertices.write_face(&mut writer, face)?;
+        }
+
+        writeln!(writer, "o links")?;
+        for (index, link) in self.links() {
+            let kind = link_kind_name(link.kind());
+            writeln!(
+                writer,
+                "g link_{index}_{kind}_{}_{}",
+                link.from(),
+                link.to()
+            )?;
+
+            let edge = link.source_edge();
+            let a = vertices.index(&mut writer, edge.p1)?;
+            let b = vertices.index(&mut writer, edge.p2)?;
+            writeln!(writer, "l {a} {b}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the walkable polygons, the non-walkable brush polygons and the links as separate
+    /// OBJ objects, so that each can be toggled when inspecting the navmesh.
+    ///
+    /// Links are written as line elements along their source edge, with one object per kind of
+    /// link, such as `links_walk` or `links_step_up`.
+    pub fn export_debug_obj(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut vertices = Vertices::default();
+
+        writeln!(writer, "o walkable")?;
+        for (_, face) in self.walkable_polygons() {
+            vertices.write_face(&mut writer, face)?;
+        }
+
+        writeln!(writer, "o brushes")?;
+        for face in self.brush_polygons() {
+            vertices.write_face(&mut writer, face)?;
+        }
+
+        let mut kinds: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+        for (_, link) in self.links() {
+            kinds
+                .entry(link_kind_name(link.kind()))
+                .or_default()
+                .push(link.source_edge());
+        }
+
+        for (kind, edges) in kinds {
+            writeln!(writer, "o links_{kind}")?;
+            for edge in edges {
+                let a = vertices.index(&mut writer, edge.p1)?;
+                let b = vertices.index(&mut writer, edge.p2)?;
+                writeln!(writer, "l {a} {b}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4, Vec3};
+
+    use crate::{
+        brush::{Brush, Face, PositionedBrush},
+        navmesh::{Navmesh, NavmeshSettings},
+    };
+
+    use super::export_obj;
+
+    fn count_prefix(obj: &str, prefix: &str) -> usize {
+        obj.lines().filter(|v| v.starts_with(prefix)).count()
+    }
+
+    /// Checks that all face and line indices refer to written vertices
+    fn assert_indices(obj: &str) {
+        let vertex_count = count_prefix(obj, "v ");
+        for line in obj
+            .lines()
+            .filter(|v| v.starts_with("f ") || v.starts_with("l "))
+        {
+            for index in line.split_whitespace().skip(1) {
+                let index: usize = index.parse().unwrap();
+                assert!(index >= 1 && index <= vertex_count);
+            }
+        }
+    }
+
+    #[test]
+    fn export_floor() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(
+            settings,
+            [PositionedBrush::new(
+                Mat4::from_scale(vec3(4.0, 0.2, 4.0)),
+                Arc::new(Brush::cube()),
+            )],
+        );
+
+        let mut output = Vec::new();
+        navmesh.export_obj(&mut output).unwrap();
+        let obj = String::from_utf8(output).unwrap();
+
+        assert_eq!(count_prefix(&obj, "v "), 4);
+        assert_eq!(count_prefix(&obj, "f "), 2);
+        assert_eq!(count_prefix(&obj, "l "), 0);
+    }
+
+    #[test]
+    fn export_navmesh() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [
+                PositionedBrush::new(
+                    Mat4::from_scale(vec3(10.0, 0.4, 10.0)),
+                    Arc::new(Brush::cube()),
+                ),
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(0.0, 0.5, 0.0)),
+                    Arc::new(Brush::cube()),
+                ),
+            ],
+        );
+
+        let mut output = Vec::new();
+        navmesh.export_obj(&mut output).unwrap();
+        let obj = String::from_utf8(output).unwrap();
+
+        let polygons = navmesh.polygons().len();
+        let links = navmesh.links().len();
+        assert!(polygons > 0);
+        assert!(links > 0);
+
+        let triangles: usize = navmesh
+            .polygons()
+            .iter()
+            .map(|v| v.1.points().len() - 2)
+            .sum();
+
+        // Adjacent polygons share vertices
+        let polygon_vertices: usize = navmesh.polygons().iter().map(|v| v.1.points().len()).sum();
+        assert!(count_prefix(&obj, "v ") < polygon_vertices);
+
+        assert_eq!(count_prefix(&obj, "f "), triangles);
+        assert_eq!(count_prefix(&obj, "l "), links);
+        assert_eq!(count_prefix(&obj, "g polygon_"), polygons);
+        assert_indices(&obj);
+    }
+
+    #[test]
+    fn export_brush() {
+        let mut output = Vec::new();
+        Brush::cube().export_obj(&mut output).unwrap();
+        let obj = String::from_utf8(output).unwrap();
+
+        assert_eq!(count_prefix(&obj, "v "), 8);
+        assert_eq!(count_prefix(&obj, "f "), 12);
+        assert_indices(&obj);
+    }
+
+    #[test]
+    fn export_faces() {
+        // Two triangles of a quad sharing an edge
+        let faces = [
+            Face::triangle(Vec3::ZERO, Vec3::Z, Vec3::X),
+            Face::triangle(Vec3::X, Vec3::Z, vec3(1.0, 0.0, 1.0)),
+        ];
+
+        let mut output = Vec::new();
+        export_obj(faces, &mut output).unwrap();
+        let obj = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            obj,
+            "o faces\nv 0 0 0\nv 0 0 1\nv 1 0 0\nf 1 2 3\nv 1 0 1\nf 3 2 4\n"
+        );
+    }
+
+    #[test]
+    fn export_debug() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        // A floor with a step onto a smaller platform
+        let navmesh = Navmesh::new(
+            settings,
+            [
+                PositionedBrush::new(
+                    Mat4::from_scale(vec3(2.0, 0.2, 1.0)),
+                    Arc::new(Brush::cube()),
+                ),
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(3.0, 0.3, 0.0))
+                        * Mat4::from_scale(vec3(1.0, 0.2, 1.0)),
+                    Arc::new(Brush::cube()),
+                ),
+            ],
+        );
+
+        let mut output = Vec::new();
+        navmesh.export_debug_obj(&mut output).unwrap();
+        let obj = String::from_utf8(output).unwrap();
+
+        let objects = obj
+            .lines()
+            .filter_map(|v| v.strip_prefix("o "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            objects,
+            ["walkable", "brushes", "links_step_down", "links_step_up"]
+        );
+
+        let brush_triangles: usize = navmesh
+            .brush_polygons()
+            .iter()
+            .map(|v| v.points().len() - 2)
+            .sum();
+        let triangles: usize = navmesh
+            .polygons()
+            .iter()
+            .map(|v| v.1.points().len() - 2)
+            .sum();
+
+        assert_eq!(count_prefix(&obj, "f "), triangles + brush_triangles);
+        assert_eq!(count_prefix(&obj, "l "), navmesh.links().len());
+        assert_indices(&obj);
+    }
+}
@@ -0,0 +1,424 @@
+use glam::Vec3;
+use itertools::Itertools;
+
+use crate::{astar::Waypoint, link::LinkKind, navmesh::Navmesh};
+
+/// Moves an agent along a path of waypoints
+#[derive(Debug, Clone)]
+pub struct PathFollower {
+    waypoints: Vec<Waypoint>,
+    /// Index of the next waypoint to reach
+    cursor: usize,
+    arrival_distance: f32,
+    max_deviation: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FollowResult {
+    /// The point the agent should move to
+    pub target: Vec3,
+    /// The target projected down onto the polygon it is standing on, or `None` if it is off the
+    /// navmesh
+    pub surface: Option<Vec3>,
+    /// Distance left to walk along the path from the target
+    pub remaining_distance: f32,
+    /// The last link crossed during this step, such as a step up to play a climbing animation
+    pub link: Option<LinkKind>,
+    /// At least one waypoint was reached during this step
+    pub passed_waypoint: bool,
+    /// The agent has strayed from the path and should re-plan
+    pub deviated: bool,
+    /// The final waypoint has been reached
+    pub finished: bool,
+}
+
+impl PathFollower {
+    pub fn new(waypoints: impl Into<Vec<Waypoint>>) -> Self {
+        Self {
+            waypoints: waypoints.into(),
+            cursor: 0,
+            arrival_distance: 0.05,
+            max_deviation: 0.5,
+        }
+    }
+
+    /// Set the distance at which a waypoint is considered reached
+    pub fn with_arrival_distance(mut self, arrival_distance: f32) -> Self {
+        self.arrival_distance = arrival_distance;
+        self
+    }
+
+    /// Set the distance from the path at which the agent is considered to have deviated
+    pub fn with_max_deviation(mut self, max_deviation: f32) -> Self {
+        self.max_deviation = max_deviation;
+        self
+    }
+
+    pub fn waypoints(&self) -> &[Waypoint] {
+        &self.waypoints
+    }
+
+    /// Returns the next waypoint to reach
+    pub fn current_waypoint(&self) -> Option<&Waypoint> {
+        self.waypoints.get(self.cursor)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.waypoints.len()
+    }
+
+    /// Returns true if the end of the path is within `tolerance` along the path
+    pub fn is_finished_at(&self, current_position: Vec3, tolerance: f32) -> bool {
+        self.remaining_distance(current_position) <= tolerance
+    }
+
+    /// Returns the distance left to walk along the remaining waypoints
+    pub fn remaining_distance(&self, current_position: Vec3) -> f32 {
+        let Some(next) = self.current_waypoint() else {
+            return 0.0;
+        };
+
+        let rest: f32 = self.waypoints[self.cursor..]
+            .iter()
+            .tuple_windows()
+            .map(|(a, b)| a.point().distance(b.point()))
+            .sum();
+
+        current_position.distance(next.point()) + rest
+    }
+
+    /// Returns the point to steer towards from `current_position`.
+    ///
+    /// The furthest waypoint within `lookahead` which can be walked to in a straight line on the
+    /// navmesh is chosen, so that corners are only cut while the agent stays on the mesh.
+    /// Waypoints after drops and off-mesh links are never skipped. If the agent is not on the
+    /// navmesh the next waypoint is returned.
+    ///
+    /// Returns `None` once the final waypoint is reached.
+    pub fn target(
+        &mut self,
+        navmesh: &Navmesh,
+        current_position: Vec3,
+        lookahead: f32,
+    ) -> Option<Vec3> {
+        while self
+            .current_waypoint()
+            .is_some_and(|v| v.point().distance(current_position) <= self.arrival_distance)
+        {
+            self.cursor += 1;
+        }
+
+        self.current_waypoint()?;
+
+        let on_mesh = navmesh.closest_polygon(current_position).is_some();
+        if on_mesh {
+            for (index, waypoint) in self.waypoints.iter().enumerate().skip(self.cursor + 1) {
+                let skippable = !matches!(
+                    waypoint.link_kind(),
+                    Some(LinkKind::Drop(..) | LinkKind::OffMesh { .. })
+                );
+
+                if !skippable
+                    || waypoint.point().distance(current_position) > lookahead
+                    || !navmesh
+                        .raycast(current_position, waypoint.point())
+                        .is_reached()
+                {
+                    break;
+                }
+
+                self.cursor = index;
+            }
+        }
+
+        self.current_waypoint().map(|v| v.point())
+    }
+
+    /// Moves at most `max_distance` along the path from `current_position`.
+    ///
+    /// If the agent was pushed further along the path, the waypoints it has been pushed past
+    /// are skipped.
+    pub fn advance(
+        &mut self,
+        navmesh: &Navmesh,
+        current_position: Vec3,
+        max_distance: f32,
+    ) -> FollowResult {
+        self.resync(current_position);
+        let deviated = !self.is_finished() && self.has_deviated(navmesh, current_position);
+
+        let mut position = current_position;
+        let mut remaining = max_distance;
+        let mut passed_waypoint = false;
+        let mut link = None;
+
+        while let Some(waypoint) = self.waypoints.get(self.cursor) {
+            let to_target = waypoint.point() - position;
+            let distance = to_target.length();
+
+            if distance <= remaining + self.arrival_distance {
+                position = waypoint.point();
+                remaining = (remaining - distance).max(0.0);
+                passed_waypoint = true;
+                link = waypoint.link_kind().or(link);
+                self.cursor += 1;
+            } else {
+                position += to_target / distance * remaining;
+                break;
+            }
+        }
+
+        FollowResult {
+            target: position,
+            surface: surface_point(navmesh, position),
+            remaining_distance: self.remaining_distance(position),
+            link,
+            passed_waypoint,
+            deviated,
+            finished: self.is_finished(),
+        }
+    }
+
+    /// Moves the cursor to the closest segment ahead of the current one within `max_deviation`
+    fn resync(&mut self, position: Vec3) {
+        let distance = |index: usize| {
+            let to = self.waypoints[index].point();
+            let from = index
+                .checked_sub(1)
+                .map_or(to, |v| self.waypoints[v].point());
+            distance_to_segment(position, from, to)
+        };
+
+        if self.is_finished() {
+            return;
+        }
+
+        let current = distance(self.cursor);
+        let closest = (self.cursor + 1..self.waypoints.len())
+            .map(|v| (v, distance(v)))
+            .filter(|v| v.1 <= self.max_deviation)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        // Segments meet at the waypoints, so only skip ahead if clearly closer
+        if let Some((index, distance)) = closest {
+            if distance + self.arrival_distance < current {
+                self.cursor = index;
+            }
+        }
+    }
+
+    fn has_deviated(&self, navmesh: &Navmesh, position: Vec3) -> bool {
+        let next = &self.waypoints[self.cursor];
+        let prev = self.cursor.checked_sub(1).map(|i| &self.waypoints[i]);
+
+        let from = prev.map(|v| v.point()).unwrap_or(next.point());
+        if distance_to_segment(position, from, next.point()) > self.max_deviation {
+            return true;
+        }
+
+        // The agent must be on one of the polygons of the current segment, or on the
+        // boundary of the polygon it arrived from
+        let expected = &self.waypoints[self.cursor.saturating_sub(2)..=self.cursor];
+        match navmesh.closest_polygon(position) {
+            Some((polygon, ..)) => expected.iter().all(|v| v.target_polygon() != polygon),
+            None => true,
+        }
+    }
+}
+
+/// Projects the point down onto the polygon it is standing on, or onto the nearest point of the
+/// nearest polygon if it is off the navmesh
+fn surface_point(navmesh: &Navmesh, point: Vec3) -> Option<Vec3> {
+    let (_, face, _) = navmesh.closest_polygon(point)?;
+    match face.vertical_projection(point) {
+        Some(v) if v.horizontal_distance <= navmesh.settings().epsilon => {
+            Some(point - Vec3::Y * v.height)
+        }
+        _ => Some(face.closest_point(point)),
+    }
+}
+
+fn distance_to_segment(point: Vec3, a: Vec3, b: Vec3) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > f32::EPSILON {
+        ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    point.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4, Vec3Swizzles};
+
+    use crate::{
+        brush::{Brush, PositionedBrush},
+        navmesh::NavmeshSettings,
+        test_util::slab,
+    };
+
+    use super::*;
+
+    fn navmesh() -> Navmesh {
+        Navmesh::new(
+            NavmeshSettings::default(),
+            [
+                PositionedBrush::new(
+                    Mat4::from_scale(vec3(10.0, 0.4, 10.0)),
+                    Arc::new(Brush::cube()),
+                ),
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(0.0, 0.5, 0.0)),
+                    Arc::new(Brush::cube()),
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn follow_path() {
+        let navmesh = navmesh();
+        let start = vec3(-5.0, 0.5, -5.0);
+        let end = vec3(5.0, 0.5, 5.0);
+
+        let path = navmesh.find_path(start, end).unwrap();
+        let waypoint_count = path.len();
+        let mut follower = PathFollower::new(path);
+
+        let mut position = start;
+        let mut passed = 0;
+        for _ in 0..1000 {
+            let result = follower.advance(&navmesh, position, 0.25);
+            assert!(!result.deviated);
+            assert!(result.target.distance(position) <= 0.25 + 0.05 + 1e-4);
+
+            position = result.target;
+            passed += result.passed_waypoint as usize;
+
+            if result.finished {
+                break;
+            }
+        }
+
+        assert!(follower.is_finished());
+        assert!(position.distance(end) < 1e-4);
+        assert!(passed > 0 && passed <= waypoint_count);
+    }
+
+    #[test]
+    fn follow_deviation() {
+        let navmesh = navmesh();
+        let start = vec3(-5.0, 0.5, -5.0);
+        let end = vec3(5.0, 0.5, 5.0);
+
+        let mut follower = PathFollower::new(navmesh.find_path(start, end).unwrap());
+
+        let result = follower.advance(&navmesh, start, 0.1);
+        assert!(!result.deviated);
+
+        // Pushed far away from the path
+        let result = follower.advance(&navmesh, vec3(5.0, 0.5, -5.0), 0.1);
+        assert!(result.deviated);
+        assert!(!result.finished);
+    }
+
+    #[test]
+    fn follow_lookahead() {
+        let navmesh = navmesh();
+        let radius = navmesh.settings().agent_radius;
+        let start = vec3(-5.0, 0.5, -5.0);
+        let end = vec3(5.0, 0.5, 5.0);
+
+        let path = navmesh.find_path(start, end).unwrap();
+        let length = path.length();
+        let mut follower = PathFollower::new(path);
+        assert!((follower.remaining_distance(start) - length).abs() < 1e-3);
+
+        const SPEED: f32 = 0.1;
+        let mut position = start;
+        let mut travelled = 0.0;
+        for _ in 0..1000 {
+            let Some(target) = follower.target(&navmesh, position, 4.0) else {
+                break;
+            };
+
+            let step = (target - position).clamp_length_max(SPEED);
+            position += step;
+            travelled += step.length();
+
+            let (_, _, distance) = navmesh.closest_polygon(position).unwrap();
+            assert!(
+                distance <= radius,
+                "{position} is {distance} from the navmesh"
+            );
+        }
+
+        assert!(follower.is_finished());
+        assert!(follower.is_finished_at(position, 0.05));
+        assert!(position.distance(end) <= 0.05);
+        assert!(travelled <= length + 0.1, "{travelled} > {length}");
+    }
+
+    #[test]
+    fn follow_step() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3)],
+        );
+        let top = 0.2 + navmesh.settings().agent_radius;
+        let start = vec3(-2.0, top, 0.0);
+        let end = vec3(0.0, top + 0.3, 0.0);
+
+        let mut follower = PathFollower::new(navmesh.find_path(start, end).unwrap());
+
+        let mut position = start;
+        let mut remaining = follower.remaining_distance(start);
+        let mut stepped = false;
+        for _ in 0..100 {
+            let result = follower.advance(&navmesh, position, 0.1);
+            stepped |= matches!(result.link, Some(LinkKind::StepUp(..)));
+
+            // The target is interpolated across the step, while the surface point is kept on the
+            // polygons below it
+            let surface = result.surface.unwrap();
+            let offset = surface - result.target;
+            assert!(offset.xz().length() < 1e-3, "{surface} {}", result.target);
+            assert!(offset.y.abs() <= 0.3 + 1e-3, "{surface} {}", result.target);
+
+            assert!(result.remaining_distance <= remaining + 1e-4);
+            remaining = result.remaining_distance;
+            position = result.target;
+
+            if result.finished {
+                break;
+            }
+        }
+
+        assert!(stepped);
+        assert!(follower.is_finished());
+        assert_eq!(remaining, 0.0);
+    }
+
+    #[test]
+    fn follow_pushed_ahead() {
+        let navmesh = navmesh();
+        let start = vec3(-5.0, 0.5, -5.0);
+        let end = vec3(5.0, 0.5, 5.0);
+
+        let path = navmesh.find_path(start, end).unwrap();
+        assert!(path.len() > 2, "{path:?}");
+        let last = path.waypoints()[path.len() - 2].point();
+
+        // Pushed onto the last segment, past the waypoints in between
+        let mut follower = PathFollower::new(path);
+        let pushed = last.lerp(end, 0.5);
+        let result = follower.advance(&navmesh, pushed, 0.1);
+
+        assert!(!result.deviated);
+        assert!((result.remaining_distance - (pushed.distance(end) - 0.1)).abs() < 1e-3);
+    }
+}
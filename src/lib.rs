@@ -1,11 +1,18 @@
 pub mod astar;
 pub mod brush;
+pub mod debug;
 pub mod edge;
 pub mod edgelist;
+#[cfg(feature = "io")]
+pub mod export;
+pub mod follow;
 pub mod link;
 pub mod navmesh;
+pub mod obstacle;
 pub mod plane;
 pub mod span;
+#[cfg(test)]
+pub(crate) mod test_util;
 pub mod tree;
 mod util;
 
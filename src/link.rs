@@ -1,16 +1,35 @@
+use glam::{Vec3, Vec3Swizzles};
+
 use crate::edge::Edge3D;
 
 /// Links two polygons of a navmesh together with an edge
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy)]
 pub struct NavmeshLink {
     from: usize,
     to: usize,
     kind: LinkKind,
+    cost: f32,
 }
 
 impl NavmeshLink {
     pub fn new(from: usize, to: usize, kind: LinkKind) -> Self {
-        Self { from, to, kind }
+        Self {
+            from,
+            to,
+            kind,
+            cost: 1.0,
+        }
+    }
+
+    /// Sets the multiplier applied to the cost of traversing the link
+    pub fn with_cost(mut self, cost: f32) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    pub fn cost(&self) -> f32 {
+        self.cost
     }
 
     pub fn from(&self) -> usize {
@@ -25,34 +44,101 @@ impl NavmeshLink {
         &self.kind
     }
 
-    pub fn reverse(&self) -> NavmeshLink {
-        Self {
+    /// Returns the link traversing the same edges in the opposite direction.
+    ///
+    /// Steps up become steps down and the other way around. Drops can not be climbed back up,
+    /// and have no reverse.
+    pub fn reverse(&self) -> Option<NavmeshLink> {
+        let kind = match self.kind {
+            LinkKind::Walk(v) => LinkKind::Walk(v),
+            LinkKind::StepUp(a, b) => LinkKind::StepDown(b, a),
+            LinkKind::StepDown(a, b) => LinkKind::StepUp(b, a),
+            LinkKind::Drop(..) => return None,
+            LinkKind::OffMesh { from, to, tag } => LinkKind::OffMesh {
+                from: to,
+                to: from,
+                tag,
+            },
+        };
+
+        Some(Self {
             from: self.to,
             to: self.from,
-            kind: match self.kind {
-                LinkKind::Walk(v) => LinkKind::Walk(v),
-                LinkKind::StepUp(a, b) => LinkKind::StepUp(b, a),
-            },
-        }
+            kind,
+            cost: self.cost,
+        })
     }
 
+    /// Returns the edge the link is entered through.
+    ///
+    /// Off-mesh links are entered at a single point, and return a degenerate edge.
     pub fn source_edge(&self) -> Edge3D {
         match self.kind {
             LinkKind::Walk(v) => v,
             LinkKind::StepUp(v, _) => v,
+            LinkKind::StepDown(v, _) => v,
+            LinkKind::Drop(v, _) => v,
+            LinkKind::OffMesh { from, .. } => Edge3D::new(from, from),
         }
     }
 
+    /// Returns the edge the link is exited through
     pub fn destination_edge(&self) -> Edge3D {
         match self.kind {
             LinkKind::Walk(v) => v,
             LinkKind::StepUp(_, v) => v,
+            LinkKind::StepDown(_, v) => v,
+            LinkKind::Drop(_, v) => v,
+            LinkKind::OffMesh { to, .. } => Edge3D::new(to, to),
         }
     }
+
+    /// Returns the horizontal width of the opening shared by both edges.
+    ///
+    /// Agents wider than this can not pass through the link. Off-mesh links are not limited in
+    /// width.
+    pub fn width(&self) -> f32 {
+        if let LinkKind::OffMesh { .. } = self.kind {
+            return f32::INFINITY;
+        }
+
+        let width = |edge: Edge3D| (edge.p2 - edge.p1).xz().length();
+        width(self.source_edge()).min(width(self.destination_edge()))
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LinkKind {
     Walk(Edge3D),
+    /// Step from a lower edge up to a higher edge within `max_step_height`
     StepUp(Edge3D, Edge3D),
+    /// Step from a higher edge down to a lower edge, the reverse of [`LinkKind::StepUp`]
+    StepDown(Edge3D, Edge3D),
+    /// One-way link from the edge of a ledge down to a lower edge
+    Drop(Edge3D, Edge3D),
+    /// Authored connection between two points, such as a ladder, jump pad or teleporter.
+    ///
+    /// The tag is chosen by the user to tell the kinds of connections apart.
+    OffMesh {
+        from: Vec3,
+        to: Vec3,
+        tag: u32,
+    },
+}
+
+impl LinkKind {
+    /// Returns the height climbed when traversing the link, which is negative when stepping or
+    /// dropping down.
+    ///
+    /// Measured between the midpoints of the edges, or between the ends of off-mesh links.
+    pub fn height_difference(&self) -> f32 {
+        match *self {
+            LinkKind::Walk(_) => 0.0,
+            LinkKind::StepUp(a, b) | LinkKind::StepDown(a, b) | LinkKind::Drop(a, b) => {
+                b.midpoint().y - a.midpoint().y
+            }
+            LinkKind::OffMesh { from, to, .. } => to.y - from.y,
+        }
+    }
 }
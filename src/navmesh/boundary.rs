@@ -0,0 +1,350 @@
+use glam::{vec2, Vec3, Vec3Swizzles};
+
+use crate::{
+    edge::Edge3D,
+    edgelist::{PolygonEdge, VerticalPlane},
+    span::Span,
+};
+
+use super::Navmesh;
+
+/// What lies beyond a boundary edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryKind {
+    /// Another polygon is adjacent, but too high to step onto
+    Wall,
+    /// Nothing walkable is adjacent, or only a drop which is too deep
+    Ledge,
+}
+
+/// A part of a polygon edge which is not crossed by any link
+#[derive(Debug, Clone, Copy)]
+pub struct BoundaryEdge {
+    pub edge: Edge3D,
+    pub polygon: usize,
+    pub kind: BoundaryKind,
+}
+
+/// Boundary edges stitched end to end, as returned by [`Navmesh::boundary_loops`]
+#[derive(Debug, Clone)]
+pub struct BoundaryLoop {
+    pub edges: Vec<BoundaryEdge>,
+    /// The last edge ends where the first edge starts.
+    ///
+    /// Chains are left open where the boundary can not be followed, such as along degenerate
+    /// geometry.
+    pub closed: bool,
+}
+
+impl BoundaryLoop {
+    /// Returns the start of each edge, followed by the end of the last edge for open chains
+    pub fn points(&self) -> impl Iterator<Item = Vec3> + '_ {
+        let end = (!self.closed).then(|| self.edges.last().map(|v| v.edge.p2));
+        self.edges.iter().map(|v| v.edge.p1).chain(end.flatten())
+    }
+
+    /// Total length of the edges, excluding any steps between them
+    pub fn length(&self) -> f32 {
+        self.edges.iter().map(|v| v.edge.length()).sum()
+    }
+}
+
+/// Height of the edge at `x` in plane coordinates
+fn height_at(plane: &VerticalPlane, edge: &PolygonEdge, x: f32) -> f32 {
+    let (a, b) = plane.coplanar_edge(edge);
+    a.y + (b.y - a.y) * (x - a.x) / (b.x - a.x)
+}
+
+impl Navmesh {
+    /// Returns the parts of the walkable polygon edges which are not connected to any other
+    /// polygon.
+    pub fn boundary_edges(&self) -> Vec<BoundaryEdge> {
+        let epsilon = self.settings.epsilon;
+        let mut result = Vec::new();
+
+        for plane in &self.edge_planes() {
+            for (edges, opposite) in [(&plane.front, &plane.back), (&plane.back, &plane.front)] {
+                for edge in edges {
+                    let interval = plane.coplanar_interval(edge);
+                    if interval.max - interval.min < 2.0 * epsilon {
+                        continue;
+                    }
+
+                    let polygon_edge = edge.edge();
+                    let on_edge = |p| polygon_edge.closest_point(p).distance(p) < epsilon;
+
+                    let mut linked = self
+                        .neighbors(edge.polygon())
+                        .map(|v| v.edge())
+                        .filter(|v| on_edge(v.p1) && on_edge(v.p2))
+                        .map(|v| {
+                            let a = plane.transform_coplanar_point(v.p1).x;
+                            let b = plane.transform_coplanar_point(v.p2).x;
+                            Span::new(a.min(b), a.max(b))
+                        })
+                        .collect::<Vec<_>>();
+
+                    linked.sort_by(|a, b| a.min.total_cmp(&b.min));
+
+                    // Collect the parts of the interval not covered by any link
+                    let mut gaps = Vec::new();
+                    let mut cursor = interval.min;
+                    for span in linked {
+                        if span.min - cursor > 2.0 * epsilon {
+                            gaps.push(Span::new(cursor, span.min));
+                        }
+
+                        cursor = cursor.max(span.max);
+                    }
+
+                    if interval.max - cursor > 2.0 * epsilon {
+                        gaps.push(Span::new(cursor, interval.max));
+                    }
+
+                    for gap in gaps {
+                        let mid = (gap.min + gap.max) * 0.5;
+                        let height = height_at(plane, edge, mid);
+
+                        let wall = opposite.iter().any(|other| {
+                            let other_interval = plane.coplanar_interval(other);
+                            other_interval.min - epsilon <= mid
+                                && mid <= other_interval.max + epsilon
+                                && height_at(plane, other, mid) > height + epsilon
+                        });
+
+                        let point = |x: f32| {
+                            let p = plane.coplanar_to_world(vec2(x, height_at(plane, edge, x)));
+                            polygon_edge.closest_point(p)
+                        };
+
+                        // Keep the winding direction of the polygon edge
+                        let (a, b) = plane.coplanar_edge(edge);
+                        let (start, end) = if a.x <= b.x {
+                            (gap.min, gap.max)
+                        } else {
+                            (gap.max, gap.min)
+                        };
+
+                        result.push(BoundaryEdge {
+                            edge: Edge3D::new(point(start), point(end)),
+                            polygon: edge.polygon(),
+                            kind: if wall {
+                                BoundaryKind::Wall
+                            } else {
+                                BoundaryKind::Ledge
+                            },
+                        });
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Stitches the boundary edges into loops by joining the end of each edge to the start of
+    /// the next.
+    ///
+    /// Edges are joined across steps within `max_step_height`, so a loop may run along several
+    /// levels connected by steps.
+    pub fn boundary_loops(&self) -> Vec<BoundaryLoop> {
+        let epsilon = self.settings.epsilon;
+        let max_step_height = self.settings.max_step_height;
+
+        let edges = self.boundary_edges();
+        let mut used = vec![false; edges.len()];
+
+        // Returns the closest unused edge with the given point as its start or end
+        let find = |used: &[bool], point: Vec3, from_start: bool| {
+            edges
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| !used[index])
+                .map(|(index, edge)| {
+                    let p = if from_start {
+                        edge.edge.p1
+                    } else {
+                        edge.edge.p2
+                    };
+                    (index, p.xz().distance(point.xz()), (p.y - point.y).abs())
+                })
+                .filter(|v| v.1 < epsilon && v.2 <= max_step_height + epsilon)
+                .min_by(|a, b| (a.1 + a.2).total_cmp(&(b.1 + b.2)))
+                .map(|v| v.0)
+        };
+
+        let joins = |a: Vec3, b: Vec3| {
+            a.xz().distance(b.xz()) < epsilon && (a.y - b.y).abs() <= max_step_height + epsilon
+        };
+
+        let mut loops = Vec::new();
+        for first in 0..edges.len() {
+            if used[first] {
+                continue;
+            }
+
+            used[first] = true;
+            let mut chain = vec![edges[first]];
+
+            let mut closed = false;
+            while let Some(last) = chain.last() {
+                if chain.len() > 1 && joins(last.edge.p2, chain[0].edge.p1) {
+                    closed = true;
+                    break;
+                }
+
+                let Some(next) = find(&used, last.edge.p2, true) else {
+                    break;
+                };
+
+                used[next] = true;
+                chain.push(edges[next]);
+            }
+
+            // Follow open chains backwards as well, as they may have started midway
+            if !closed {
+                let mut prefix = Vec::new();
+                let mut start = chain[0].edge.p1;
+                while let Some(prev) = find(&used, start, false) {
+                    used[prev] = true;
+                    prefix.push(edges[prev]);
+                    start = edges[prev].edge.p1;
+                }
+
+                prefix.reverse();
+                prefix.append(&mut chain);
+                chain = prefix;
+            }
+
+            loops.push(BoundaryLoop {
+                edges: chain,
+                closed,
+            });
+        }
+
+        loops
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{vec3, Vec3};
+
+    use crate::{brush::PositionedBrush, navmesh::NavmeshSettings, test_util::cube};
+
+    use super::*;
+
+    fn navmesh(brushes: impl IntoIterator<Item = PositionedBrush>) -> Navmesh {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        Navmesh::new(settings, brushes)
+    }
+
+    /// Total length of the boundary in the plane `x = 1`
+    fn step_length(edges: &[BoundaryEdge]) -> f32 {
+        edges
+            .iter()
+            .filter(|v| (v.edge.p1.x - 1.0).abs() < 0.01 && (v.edge.p2.x - 1.0).abs() < 0.01)
+            .map(|v| v.edge.length())
+            .sum()
+    }
+
+    #[test]
+    fn boundary_step() {
+        let navmesh = navmesh([
+            cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+            cube(vec3(2.0, 0.3, 0.0), vec3(1.0, 0.2, 1.0)),
+        ]);
+
+        let edges = navmesh.boundary_edges();
+
+        // Only the outer rim of both slabs remains
+        assert!(step_length(&edges) < 0.01);
+        let length: f32 = edges.iter().map(|v| v.edge.length()).sum();
+        assert!((length - 12.0).abs() < 0.01, "{length}");
+        assert!(edges.iter().all(|v| v.kind == BoundaryKind::Ledge));
+    }
+
+    #[test]
+    fn boundary_wall() {
+        let navmesh = navmesh([
+            cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+            cube(vec3(2.0, 1.0, 0.0), vec3(1.0, 0.2, 1.0)),
+        ]);
+
+        let edges = navmesh.boundary_edges();
+        let low = navmesh.closest_polygon(vec3(0.0, 0.3, 0.0)).unwrap().0;
+
+        let step = edges
+            .iter()
+            .filter(|v| (v.edge.p1.x - 1.0).abs() < 0.01 && (v.edge.p2.x - 1.0).abs() < 0.01)
+            .collect::<Vec<_>>();
+
+        assert!(!step.is_empty());
+        for edge in step {
+            let expected = if edge.polygon == low {
+                BoundaryKind::Wall
+            } else {
+                BoundaryKind::Ledge
+            };
+
+            assert_eq!(edge.kind, expected);
+        }
+    }
+
+    #[test]
+    fn boundary_partial_link() {
+        // The narrow slab only links to the middle third of the edge
+        let navmesh = navmesh([
+            cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+            cube(vec3(2.0, 0.3, 0.0), vec3(1.0, 0.2, 1.0 / 3.0)),
+        ]);
+
+        let edges = navmesh.boundary_edges();
+        let length = step_length(&edges);
+        assert!((length - 4.0 / 3.0).abs() < 0.01, "{length}");
+    }
+
+    #[test]
+    fn boundary_loops() {
+        let platform = navmesh([cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0))]);
+        let loops = platform.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert!(loops[0].closed);
+        assert!((loops[0].length() - 8.0).abs() < 0.01);
+        assert_eq!(loops[0].points().count(), loops[0].edges.len());
+
+        // Consecutive edges meet
+        let edges = &loops[0].edges;
+        for (a, b) in edges.iter().zip(edges.iter().cycle().skip(1)) {
+            assert!(a.edge.p2.distance(b.edge.p1) < 0.01);
+        }
+
+        // The loop runs around both slabs, crossing the step at either end of it
+        let stepped = navmesh([
+            cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+            cube(vec3(2.0, 0.3, 0.0), vec3(1.0, 0.2, 1.0)),
+        ]);
+
+        let loops = stepped.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert!(loops[0].closed);
+        assert!((loops[0].length() - 12.0).abs() < 0.01);
+        assert!(step_length(&loops[0].edges) < 0.01);
+
+        // Separate platforms form separate loops
+        let separate = navmesh([
+            cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+            cube(vec3(4.0, 0.0, 0.0), vec3(1.0, 0.2, 1.0)),
+        ]);
+
+        let loops = separate.boundary_loops();
+        assert_eq!(loops.len(), 2);
+        assert!(loops
+            .iter()
+            .all(|v| v.closed && (v.length() - 8.0).abs() < 0.01));
+    }
+}
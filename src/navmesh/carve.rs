@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use glam::{vec3, Mat4, Vec3};
+use itertools::Itertools;
+
+use crate::{
+    brush::{Brush, Face},
+    link::LinkKind,
+    tree::BspTree,
+    util::{par_map, TOLERANCE},
+};
+
+use super::{merge::merge_coplanar, plane_links, Navmesh, NavmeshSettings, PolygonIds};
+
+/// Identifies a volume carved out of a navmesh by [`Navmesh::carve_obstacle`]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CarveToken(usize);
+
+/// A world space volume removed from the walkable polygons
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub(super) struct Carve {
+    faces: Vec<Face>,
+    bounds: (Vec3, Vec3),
+}
+
+impl Carve {
+    fn new(faces: Vec<Face>) -> Self {
+        Self {
+            bounds: bounds(&faces),
+            faces,
+        }
+    }
+
+    fn tree(&self, settings: &NavmeshSettings) -> Option<BspTree> {
+        BspTree::build_with_parallelism(&self.faces, settings.epsilon, settings.parallel)
+    }
+}
+
+/// A walkable polygon as it was before being carved, and the pieces left of it
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub(super) struct CarvedPolygon {
+    face: Face,
+    /// Carves overlapping the original polygon
+    carves: BTreeSet<CarveToken>,
+    /// Indices of the remaining pieces in the walkable polygons
+    pieces: Vec<usize>,
+}
+
+fn bounds<'a>(faces: impl IntoIterator<Item = &'a Face>) -> (Vec3, Vec3) {
+    faces
+        .into_iter()
+        .flat_map(|v| v.points())
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+            (min.min(p), max.max(p))
+        })
+}
+
+fn overlaps(a: (Vec3, Vec3), b: (Vec3, Vec3), margin: Vec3) -> bool {
+    (a.0 - margin).cmple(b.1).all() && (b.0 - margin).cmple(a.1).all()
+}
+
+/// Returns true if the carve removes any of the face
+fn carves_face(face: &Face, carve: &Carve, tree: &BspTree, settings: &NavmeshSettings) -> bool {
+    if !overlaps(bounds([face]), carve.bounds, Vec3::splat(settings.epsilon)) {
+        return false;
+    }
+
+    // Clipping may split the face without removing anything from it
+    let area = face.area();
+    let kept = tree
+        .clip_faces(std::slice::from_ref(face))
+        .iter()
+        .map(Face::area)
+        .sum::<f32>();
+
+    area - kept > settings.min_polygon_area.max(area * TOLERANCE)
+}
+
+impl Navmesh {
+    /// Removes the volume of the brush from the walkable polygons until it is restored, such as
+    /// for a closed door or a dropped crate.
+    ///
+    /// Unlike [`Self::add_obstacle`], polygons are clipped to the volume rather than blocked as a
+    /// whole, and only the links of the clipped polygons are regenerated. The brush is inflated
+    /// by the agent radius like the brushes the navmesh is generated from.
+    ///
+    /// Carves may overlap and be restored in any order, and are reapplied when the navmesh is
+    /// rebuilt. Clipped polygons are given new indices, so any clusters are discarded.
+    pub fn carve_obstacle(&mut self, shape: &Brush, transform: Mat4) -> CarveToken {
+        let settings = self.settings;
+        let brush = Brush::new(
+            shape
+                .faces()
+                .iter()
+                .map(|v| v.transform(transform))
+                .collect(),
+        );
+        let brush = if settings.agent_radius > 0.0 {
+            brush.inflated(settings.agent_radius).unwrap_or(brush)
+        } else {
+            brush
+        };
+
+        let carve = Carve::new(brush.faces().to_vec());
+        let token = CarveToken(self.carves.vacant_key());
+
+        let mut affected = Vec::new();
+        if let Some(tree) = carve.tree(&settings) {
+            // Compare against the original polygons, so that restoring an overlapping carve
+            // does not bring back the parts shared with this one
+            let (touched, rest) = std::mem::take(&mut self.carved_polygons)
+                .into_iter()
+                .partition(|v| carves_face(&v.face, &carve, &tree, &settings));
+
+            affected = touched;
+            self.carved_polygons = rest;
+
+            let pieces = self
+                .carved_polygons
+                .iter()
+                .chain(&affected)
+                .flat_map(|v| v.pieces.iter().copied())
+                .collect::<BTreeSet<_>>();
+
+            affected.extend(
+                self.walkable_polygons
+                    .iter()
+                    .filter(|v| !pieces.contains(&v.0))
+                    .filter(|v| carves_face(v.1, &carve, &tree, &settings))
+                    .map(|(index, face)| CarvedPolygon {
+                        face: face.clone(),
+                        carves: BTreeSet::new(),
+                        pieces: vec![index],
+                    }),
+            );
+        }
+
+        for polygon in &mut affected {
+            polygon.carves.insert(token);
+        }
+
+        self.carves.insert(carve);
+        self.recarve(affected);
+        token
+    }
+
+    /// Restores the polygons removed by a carve.
+    ///
+    /// Parts covered by other carves stay removed. Returns false if the carve was already
+    /// restored.
+    pub fn restore(&mut self, token: CarveToken) -> bool {
+        if self.carves.try_remove(token.0).is_none() {
+            return false;
+        }
+
+        let (mut affected, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.carved_polygons)
+            .into_iter()
+            .partition(|v| v.carves.contains(&token));
+
+        self.carved_polygons = rest;
+        for polygon in &mut affected {
+            polygon.carves.remove(&token);
+        }
+
+        self.recarve(affected);
+        true
+    }
+
+    /// Clips the walkable polygons to all carves, after the polygons are regenerated
+    pub(super) fn apply_carves(&mut self) {
+        self.carved_polygons.clear();
+
+        let settings = self.settings;
+        let trees = self
+            .carves
+            .iter()
+            .filter_map(|(index, carve)| Some((CarveToken(index), carve, carve.tree(&settings)?)))
+            .collect_vec();
+
+        if trees.is_empty() {
+            return;
+        }
+
+        let polygons = self
+            .walkable_polygons
+            .iter()
+            .filter_map(|(index, face)| {
+                let carves = trees
+                    .iter()
+                    .filter(|v| carves_face(face, v.1, &v.2, &settings))
+                    .map(|v| v.0)
+                    .collect::<BTreeSet<_>>();
+
+                (!carves.is_empty()).then(|| CarvedPolygon {
+                    face: face.clone(),
+                    carves,
+                    pieces: vec![index],
+                })
+            })
+            .collect_vec();
+
+        self.replace_pieces(polygons);
+    }
+
+    /// Puts back the original polygons of all carves, which are kept to be reapplied
+    pub(super) fn remove_carves(&mut self) {
+        let polygons = std::mem::take(&mut self.carved_polygons)
+            .into_iter()
+            .map(|mut v| {
+                v.carves.clear();
+                v
+            })
+            .collect_vec();
+
+        self.replace_pieces(polygons);
+    }
+
+    /// Replaces the pieces of the polygons and regenerates the links around them
+    fn recarve(&mut self, polygons: Vec<CarvedPolygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+
+        let region = bounds(polygons.iter().map(|v| &v.face));
+        let (removed, added) = self.replace_pieces(polygons);
+        self.relink(&removed, &added, region);
+    }
+
+    /// Replaces the pieces of each polygon with the parts left outside of its carves.
+    ///
+    /// Polygons without any carves are put back whole and no longer tracked. Returns the indices
+    /// of the removed and added polygons, which may overlap.
+    fn replace_pieces(
+        &mut self,
+        polygons: Vec<CarvedPolygon>,
+    ) -> (BTreeSet<usize>, BTreeSet<usize>) {
+        let settings = self.settings;
+        let trees = polygons
+            .iter()
+            .flat_map(|v| &v.carves)
+            .unique()
+            .filter_map(|&token| Some((token, self.carves[token.0].tree(&settings)?)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut removed = BTreeSet::new();
+        let mut added = BTreeSet::new();
+
+        for mut polygon in polygons {
+            for piece in polygon.pieces.drain(..) {
+                self.walkable_polygons.remove(piece);
+                removed.insert(piece);
+            }
+
+            let pieces = if polygon.carves.is_empty() {
+                vec![polygon.face.clone()]
+            } else {
+                let pieces = polygon
+                    .carves
+                    .iter()
+                    .filter_map(|v| trees.get(v))
+                    .fold(vec![polygon.face.clone()], |pieces, tree| {
+                        tree.clip_faces(&pieces)
+                    });
+
+                // Clipping splits the polygon along every plane of the volume
+                let mut pieces = merge_coplanar(pieces, settings.epsilon);
+                pieces.retain(|v| v.area() >= settings.min_polygon_area);
+                pieces
+            };
+
+            for piece in pieces {
+                let index = self.walkable_polygons.insert(piece);
+                polygon.pieces.push(index);
+                added.insert(index);
+            }
+
+            if !polygon.carves.is_empty() {
+                self.carved_polygons.push(polygon);
+            }
+        }
+
+        (removed, added)
+    }
+
+    /// Removes the links of the removed polygons, and links the added polygons to the polygons
+    /// around them within `region`
+    fn relink(&mut self, removed: &BTreeSet<usize>, added: &BTreeSet<usize>, region: (Vec3, Vec3)) {
+        let _span = tracing::info_span!("navmesh::relink").entered();
+        let settings = self.settings;
+
+        // Off-mesh links are reapplied, as their polygons may have been replaced
+        let stale = self
+            .links
+            .iter()
+            .filter(|(_, link)| {
+                removed.contains(&link.from())
+                    || removed.contains(&link.to())
+                    || matches!(link.kind(), LinkKind::OffMesh { .. })
+            })
+            .map(|v| v.0)
+            .collect_vec();
+
+        for index in stale {
+            let link = self.links.remove(index);
+            if let Some(links) = self.polygon_links.get_mut(&link.from()) {
+                links.retain(|&v| v != index);
+                if links.is_empty() {
+                    self.polygon_links.remove(&link.from());
+                }
+            }
+        }
+
+        self.clusters = None;
+        self.rebuild_spatial_index();
+
+        let height = settings.max_step_height.max(settings.max_drop_height);
+        let margin = vec3(
+            settings.epsilon,
+            height + settings.epsilon,
+            settings.epsilon,
+        );
+        let nearby = self
+            .walkable_polygons
+            .iter()
+            .filter(|v| overlaps(bounds([v.1]), region, margin))
+            .collect_vec();
+
+        // Links between the untouched polygons are still in place
+        let planes = self.edge_planes_of(nearby);
+        let candidates = par_map(&planes, settings.parallel, |plane| {
+            plane_links(plane, &settings)
+        });
+        self.insert_links(
+            candidates
+                .into_iter()
+                .flatten()
+                .filter(|v| added.contains(&v.from()) || added.contains(&v.to())),
+        );
+
+        self.apply_offmesh_links();
+        self.polygon_ids = PolygonIds::new(&self.walkable_polygons);
+        self.apply_obstacles();
+        self.apply_obstacle_links();
+        self.compute_regions();
+
+        #[cfg(feature = "strict-validation")]
+        {
+            let report = self.validate();
+            debug_assert!(report.is_ok(), "{report}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::Vec3Swizzles;
+
+    use crate::brush::PositionedBrush;
+
+    use super::*;
+
+    #[test]
+    fn carve_reroute() {
+        let floor = || {
+            PositionedBrush::new(
+                Mat4::from_scale(vec3(6.0, 0.2, 2.0)),
+                Arc::new(Brush::cube()),
+            )
+        };
+
+        let mut navmesh = Navmesh::new(NavmeshSettings::default(), [floor()]);
+        let polygons = navmesh.polygons().len();
+
+        let (start, end) = (vec3(-5.0, 0.4, 0.0), vec3(5.0, 0.4, 0.0));
+        let points = |navmesh: &Navmesh| {
+            navmesh
+                .find_path(start, end)
+                .unwrap()
+                .iter()
+                .map(|v| v.point())
+                .collect_vec()
+        };
+
+        // Links must not refer to removed polygons or links
+        let assert_valid = |navmesh: &Navmesh| {
+            let report = navmesh.validate();
+            assert!(report.is_ok(), "{report}");
+            for (index, _) in navmesh.polygons() {
+                for &link in navmesh.polygon_links(index) {
+                    assert_eq!(navmesh.link(link).unwrap().from(), index);
+                }
+            }
+        };
+
+        let inside = |p: Vec3, (min, max): (Vec3, Vec3)| {
+            p.xz().cmpgt(min.xz()).all() && p.xz().cmplt(max.xz()).all()
+        };
+
+        // Samples the path, returning true if it passes through the box
+        let crosses = |points: &[Vec3], bounds: (Vec3, Vec3)| {
+            points
+                .iter()
+                .tuple_windows()
+                .any(|(a, b)| (0..=20).any(|i| inside(a.lerp(*b, i as f32 / 20.0), bounds)))
+        };
+
+        let original = points(&navmesh);
+        assert!(original.iter().all(|v| v.z.abs() < 0.1));
+
+        // A crate in the middle of the floor
+        let crate_box = (vec3(-0.5, 0.0, -1.0), vec3(0.5, 1.0, 1.0));
+        let first = navmesh.carve_obstacle(&Brush::cube(), Mat4::from_scale(vec3(0.5, 1.0, 1.0)));
+
+        assert_valid(&navmesh);
+        let rerouted = points(&navmesh);
+        assert!(!crosses(&rerouted, crate_box));
+        assert!(rerouted.iter().any(|v| v.z.abs() > 1.1));
+
+        // A door closing on one side of the crate, overlapping it
+        let door_box = (vec3(-0.3, 0.0, 1.0), vec3(0.3, 1.0, 2.0));
+        let second = navmesh.carve_obstacle(
+            &Brush::cube(),
+            Mat4::from_translation(vec3(0.0, 0.0, 1.5)) * Mat4::from_scale(vec3(0.3, 1.0, 0.5)),
+        );
+
+        assert_valid(&navmesh);
+        let around = points(&navmesh);
+        assert!(!crosses(&around, crate_box));
+        assert!(!crosses(&around, door_box));
+        assert!(around.iter().any(|v| v.z < -1.1));
+
+        // Carves are reapplied when rebuilding
+        let carved = navmesh.polygons().len();
+        navmesh.rebuild([floor()]);
+        assert_valid(&navmesh);
+        assert_eq!(navmesh.polygons().len(), carved);
+        assert_eq!(points(&navmesh), around);
+
+        // Removing the crate first keeps the door
+        assert!(navmesh.restore(first));
+        assert_valid(&navmesh);
+        let through = points(&navmesh);
+        assert!(through.iter().all(|v| v.z.abs() < 0.1));
+        assert!(navmesh
+            .polygons()
+            .iter()
+            .all(|(_, face)| !inside(face.centroid(), door_box)));
+
+        assert!(navmesh.restore(second));
+        assert!(!navmesh.restore(second));
+        assert_valid(&navmesh);
+        assert_eq!(navmesh.polygons().len(), polygons);
+        assert_eq!(points(&navmesh), original);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
+
+use glam::Vec3;
+use ordered_float::OrderedFloat;
+
+use super::Navmesh;
+
+/// Marks polygon keys without a cluster in [`Clusters`]
+const NO_CLUSTER: usize = usize::MAX;
+
+/// Groups of nearby polygons and the links between them, for planning long paths on a coarse
+/// graph before refining them on the polygons.
+///
+/// Created by [`Navmesh::build_clusters`], and discarded whenever the links are regenerated.
+#[derive(Debug, Clone)]
+pub struct Clusters {
+    cluster_size: f32,
+    /// Cluster of each polygon, indexed by polygon
+    polygon_clusters: Vec<usize>,
+    /// Area weighted center of the polygons in each cluster
+    centers: Vec<Vec3>,
+    /// Reachable clusters of each cluster, along with the links crossing into them
+    neighbors: Vec<BTreeMap<usize, Vec<usize>>>,
+}
+
+impl Clusters {
+    fn build(navmesh: &Navmesh, cluster_size: f32) -> Self {
+        let len = navmesh.polygons().capacity();
+        let mut polygon_clusters = vec![NO_CLUSTER; len];
+
+        // Polygons in the same tile but different regions can never be connected inside it
+        let mut keys = BTreeMap::new();
+        let mut weighted = Vec::new();
+        for (index, face) in navmesh.polygons() {
+            let centroid = face.centroid();
+            let key = (
+                (centroid.x / cluster_size).floor() as i32,
+                (centroid.z / cluster_size).floor() as i32,
+                navmesh.region(index),
+            );
+
+            let next = keys.len();
+            let cluster = *keys.entry(key).or_insert(next);
+            if cluster == weighted.len() {
+                weighted.push((Vec3::ZERO, 0.0));
+            }
+
+            let area = face.area().max(f32::EPSILON);
+            weighted[cluster].0 += centroid * area;
+            weighted[cluster].1 += area;
+            polygon_clusters[index] = cluster;
+        }
+
+        let mut neighbors = vec![BTreeMap::<usize, Vec<usize>>::new(); weighted.len()];
+        for (index, link) in navmesh.links() {
+            let (from, to) = (polygon_clusters[link.from()], polygon_clusters[link.to()]);
+            if from != to {
+                neighbors[from].entry(to).or_default().push(index);
+            }
+        }
+
+        Self {
+            cluster_size,
+            polygon_clusters,
+            centers: weighted.into_iter().map(|(sum, area)| sum / area).collect(),
+            neighbors,
+        }
+    }
+
+    pub fn cluster_size(&self) -> f32 {
+        self.cluster_size
+    }
+
+    pub fn cluster_count(&self) -> usize {
+        self.centers.len()
+    }
+
+    /// Returns the cluster the polygon belongs to
+    pub fn cluster(&self, polygon: usize) -> Option<usize> {
+        self.polygon_clusters
+            .get(polygon)
+            .copied()
+            .filter(|&v| v != NO_CLUSTER)
+    }
+
+    pub fn center(&self, cluster: usize) -> Vec3 {
+        self.centers[cluster]
+    }
+
+    /// Returns the clusters reachable from `cluster` and the indices of the links leading there
+    pub fn neighbors(&self, cluster: usize) -> impl Iterator<Item = (usize, &[usize])> {
+        self.neighbors[cluster].iter().map(|(&k, v)| (k, &v[..]))
+    }
+
+    /// Finds the sequence of clusters from `from` to `to`, travelling between the cluster
+    /// centers and starting at `start` and ending at `end`.
+    ///
+    /// Returns the clusters along with the number of expanded clusters.
+    pub(crate) fn route(
+        &self,
+        from: usize,
+        to: usize,
+        start: Vec3,
+        end: Vec3,
+    ) -> (Option<Vec<usize>>, usize) {
+        let mut open = BinaryHeap::new();
+        let mut costs = vec![f32::INFINITY; self.centers.len()];
+        let mut prev = vec![NO_CLUSTER; self.centers.len()];
+        let mut closed = vec![false; self.centers.len()];
+        let mut expansions = 0;
+
+        let position = |cluster: usize| match cluster {
+            v if v == from => start,
+            v if v == to => end,
+            v => self.centers[v],
+        };
+
+        costs[from] = 0.0;
+        open.push(Reverse((OrderedFloat(start.distance(end)), from)));
+
+        while let Some(Reverse((_, current))) = open.pop() {
+            if closed[current] {
+                continue;
+            }
+
+            closed[current] = true;
+            expansions += 1;
+
+            if current == to {
+                let mut route = vec![to];
+                while let Some(&last) = route.last().filter(|&&v| v != from) {
+                    route.push(prev[last]);
+                }
+
+                route.reverse();
+                return (Some(route), expansions);
+            }
+
+            for &next in self.neighbors[current].keys() {
+                let cost = costs[current] + position(current).distance(position(next));
+                if closed[next] || cost >= costs[next] {
+                    continue;
+                }
+
+                costs[next] = cost;
+                prev[next] = current;
+                open.push(Reverse((
+                    OrderedFloat(cost + position(next).distance(end)),
+                    next,
+                )));
+            }
+        }
+
+        (None, expansions)
+    }
+}
+
+impl Navmesh {
+    /// Groups the polygons into clusters by the `cluster_size` square tile their centroid falls
+    /// in, enabling [`crate::astar::astar_hierarchical`].
+    ///
+    /// The clusters are discarded when the links are regenerated, and need to be built again.
+    pub fn build_clusters(&mut self, cluster_size: f32) {
+        self.clusters = Some(Clusters::build(self, cluster_size));
+    }
+
+    pub fn clusters(&self) -> Option<&Clusters> {
+        self.clusters.as_ref()
+    }
+}
@@ -0,0 +1,274 @@
+use glam::Vec3;
+
+use crate::{
+    brush::Face,
+    tree::{BspTree, RayHit},
+};
+
+/// The brushes of a group whose bounds overlap, unioned in a balanced order.
+///
+/// Unioning every brush into one tree clips the whole tree for each brush, which is quadratic
+/// for large connected layouts such as tiled floors. Instead the trees are kept on a stack where
+/// each holds more brushes than the one above it, and two trees are only unioned once they hold
+/// as many brushes, like a binary counter. Each brush is then clipped a logarithmic number of
+/// times.
+struct Group {
+    min: Vec3,
+    max: Vec3,
+    /// Trees and the number of brushes unioned into each, in the order they were added
+    trees: Vec<(usize, BspTree)>,
+}
+
+impl Group {
+    fn new(min: Vec3, max: Vec3, tree: BspTree) -> Self {
+        Self {
+            min,
+            max,
+            trees: vec![(1, tree)],
+        }
+    }
+
+    fn push(&mut self, count: usize, tree: BspTree) {
+        self.trees.push((count, tree));
+
+        while let [.., (a, _), (b, _)] = self.trees[..] {
+            if a > b {
+                break;
+            }
+
+            self.merge_last();
+        }
+    }
+
+    /// Unions the last tree into the one before it
+    fn merge_last(&mut self) {
+        let (count, tree) = self.trees.pop().unwrap();
+        let last = self.trees.last_mut().unwrap();
+        last.0 += count;
+        last.1.union(tree);
+    }
+
+    /// Unions all trees of the group into a single one
+    fn tree(&mut self) -> &mut BspTree {
+        while self.trees.len() > 1 {
+            self.merge_last();
+        }
+
+        &mut self.trees[0].1
+    }
+
+    fn into_tree(mut self) -> BspTree {
+        self.tree();
+        self.trees.pop().unwrap().1
+    }
+
+    /// Returns the tree of the group, which is only complete once [`CsgGroups::finish`] is
+    /// called
+    fn finished(&self) -> &BspTree {
+        debug_assert_eq!(self.trees.len(), 1, "groups are unfinished");
+        &self.trees[0].1
+    }
+}
+
+/// Combines brushes into groups of overlapping brushes, so that brushes only need to be clipped
+/// against the brushes they may touch rather than everything before them.
+///
+/// Groups are kept in a separate tree each. Brushes touching several groups merge them.
+/// [`Self::finish`] must be called once all brushes are added, before reading back the result.
+pub(super) struct CsgGroups {
+    groups: Vec<Group>,
+    epsilon: f32,
+}
+
+fn bounds(faces: &[Face]) -> (Vec3, Vec3) {
+    faces
+        .iter()
+        .flat_map(|v| v.points())
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+            (min.min(p), max.max(p))
+        })
+}
+
+impl CsgGroups {
+    /// Brushes within `epsilon` of each other are grouped, so that touching faces are removed
+    pub(super) fn new(epsilon: f32) -> Self {
+        Self {
+            groups: Vec::new(),
+            epsilon,
+        }
+    }
+
+    fn overlapping(&self, min: Vec3, max: Vec3) -> impl Iterator<Item = usize> + '_ {
+        let margin = Vec3::splat(self.epsilon);
+        self.groups
+            .iter()
+            .enumerate()
+            .filter(move |(_, group)| {
+                (min - margin).cmple(group.max).all() && (max + margin).cmpge(group.min).all()
+            })
+            .map(|v| v.0)
+    }
+
+    pub(super) fn union(&mut self, faces: &[Face], tree: BspTree) {
+        let (min, max) = bounds(faces);
+        let overlapping = self.overlapping(min, max).collect::<Vec<_>>();
+
+        let Some((&first, rest)) = overlapping.split_first() else {
+            self.groups.push(Group::new(min, max, tree));
+            return;
+        };
+
+        // Merge the later groups into the earliest one, keeping the order of the unions for
+        // brushes which only touch a single group
+        for &index in rest.iter().rev() {
+            let other = self.groups.remove(index);
+            let (count, bounds) = (
+                other.trees.iter().map(|v| v.0).sum(),
+                (other.min, other.max),
+            );
+
+            let group = &mut self.groups[first];
+            group.push(count, other.into_tree());
+            group.min = group.min.min(bounds.0);
+            group.max = group.max.max(bounds.1);
+        }
+
+        let group = &mut self.groups[first];
+        group.push(1, tree);
+        group.min = group.min.min(min);
+        group.max = group.max.max(max);
+    }
+
+    /// Subtracts the brush from the groups it overlaps
+    pub(super) fn subtract(&mut self, faces: &[Face], tree: BspTree) {
+        let (min, max) = bounds(faces);
+        let overlapping = self.overlapping(min, max).collect::<Vec<_>>();
+
+        for index in overlapping {
+            self.groups[index].tree().subtract(tree.clone());
+        }
+    }
+
+    /// Unions the remaining trees of each group
+    pub(super) fn finish(&mut self) {
+        for group in &mut self.groups {
+            group.tree();
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Returns the closest hit of the ray with any group
+    pub(super) fn raycast(&self, origin: Vec3, dir: Vec3) -> Option<RayHit> {
+        self.groups
+            .iter()
+            .filter_map(|v| v.finished().raycast(origin, dir))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+
+    pub(super) fn polygons_cleaned(&self, min_area: f32) -> Vec<Face> {
+        self.groups
+            .iter()
+            .flat_map(|v| v.finished().polygons_cleaned(min_area))
+            .collect()
+    }
+
+    pub(super) fn into_polygons_cleaned(self, min_area: f32) -> Vec<Face> {
+        self.groups
+            .into_iter()
+            .flat_map(|v| v.into_tree().into_polygons_cleaned(min_area))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{vec3, Mat4};
+
+    use crate::brush::{Brush, PositionedBrush};
+
+    use super::*;
+
+    fn cube(x: f32, z: f32) -> Vec<Face> {
+        PositionedBrush::new(Mat4::from_translation(vec3(x, 0.0, z)), Brush::cube())
+            .world_faces()
+            .collect()
+    }
+
+    fn sequential(brushes: &[Vec<Face>]) -> Vec<Face> {
+        brushes
+            .iter()
+            .filter_map(|v| BspTree::build(v))
+            .reduce(|mut tree, other| {
+                tree.union(other);
+                tree
+            })
+            .unwrap()
+            .into_polygons_cleaned(0.0)
+    }
+
+    fn grouped(brushes: &[Vec<Face>]) -> Vec<Face> {
+        let mut groups = CsgGroups::new(1e-4);
+        for faces in brushes {
+            groups.union(faces, BspTree::build(faces).unwrap());
+        }
+
+        groups.into_polygons_cleaned(0.0)
+    }
+
+    fn area(faces: &[Face]) -> f32 {
+        faces.iter().map(|v| v.area()).sum()
+    }
+
+    #[test]
+    fn overlapping_matches_sequential() {
+        // A chain of overlapping cubes forms a single group
+        let brushes = (0..5)
+            .map(|i| cube(i as f32 * 1.5, 0.0))
+            .collect::<Vec<_>>();
+
+        let mut expected = sequential(&brushes);
+        let mut result = grouped(&brushes);
+        expected.sort_by_cached_key(|v| v.canonical_key());
+        result.sort_by_cached_key(|v| v.canonical_key());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn disjoint_groups() {
+        let brushes = [
+            cube(0.0, 0.0),
+            cube(10.0, 0.0),
+            cube(1.0, 0.0),
+            // Touching the first cube, bridging it with the cube at x = 4
+            cube(2.0, -2.0),
+            cube(4.0, -2.0),
+            cube(10.0, 10.0),
+        ];
+
+        let mut groups = CsgGroups::new(1e-4);
+        for faces in &brushes {
+            groups.union(faces, BspTree::build(faces).unwrap());
+        }
+
+        assert_eq!(groups.groups.len(), 3);
+        groups.finish();
+        let result = groups.polygons_cleaned(0.0);
+        assert!((area(&result) - area(&sequential(&brushes))).abs() < 1e-3);
+
+        // Only the group below is hit
+        let hit = groups.raycast(vec3(10.0, -5.0, 10.0), Vec3::Y).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+        assert!(groups.raycast(vec3(5.0, -5.0, 5.0), Vec3::Y).is_none());
+
+        // Subtracting only affects the overlapped group
+        groups.subtract(
+            &cube(10.0, 11.0),
+            BspTree::build(&cube(10.0, 11.0)).unwrap(),
+        );
+        let carved = groups.polygons_cleaned(0.0);
+        assert!((area(&result) - area(&carved) - 8.0).abs() < 1e-3);
+    }
+}
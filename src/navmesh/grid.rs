@@ -0,0 +1,174 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use glam::{Vec2, Vec3, Vec3Swizzles};
+use slab::Slab;
+
+use crate::brush::Face;
+
+/// Uniform grid over the footprints of the polygons, for finding the polygons near a point
+/// without testing every polygon.
+///
+/// Rebuilt whenever the links are generated.
+#[derive(Debug, Clone)]
+pub(super) struct QueryGrid {
+    cell_size: f32,
+    /// Polygons whose footprint, grown by the margin, overlaps each cell, by increasing index
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl QueryGrid {
+    /// Builds the grid with the given cell size, or the average extent of the polygons.
+    ///
+    /// Polygons are added to every cell within `margin` of their footprint.
+    pub(super) fn new(polygons: &Slab<Face>, cell_size: Option<f32>, margin: f32) -> Self {
+        let footprint = |face: &Face| {
+            face.points()
+                .iter()
+                .fold((Vec2::INFINITY, Vec2::NEG_INFINITY), |(min, max), p| {
+                    (min.min(p.xz()), max.max(p.xz()))
+                })
+        };
+
+        let cell_size = cell_size.unwrap_or_else(|| {
+            let total = polygons
+                .iter()
+                .map(|(_, face)| {
+                    let (min, max) = footprint(face);
+                    (max - min).max_element()
+                })
+                .sum::<f32>();
+
+            total / polygons.len().max(1) as f32
+        });
+
+        let mut this = Self {
+            cell_size: cell_size.max(margin * 2.0).max(f32::EPSILON),
+            cells: HashMap::new(),
+        };
+
+        for (index, face) in polygons {
+            let (min, max) = footprint(face);
+            let (min, max) = (this.cell(min - margin), this.cell(max + margin));
+
+            for x in min.0..=max.0 {
+                for z in min.1..=max.1 {
+                    this.cells.entry((x, z)).or_default().push(index);
+                }
+            }
+        }
+
+        this
+    }
+
+    pub(super) fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell(&self, point: Vec2) -> (i32, i32) {
+        let cell = (point / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+
+    /// Returns the polygons which may lie within `radius` of the point horizontally, by
+    /// increasing index.
+    ///
+    /// Returns `None` if the radius spans more cells than there are in the grid, in which case
+    /// testing all polygons is faster.
+    pub(super) fn query(&self, point: Vec3, radius: f32) -> Option<Cow<'_, [usize]>> {
+        let (min, max) = (
+            self.cell(point.xz() - radius),
+            self.cell(point.xz() + radius),
+        );
+
+        if min == max {
+            return Some(
+                self.cells
+                    .get(&min)
+                    .map_or(Cow::Borrowed(&[][..]), |v| Cow::Borrowed(&v[..])),
+            );
+        }
+
+        let count = (max.0 as i64 - min.0 as i64 + 1) * (max.1 as i64 - min.1 as i64 + 1);
+        if count > self.cells.len() as i64 {
+            return None;
+        }
+
+        let mut polygons = Vec::new();
+        for x in min.0..=max.0 {
+            for z in min.1..=max.1 {
+                polygons.extend(self.cells.get(&(x, z)).into_iter().flatten());
+            }
+        }
+
+        polygons.sort_unstable();
+        polygons.dedup();
+        Some(Cow::Owned(polygons))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4};
+
+    use crate::{
+        brush::{Brush, PositionedBrush},
+        navmesh::{Navmesh, NavmeshSettings},
+    };
+
+    #[test]
+    fn grid_matches_linear() {
+        let mut seed = 0x2545_f491_u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32
+        };
+
+        // Platforms of varying size and height, some of them overlapping
+        let brushes = (0..40)
+            .map(|_| {
+                let translation = vec3(random() * 20.0, random() * 2.0, random() * 20.0);
+                let scale = vec3(0.2 + random() * 2.0, 0.2, 0.2 + random() * 2.0);
+                PositionedBrush::new(
+                    Mat4::from_translation(translation) * Mat4::from_scale(scale),
+                    Arc::new(Brush::cube()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut navmesh = Navmesh::new(NavmeshSettings::default(), brushes);
+        assert!(navmesh.query_grid.is_some());
+
+        let points = (0..1000)
+            .map(|_| vec3(random() * 24.0 - 2.0, random() * 3.0, random() * 24.0 - 2.0))
+            .collect::<Vec<_>>();
+
+        let query = |navmesh: &Navmesh| {
+            points
+                .iter()
+                .map(|&p| {
+                    (
+                        navmesh.closest_polygon(p).map(|v| (v.0, v.2)),
+                        navmesh.nearest_polygon(p, f32::INFINITY).map(|v| v.0),
+                        navmesh.project_point(p, 1.0),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let grid = query(&navmesh);
+        navmesh.query_grid = None;
+        let linear = query(&navmesh);
+
+        assert!(grid.iter().any(|v| v.0.is_some()));
+        assert!(grid.iter().any(|v| v.0.is_none()));
+        assert_eq!(grid, linear);
+
+        navmesh.rebuild_spatial_index();
+        assert!(navmesh.query_grid.is_some());
+        assert_eq!(query(&navmesh), grid);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use slab::Slab;
+
+use crate::brush::Face;
+
+use super::Navmesh;
+
+/// Identifies a walkable polygon by its geometry.
+///
+/// Unlike polygon indices, the id stays the same when the navmesh is regenerated as long as the
+/// polygon itself is unchanged, so it can be stored in saved routes or agent memory. Polygons
+/// whose geometry hashes to the same value are told apart by their order.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PolygonId {
+    hash: u64,
+    index: u32,
+}
+
+/// FNV-1a hash of the quantized vertices, which unlike the std hashers is stable across
+/// versions and platforms
+fn geometry_hash(face: &Face) -> u64 {
+    face.canonical_key()
+        .iter()
+        .flatten()
+        .flat_map(|v| v.to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Stable ids of the walkable polygons, rebuilt whenever the polygons change
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default)]
+pub(super) struct PolygonIds {
+    /// Id of each polygon, indexed by polygon
+    ids: Vec<Option<PolygonId>>,
+    /// Polygons sharing each hash, in a deterministic order
+    chains: HashMap<u64, Vec<usize>>,
+}
+
+impl PolygonIds {
+    pub(super) fn new(polygons: &Slab<Face>) -> Self {
+        let mut chains: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, face) in polygons {
+            chains.entry(geometry_hash(face)).or_default().push(index);
+        }
+
+        let mut ids = vec![None; polygons.capacity()];
+        for (&hash, chain) in &mut chains {
+            // Indices follow the geometry, so order collisions by it as well
+            chain.sort_by_cached_key(|&v| polygons[v].canonical_key());
+            for (index, &polygon) in chain.iter().enumerate() {
+                ids[polygon] = Some(PolygonId {
+                    hash,
+                    index: index as u32,
+                });
+            }
+        }
+
+        Self { ids, chains }
+    }
+}
+
+impl Navmesh {
+    /// Returns the stable id of the polygon
+    pub fn polygon_id(&self, polygon: usize) -> Option<PolygonId> {
+        self.polygon_ids.ids.get(polygon).copied().flatten()
+    }
+
+    /// Returns the index of the polygon with the given id, if it still exists
+    pub fn find_polygon(&self, id: PolygonId) -> Option<usize> {
+        self.polygon_ids
+            .chains
+            .get(&id.hash)?
+            .get(id.index as usize)
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::vec3;
+
+    use crate::{brush::Brush, navmesh::NavmeshSettings, test_util::slab};
+
+    use super::*;
+
+    #[test]
+    fn stable_ids() {
+        let scene = || [slab(0.0, 0.0), slab(2.0, 0.3), slab(4.0, 0.0)];
+
+        let navmesh = Navmesh::new(NavmeshSettings::default(), scene());
+        let ids = navmesh
+            .polygons()
+            .iter()
+            .map(|(index, face)| {
+                let id = navmesh.polygon_id(index).unwrap();
+                (index, id, face.canonical_key())
+            })
+            .collect::<Vec<_>>();
+
+        // Rebuilding with an extra brush shifts the indices of the original polygons
+        let mut rebuilt = Navmesh::new(NavmeshSettings::default(), scene());
+        rebuilt.rebuild([slab(-6.0, 2.0)].into_iter().chain(scene()));
+
+        let mut shifted = false;
+        for (index, id, key) in ids {
+            let polygon = rebuilt.find_polygon(id).unwrap();
+            assert_eq!(rebuilt.polygons()[polygon].canonical_key(), key);
+            assert_eq!(rebuilt.polygon_id(polygon), Some(id));
+            shifted |= polygon != index;
+        }
+
+        assert!(shifted);
+
+        // Waypoints carry the id of the polygon they are on
+        let path = rebuilt
+            .find_path(vec3(-0.5, 0.2, 0.0), vec3(4.5, 0.2, 0.0))
+            .unwrap();
+        for waypoint in &path {
+            assert!(waypoint.polygon_id().is_some());
+            assert_eq!(
+                waypoint.polygon_id(),
+                rebuilt.polygon_id(waypoint.target_polygon())
+            );
+        }
+    }
+
+    #[test]
+    fn colliding_ids() {
+        let mut polygons = Slab::new();
+        let face = || Brush::cube().faces()[0].clone();
+        polygons.insert(face());
+        polygons.insert(face());
+
+        // Identical geometry is the worst case of a collision
+        let ids = PolygonIds::new(&polygons);
+        let (a, b) = (ids.ids[0].unwrap(), ids.ids[1].unwrap());
+        assert_ne!(a, b);
+        assert_eq!(a.hash, b.hash);
+
+        let mut found = [
+            ids.chains[&a.hash][a.index as usize],
+            ids.chains[&b.hash][b.index as usize],
+        ];
+        found.sort();
+        assert_eq!(found, [0, 1]);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+use itertools::Itertools;
+
+use crate::brush::Face;
+
+use super::Navmesh;
+
+impl Navmesh {
+    /// Merges adjacent coplanar walkable polygons into larger convex polygons and regenerates
+    /// the links.
+    ///
+    /// Polygon indices are reassigned, and obstacles and carves are reapplied to the merged
+    /// polygons.
+    pub fn merge_coplanar_polygons(&mut self) {
+        self.remove_carves();
+        let faces = self.walkable_polygons.drain().collect_vec();
+
+        for face in merge_coplanar(faces, self.settings.epsilon) {
+            self.walkable_polygons.insert(face);
+        }
+
+        self.apply_carves();
+        self.polygon_ids = super::PolygonIds::new(&self.walkable_polygons);
+        self.apply_obstacles();
+        self.generate_links();
+    }
+}
+
+/// Greedily merges pairs of coplanar faces sharing a whole edge for as long as the result is
+/// convex, similar to Hertel-Mehlhorn.
+///
+/// Vertices made redundant by a merge are removed, so that neighboring merged faces may share
+/// the remaining longer edges. Degenerate faces are dropped, as they can not be merged.
+pub(super) fn merge_coplanar(mut faces: Vec<Face>, epsilon: f32) -> Vec<Face> {
+    let quantize = |p: Vec3| (p / epsilon).round().as_ivec3().to_array();
+    faces.retain(|v| !v.is_degenerate(epsilon));
+
+    loop {
+        let mut edges = HashMap::new();
+        for (index, face) in faces.iter().enumerate() {
+            for (edge, (a, b)) in face.edges().enumerate() {
+                edges.insert((quantize(a), quantize(b)), (index, edge));
+            }
+        }
+
+        // Each face is merged at most once per pass, as its edge indices change
+        let mut merged = vec![false; faces.len()];
+        let mut removed = vec![false; faces.len()];
+
+        for index in 0..faces.len() {
+            if merged[index] {
+                continue;
+            }
+
+            for edge in 0..faces[index].points().len() {
+                let (a, b) = faces[index].edges().nth(edge).unwrap();
+                let Some(&(other, other_edge)) = edges.get(&(quantize(b), quantize(a))) else {
+                    continue;
+                };
+
+                if other == index || merged[other] {
+                    continue;
+                }
+
+                let Some(face) =
+                    merge_pair(&faces[index], edge, &faces[other], other_edge, epsilon)
+                else {
+                    continue;
+                };
+
+                faces[index] = face;
+                merged[index] = true;
+                merged[other] = true;
+                removed[other] = true;
+                break;
+            }
+        }
+
+        if !removed.contains(&true) {
+            return faces;
+        }
+
+        faces = faces
+            .into_iter()
+            .zip(removed)
+            .filter(|v| !v.1)
+            .map(|v| v.0)
+            .collect_vec();
+    }
+}
+
+/// Joins `a` and `b` along edge `a_edge` of `a`, which is edge `b_edge` of `b` in the opposite
+/// direction.
+///
+/// Returns `None` if the faces are not coplanar or the result is not convex.
+fn merge_pair(a: &Face, a_edge: usize, b: &Face, b_edge: usize, epsilon: f32) -> Option<Face> {
+    let normal = a.normal();
+    if a.flags() != b.flags()
+        || normal.dot(b.normal()) < 1.0 - epsilon
+        || b.points()
+            .iter()
+            .any(|&p| a.distance_to_plane(p).abs() > epsilon)
+    {
+        return None;
+    }
+
+    let (n, m) = (a.points().len(), b.points().len());
+
+    // Walk around `a` starting after the shared edge, then around `b` skipping both ends of it
+    let points = (1..=n)
+        .map(|i| a.points()[(a_edge + i) % n])
+        .chain((2..m).map(|i| b.points()[(b_edge + i) % m]))
+        .collect_vec();
+
+    // Drop vertices which lie on the straight line between their neighbors
+    let turn = |p: Vec3, q: Vec3, r: Vec3| {
+        let (u, v) = ((q - p).normalize_or_zero(), (r - q).normalize_or_zero());
+        (u.cross(v).dot(normal), u.dot(v))
+    };
+
+    let mut points = points;
+    let mut i = 0;
+    while i < points.len() && points.len() > 3 {
+        let len = points.len();
+        let (p, q, r) = (
+            points[(i + len - 1) % len],
+            points[i],
+            points[(i + 1) % len],
+        );
+        let (sin, cos) = turn(p, q, r);
+        if sin.abs() <= epsilon && cos > 0.0 {
+            points.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+
+    let convex = points
+        .iter()
+        .circular_tuple_windows()
+        .all(|(&p, &q, &r)| turn(p, q, r).0 > epsilon);
+
+    convex.then(|| Face::new(points).with_flags(a.flags()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4};
+
+    use crate::{
+        astar::astar,
+        brush::{Brush, PositionedBrush},
+        navmesh::NavmeshSettings,
+    };
+
+    use super::*;
+
+    /// A 20x20 floor made of abutting tiles, which the boolean operations cut into pieces
+    fn tiled_floor(settings: NavmeshSettings) -> Navmesh {
+        let tiles = (0..5).flat_map(|x| (0..5).map(move |z| (x, z)));
+        Navmesh::new(
+            settings,
+            tiles.map(|(x, z)| {
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(x as f32 * 4.0 - 8.0, 0.0, z as f32 * 4.0 - 8.0))
+                        * Mat4::from_scale(vec3(2.0, 0.2, 2.0)),
+                    Arc::new(Brush::cube()),
+                )
+            }),
+        )
+    }
+
+    #[test]
+    fn merge_flat_plane() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let mut navmesh = tiled_floor(settings);
+        let merged = tiled_floor(NavmeshSettings {
+            merge_polygons: true,
+            ..settings
+        });
+
+        let (before, after) = (navmesh.polygons().len(), merged.polygons().len());
+        assert!(after * 2 <= before, "{before} -> {after}");
+        assert!((navmesh.total_walkable_area() - merged.total_walkable_area()).abs() < 0.01);
+
+        for (_, face) in merged.polygons() {
+            assert!(face.contains_point(face.centroid()));
+            assert!(face.edges().all(|(a, b)| a.distance(b) > settings.epsilon));
+        }
+
+        // Merging after generation gives the same result
+        navmesh.merge_coplanar_polygons();
+        assert_eq!(navmesh.polygons().len(), after);
+
+        let start = vec3(-9.0, 0.2, -9.0);
+        let end = vec3(9.0, 0.2, 9.0);
+        let path = astar(&merged, start, end, |a, b| a.distance(b)).unwrap();
+        assert!(
+            (path.length() - start.distance(end)).abs() < 0.01,
+            "{path:?}"
+        );
+    }
+
+    #[test]
+    fn merge_keeps_concave_corner() {
+        // Merging all three squares of an L would produce a concave polygon
+        let square = |x: f32, z: f32| {
+            Face::new([
+                vec3(x, 0.0, z),
+                vec3(x, 0.0, z + 1.0),
+                vec3(x + 1.0, 0.0, z + 1.0),
+                vec3(x + 1.0, 0.0, z),
+            ])
+        };
+
+        // Along with a degenerate sliver lying on the edge of a square
+        let sliver = Face::new([
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.5, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+        ]);
+
+        let faces = merge_coplanar(
+            vec![square(0.0, 0.0), square(1.0, 0.0), square(0.0, 1.0), sliver],
+            0.001,
+        );
+
+        assert_eq!(faces.len(), 2);
+        let area: f32 = faces.iter().map(|v| v.area()).sum();
+        assert!((area - 3.0).abs() < 1e-4);
+    }
+}
@@ -1,33 +1,123 @@
-use std::{collections::BTreeMap, f32::consts::TAU};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    f32::consts::FRAC_PI_2,
+};
 
-use glam::{vec2, Vec3};
-use itertools::Itertools;
+use glam::{vec2, Vec2, Vec3};
+use itertools::{Either, Itertools};
 use slab::Slab;
 
 use crate::{
-    astar::{astar, Waypoint},
-    brush::{Face, PositionedBrush},
+    astar::{astar_with_options, AstarOptions, Path, PathError},
+    brush::{Brush, CsgOp, Face, PositionedBrush},
     edge::Edge3D,
     edgelist::{PolygonEdge, VerticalPlane},
     link::{LinkKind, NavmeshLink},
+    obstacle::{ObstacleId, ObstacleShape},
+    plane::Plane,
     span::Span,
     tree::BspTree,
-    util::TOLERANCE,
+    util::{par_map, TOLERANCE},
 };
 
-#[derive(Debug, Clone, Copy)]
+mod boundary;
+mod carve;
+mod clusters;
+mod csg;
+mod grid;
+mod ids;
+mod merge;
+mod offmesh;
+mod raycast;
+mod regions;
+mod report;
+#[cfg(feature = "sampling")]
+mod sampling;
+mod settings;
+mod validate;
+
+pub use boundary::{BoundaryEdge, BoundaryKind, BoundaryLoop};
+pub use carve::CarveToken;
+pub use clusters::Clusters;
+pub use ids::PolygonId;
+pub use raycast::RaycastResult;
+pub use regions::NO_REGION;
+pub use report::NavmeshBuildReport;
+pub use settings::{NavmeshSettingsBuilder, SettingsError};
+pub use validate::ValidationReport;
+
+use carve::{Carve, CarvedPolygon};
+use csg::CsgGroups;
+use grid::QueryGrid;
+use ids::PolygonIds;
+use offmesh::OffMeshLink;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct NavmeshSettings {
     pub max_step_height: f32,
+    /// Maximum height an agent may drop down from a ledge.
+    ///
+    /// Drops are one-way, and are only generated when this is larger than `max_step_height`.
+    pub max_drop_height: f32,
+    /// Cosine of the steepest walkable slope, measured from the horizontal.
+    ///
+    /// This is `1.0` for only flat ground and `0.0` for vertical walls. See
+    /// [`Self::set_max_slope_degrees`] to set it from an angle.
     pub max_slope_cosine: f32,
     pub agent_radius: f32,
+    /// Minimum headroom above a walkable polygon, such as below a bridge.
+    ///
+    /// Polygons with less clearance are not walkable. `0` disables the check.
+    pub agent_height: f32,
+    /// Polygons with a smaller area are discarded during generation
+    pub min_polygon_area: f32,
+    /// Scales the cost of a path segment by `1 + slope_penalty * rise / length`, making steep
+    /// segments more expensive than flat ones
+    pub slope_penalty: f32,
+    /// Flat cost added when traversing a step link
+    pub step_cost: f32,
+    /// Maximum distance to the nearest polygon for points which are not above or below any
+    /// polygon
+    pub search_radius: f32,
+    /// Links with a narrower horizontal opening are discarded during generation, such as steps
+    /// onto platforms which barely overlap
+    pub min_link_width: f32,
+    /// Merge adjacent coplanar walkable polygons into larger convex polygons, reducing the
+    /// number of polygons to search through
+    pub merge_polygons: bool,
+    /// Distance within which points are considered coincident or coplanar during generation.
+    ///
+    /// Scale this with the units of the level, e.g. to `0.1` for levels authored in centimeters.
+    pub epsilon: f32,
+    /// Size of the grid cells used to look up the polygons near a point.
+    ///
+    /// Defaults to the average extent of the walkable polygons.
+    pub query_grid_cell_size: Option<f32>,
+    /// Generate on the rayon thread pool. Has no effect without the `rayon` feature.
+    ///
+    /// The generated navmesh is the same either way.
+    pub parallel: bool,
 }
 
 impl NavmeshSettings {
     pub fn new() -> Self {
         Self {
             max_step_height: 0.7,
+            max_drop_height: 0.0,
             max_slope_cosine: 0.707,
             agent_radius: 0.2,
+            agent_height: 0.0,
+            min_polygon_area: TOLERANCE * TOLERANCE,
+            slope_penalty: 0.0,
+            step_cost: 0.0,
+            search_radius: 1.0,
+            min_link_width: 0.0,
+            merge_polygons: false,
+            epsilon: TOLERANCE,
+            query_grid_cell_size: None,
+            parallel: true,
         }
     }
 }
@@ -38,272 +128,853 @@ impl Default for NavmeshSettings {
     }
 }
 
+/// Walkable polygons generated from brushes, and the links between them.
+///
+/// The navmesh is `Send + Sync`, so queries may run from several threads at once given a
+/// separate [`crate::astar::PathfindingContext`] for each thread.
+///
+/// With the `serde` feature the navmesh can be baked offline. Polygon and link indices are kept
+/// as is, but the clusters and the grid accelerating point queries are not serialized. Queries
+/// fall back to testing every polygon until [`Self::rebuild_spatial_index`] or
+/// [`Self::generate_links`] is called.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Navmesh {
     brush_polygons: Vec<Face>,
     walkable_polygons: Slab<Face>,
     polygon_links: BTreeMap<usize, Vec<usize>>,
     links: Slab<NavmeshLink>,
     settings: NavmeshSettings,
+    obstacles: Slab<Obstacle>,
+    /// Obstacles currently blocking each polygon
+    blocked: BTreeMap<usize, BTreeSet<ObstacleId>>,
+    /// Obstacles currently blocking each link
+    disabled_links: BTreeMap<usize, BTreeSet<ObstacleId>>,
+    /// Connected region of each polygon, indexed by polygon
+    regions: Vec<usize>,
+    polygon_ids: PolygonIds,
+    region_count: usize,
+    /// Authored links, which are reapplied whenever the links are generated
+    offmesh_links: Vec<OffMeshLink>,
+    /// Volumes removed from the walkable polygons until restored
+    carves: Slab<Carve>,
+    /// Polygons clipped by the carves
+    carved_polygons: Vec<CarvedPolygon>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clusters: Option<Clusters>,
+    /// Rebuilt along with the links, and skipped by queries when missing
+    #[cfg_attr(feature = "serde", serde(skip))]
+    query_grid: Option<QueryGrid>,
+}
+
+// Queries are shared between threads, which must not be broken by future fields
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Navmesh>();
+};
+
+/// Returns the parts of a walkable face with enough headroom above them.
+///
+/// Faces partially covered by a low ceiling are split along the outline of the ceiling faces
+/// above them, so that only the covered parts are removed.
+fn clear_parts(
+    tree: &CsgGroups,
+    face: Face,
+    brush_polygons: &[Face],
+    settings: &NavmeshSettings,
+) -> Vec<Face> {
+    if has_clearance(tree, &face, settings) {
+        return vec![face];
+    }
+
+    let (min, max) = face
+        .points()
+        .iter()
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+            (min.min(p), max.max(p))
+        });
+
+    // Downward facing faces overlapping the face from above
+    let ceilings = brush_polygons.iter().filter(|ceiling| {
+        let points = ceiling.points();
+        ceiling.normal().y < 0.0
+            && points
+                .iter()
+                .any(|p| p.y > min.y && p.y < max.y + settings.agent_height)
+            && points.iter().any(|p| p.x > min.x)
+            && points.iter().any(|p| p.x < max.x)
+            && points.iter().any(|p| p.z > min.z)
+            && points.iter().any(|p| p.z < max.z)
+    });
+
+    let mut parts = vec![face];
+    for ceiling in ceilings {
+        for (a, b) in ceiling.edges() {
+            let normal = (b - a).cross(Vec3::Y).normalize_or_zero();
+            if normal == Vec3::ZERO {
+                continue;
+            }
+
+            let plane = Plane::new(normal, a.dot(normal));
+            let (mut front, mut back) = (Vec::new(), Vec::new());
+            for part in &parts {
+                plane.split_face_with_epsilon(part, &mut front, &mut back, settings.epsilon);
+            }
+
+            front.append(&mut back);
+            parts = front;
+        }
+    }
+
+    // Splitting along the edges of the ceilings may leave slivers behind
+    parts.retain(|v| v.area() >= settings.min_polygon_area && has_clearance(tree, v, settings));
+    parts
+}
+
+/// Returns true if there is at least `agent_height` of headroom above the face.
+///
+/// The ceiling is sampled above the centroid and slightly inset vertices of the face. Both the
+/// face and the ceiling are offset by the agent radius through the inflation, which is added
+/// back to the measured clearance.
+fn has_clearance(tree: &CsgGroups, face: &Face, settings: &NavmeshSettings) -> bool {
+    let centroid = face.centroid();
+    let offset = Vec3::Y * settings.epsilon * 2.0;
+
+    let samples = face
+        .points()
+        .iter()
+        .map(|&p| p.lerp(centroid, 0.05))
+        .chain([centroid]);
+
+    samples
+        .into_iter()
+        .all(|point| match tree.raycast(point + offset, Vec3::Y) {
+            Some(hit) if hit.entering => {
+                hit.t + settings.epsilon * 2.0 + settings.agent_radius * 2.0
+                    >= settings.agent_height
+            }
+            _ => true,
+        })
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Obstacle {
+    shape: ObstacleShape,
+    polygons: Vec<usize>,
+    links: Vec<usize>,
 }
 
 impl Navmesh {
-    pub fn new<'a>(
+    pub fn new(
+        settings: NavmeshSettings,
+        brushes: impl IntoIterator<Item = impl Into<PositionedBrush>>,
+    ) -> Self {
+        Self::new_with_report(settings, brushes).0
+    }
+
+    /// Generates a navmesh along with statistics of each stage of the generation
+    pub fn new_with_report(
         settings: NavmeshSettings,
-        brushes: impl IntoIterator<Item = PositionedBrush>,
+        brushes: impl IntoIterator<Item = impl Into<PositionedBrush>>,
+    ) -> (Self, NavmeshBuildReport) {
+        let mut this = Self {
+            settings,
+            walkable_polygons: Slab::new(),
+            links: Slab::new(),
+            polygon_links: Default::default(),
+            brush_polygons: Vec::new(),
+            obstacles: Slab::new(),
+            blocked: BTreeMap::new(),
+            disabled_links: BTreeMap::new(),
+            regions: Vec::new(),
+            polygon_ids: PolygonIds::default(),
+            region_count: 0,
+            offmesh_links: Vec::new(),
+            carves: Slab::new(),
+            carved_polygons: Vec::new(),
+            clusters: None,
+            query_grid: None,
+        };
+
+        let report = this.rebuild_with_report(brushes);
+        (this, report)
+    }
+
+    /// Regenerates the navmesh from a new set of brushes, reusing the existing allocations.
+    ///
+    /// The settings, obstacles and carves are kept, and reapplied to the new polygons.
+    pub fn rebuild(&mut self, brushes: impl IntoIterator<Item = impl Into<PositionedBrush>>) {
+        self.rebuild_with_report(brushes);
+    }
+
+    /// Regenerates the navmesh like [`Self::rebuild`], returning statistics of each stage
+    pub fn rebuild_with_report(
+        &mut self,
+        brushes: impl IntoIterator<Item = impl Into<PositionedBrush>>,
+    ) -> NavmeshBuildReport {
+        self.rebuild_csg_with_report(brushes.into_iter().map(|v| (CsgOp::Union, v)))
+    }
+
+    /// Generates a navmesh from brushes combined in order by each operation, such as
+    /// subtracting a doorway from a wall.
+    ///
+    /// Each operation is applied to the combined volume of the brushes before it, and
+    /// subtracting from nothing has no effect.
+    pub fn from_csg(
+        settings: NavmeshSettings,
+        ops: impl IntoIterator<Item = (CsgOp, impl Into<PositionedBrush>)>,
     ) -> Self {
+        Self::from_csg_with_report(settings, ops).0
+    }
+
+    /// Generates a navmesh like [`Self::from_csg`], along with statistics of each stage
+    pub fn from_csg_with_report(
+        settings: NavmeshSettings,
+        ops: impl IntoIterator<Item = (CsgOp, impl Into<PositionedBrush>)>,
+    ) -> (Self, NavmeshBuildReport) {
+        let mut this = Self::new(settings, std::iter::empty::<PositionedBrush>());
+        let report = this.rebuild_csg_with_report(ops);
+        (this, report)
+    }
+
+    /// Regenerates the navmesh from brushes combined by each operation, returning statistics of
+    /// each stage.
+    ///
+    /// See [`Self::from_csg`].
+    pub fn rebuild_csg_with_report(
+        &mut self,
+        ops: impl IntoIterator<Item = (CsgOp, impl Into<PositionedBrush>)>,
+    ) -> NavmeshBuildReport {
+        let settings = self.settings;
+        let mut report = NavmeshBuildReport::default();
         let agent_radius = settings.agent_radius;
 
-        let brushes = brushes
-            .into_iter()
-            .filter_map(|brush| {
-                // inflate and transform each brush
-                let faces = brush
-                    .brush()
-                    .faces()
-                    .iter()
-                    .map(|face| {
-                        face.map(|p| {
-                            brush
-                                .transform()
-                                .transform_point3(p + p.signum() * agent_radius)
-                        })
-                    })
-                    .collect_vec();
+        let span = tracing::info_span!("navmesh::csg").entered();
 
-                BspTree::build(&faces)
-            })
+        let ops = ops
+            .into_iter()
+            .map(|(op, brush)| (op, brush.into()))
+            .enumerate()
             .collect_vec();
 
-        let tree = brushes.into_iter().reduce(|mut brush, other| {
-            brush.union(other);
-            brush
-        });
+        // Brushes are prepared and built into trees independently, so only combining them is
+        // done in order
+        let mut brushes = par_map(&ops, settings.parallel, |(index, (op, brush))| {
+            let (index, op) = (*index, *op);
+            let brush = Brush::new(brush.world_faces().collect_vec());
 
-        let mut brush_polygons = Vec::new();
-        let mut output_faces = Slab::new();
-        for face in tree.map(|v| v.polygons()).into_iter().flatten() {
-            if face.normal().dot(Vec3::Y) > settings.max_slope_cosine {
-                output_faces.insert(face);
+            if let Err(defects) = brush.validate() {
+                tracing::warn!(index, ?defects, "brush is malformed");
+            }
+
+            // Brushes are inflated in world space so that rotated and scaled brushes are
+            // grown evenly. Only closed convex brushes can be inflated, others are used as is.
+            // Subtracted brushes are eroded instead, so that the result stays the Minkowski
+            // offset of the combined solid.
+            let radius = match op {
+                CsgOp::Union => agent_radius,
+                CsgOp::Subtract => -agent_radius,
+            };
+
+            if agent_radius > 0.0 {
+                (op, brush.inflated(radius).unwrap_or(brush))
             } else {
-                brush_polygons.push(face);
+                (op, brush)
             }
+        });
+
+        // The result depends on the order of the brushes, so sort each run of the same
+        // operation to generate the same navmesh regardless of the order they were given in
+        for run in brushes.chunk_by_mut(|a, b| a.0 == b.0) {
+            run.sort_by_cached_key(|v| (v.1.canonical_key(), v.1.faces().first().map(Face::flags)));
         }
 
-        let mut this = Self {
-            settings,
-            walkable_polygons: output_faces,
-            links: Slab::new(),
-            polygon_links: Default::default(),
-            brush_polygons,
+        report.brush_count = brushes.len();
+
+        let trees = par_map(&brushes, settings.parallel, |(_, brush)| {
+            BspTree::build_with_parallelism(brush.faces(), settings.epsilon, settings.parallel)
+        });
+
+        // Only brushes which may touch are clipped against each other, rather than every brush
+        // against all brushes before it
+        let mut tree = CsgGroups::new(settings.epsilon);
+        for ((op, brush), other) in brushes.iter().zip(trees) {
+            let Some(other) = other else {
+                report.rejected_brushes += 1;
+                continue;
+            };
+
+            match op {
+                CsgOp::Union => tree.union(brush.faces(), other),
+                CsgOp::Subtract => tree.subtract(brush.faces(), other),
+            }
+        }
+
+        tree.finish();
+        let tree = (!tree.is_empty()).then_some(tree);
+
+        // The tree is only kept around when needed for the clearance checks
+        let (mut faces, tree) = match tree {
+            Some(tree) if settings.agent_height > 0.0 => {
+                (tree.polygons_cleaned(settings.min_polygon_area), Some(tree))
+            }
+            Some(tree) => (tree.into_polygons_cleaned(settings.min_polygon_area), None),
+            None => (Vec::new(), None),
+        };
+
+        faces.sort_by_cached_key(|v| v.canonical_key());
+        report.polygon_count = faces.len();
+
+        let (walkable, brush_polygons): (Vec<_>, Vec<_>) = faces.into_iter().partition(|face| {
+            let cosine = face.normal().dot(Vec3::Y);
+            if face.flags().walkable && cosine <= settings.max_slope_cosine {
+                report.add_rejected_slope(cosine);
+            }
+
+            face.flags().walkable && cosine > settings.max_slope_cosine
+        });
+
+        let walkable = match &tree {
+            Some(tree) => walkable
+                .into_iter()
+                .flat_map(|face| clear_parts(tree, face, &brush_polygons, &settings))
+                .collect_vec(),
+            None => walkable,
+        };
+
+        let walkable = if settings.merge_polygons {
+            merge::merge_coplanar(walkable, settings.epsilon)
+        } else {
+            walkable
         };
 
-        this.generate_links();
-        this
+        drop(span);
+
+        self.brush_polygons = brush_polygons;
+        self.walkable_polygons.clear();
+        for face in walkable {
+            self.walkable_polygons.insert(face);
+        }
+
+        self.apply_carves();
+        self.polygon_ids = PolygonIds::new(&self.walkable_polygons);
+        self.apply_obstacles();
+        report.edge_plane_count = self.link_edge_planes();
+        report.walkable_count = self.walkable_polygons.len();
+        report.count_links(self);
+
+        report
+    }
+
+    /// Recomputes the polygons blocked by each obstacle
+    fn apply_obstacles(&mut self) {
+        self.blocked.clear();
+        for (index, obstacle) in &mut self.obstacles {
+            obstacle.polygons.clear();
+            obstacle.polygons.extend(
+                self.walkable_polygons
+                    .iter()
+                    .filter(|v| obstacle.shape.covers_face(v.1))
+                    .map(|v| v.0),
+            );
+
+            for &polygon in &obstacle.polygons {
+                self.blocked
+                    .entry(polygon)
+                    .or_default()
+                    .insert(ObstacleId(index));
+            }
+        }
+    }
+
+    /// Recomputes the links blocked by each obstacle, as link indices change whenever links are
+    /// added
+    fn apply_obstacle_links(&mut self) {
+        self.disabled_links.clear();
+        for (index, obstacle) in &mut self.obstacles {
+            obstacle.links.clear();
+            obstacle.links.extend(
+                self.links
+                    .iter()
+                    .filter(|v| obstacle.shape.intersects_link(v.1.kind()))
+                    .map(|v| v.0),
+            );
+
+            for &link in &obstacle.links {
+                self.disabled_links
+                    .entry(link)
+                    .or_default()
+                    .insert(ObstacleId(index));
+            }
+        }
     }
 
+    /// Returns the polygons which are not blocked by any obstacle
     pub fn walkable_polygons(&self) -> impl Iterator<Item = (usize, &Face)> {
-        self.walkable_polygons.iter()
+        self.walkable_polygons
+            .iter()
+            .filter(|v| !self.is_blocked(v.0))
+    }
+
+    /// Returns the total area of the polygons which are not blocked by any obstacle
+    pub fn total_walkable_area(&self) -> f32 {
+        self.walkable_polygons().map(|v| v.1.area()).sum()
+    }
+
+    /// Returns the polygon the point is standing on.
+    ///
+    /// Polygons below the point are preferred, and polygons up to `max_step_height` above the
+    /// point are considered if there is nothing below. If the point is off the navmesh, the
+    /// nearest polygon within `search_radius` is used instead.
+    ///
+    /// The distance from the point to the surface of the polygon is returned along with it, so
+    /// that far matches can be rejected.
+    pub fn closest_polygon(&self, point: Vec3) -> Option<(usize, Face, f32)> {
+        self.closest_polygon_within(point, self.settings.max_step_height, f32::INFINITY)
+            .or_else(|| self.nearest_polygon(point, self.settings.search_radius))
+    }
+
+    /// Returns the polygon with the closest surface point within `max_distance`
+    pub fn nearest_polygon(&self, point: Vec3, max_distance: f32) -> Option<(usize, Face, f32)> {
+        let nearest = |radius: f32| {
+            self.polygons_near(point, radius)
+                .map(|v| (v.0, v.1, v.1.closest_point(point).distance(point)))
+                .filter(|v| v.2 <= radius)
+                .min_by_key(|v| ordered_float::OrderedFloat(v.2))
+        };
+
+        // Search a growing radius, as the nearest polygon may lie outside the cells around the
+        // point
+        let mut radius = self
+            .query_grid
+            .as_ref()
+            .map_or(max_distance, |v| v.cell_size().min(max_distance));
+
+        loop {
+            if let Some((index, face, distance)) = nearest(radius) {
+                return Some((index, face.clone(), distance));
+            }
+
+            if radius >= max_distance {
+                return None;
+            }
+
+            radius = (radius * 2.0).min(max_distance);
+        }
+    }
+
+    /// Projects the point onto the closest point of the walkable polygons within `max_distance`.
+    ///
+    /// Unlike [`Self::closest_polygon`], the point does not need to be above a polygon, so points
+    /// drifting just past the edge of a ledge are moved back onto it.
+    pub fn project_point(&self, point: Vec3, max_distance: f32) -> Option<(usize, Vec3)> {
+        let (index, face, _) = self.nearest_polygon(point, max_distance)?;
+        Some((index, face.closest_point(point)))
+    }
+
+    /// Returns the walkable polygons which may lie within `radius` of the point horizontally, in
+    /// the same order as [`Self::walkable_polygons`]
+    fn polygons_near(&self, point: Vec3, radius: f32) -> impl Iterator<Item = (usize, &Face)> {
+        match self
+            .query_grid
+            .as_ref()
+            .and_then(|v| v.query(point, radius))
+        {
+            Some(polygons) => {
+                let polygons = match polygons {
+                    Cow::Borrowed(v) => Either::Left(v.iter().copied()),
+                    Cow::Owned(v) => Either::Right(v.into_iter()),
+                };
+
+                Either::Left(
+                    polygons
+                        .filter(|&v| !self.is_blocked(v))
+                        .map(|v| (v, &self.walkable_polygons[v])),
+                )
+            }
+            None => Either::Right(self.walkable_polygons()),
+        }
+    }
+
+    /// Returns the closest polygon below the point within `max_below`, or the closest polygon
+    /// above the point within `max_above` if there is none below.
+    ///
+    /// Only polygons whose footprint contains the point are considered, and the vertical
+    /// distance to the polygon is returned along with it.
+    pub fn closest_polygon_within(
+        &self,
+        point: Vec3,
+        max_above: f32,
+        max_below: f32,
+    ) -> Option<(usize, Face, f32)> {
+        let candidates = || {
+            self.polygons_near(point, 0.0)
+                .filter_map(|(index, face)| {
+                    let projection = face.vertical_projection(point)?;
+                    (projection.horizontal_distance <= self.settings.epsilon).then_some((
+                        index,
+                        face,
+                        projection.height,
+                    ))
+                })
+                .filter(|v| v.2 >= -max_above && v.2 <= max_below)
+        };
+
+        let below = candidates()
+            .filter(|v| v.2 >= -self.settings.epsilon)
+            .min_by_key(|v| ordered_float::OrderedFloat(v.2));
+
+        below
+            .or_else(|| candidates().min_by_key(|v| ordered_float::OrderedFloat(v.2.abs())))
+            .map(|(index, face, height)| (index, face.clone(), height.abs()))
     }
 
-    pub fn closest_polygon(&self, point: Vec3) -> Option<(usize, Face)> {
+    /// Returns the bounding box of the walkable polygons as `(min, max)`.
+    ///
+    /// An empty navmesh yields an inverted box with `min > max`.
+    pub fn aabb(&self) -> (Vec3, Vec3) {
         self.walkable_polygons
             .iter()
-            .filter(|v| v.1.contains_point(point))
-            .map(|v| (v.0, v.1, v.1.distance_to_plane(point)))
-            // .filter(|v| v.2 >= -TOLERANCE)
-            .min_by_key(|v| ordered_float::OrderedFloat(v.2))
-            .map(|(index, &face, _)| (index, face))
+            .flat_map(|(_, face)| face.points())
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| {
+                (min.min(p), max.max(p))
+            })
     }
 
-    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<Vec<Waypoint>> {
-        astar(self, start, end, |a, b| a.distance(b))
+    /// Returns true if the point is within the bounds of the walkable polygons, grown by
+    /// `search_radius` and `agent_height`.
+    ///
+    /// Used to pick which of several navmeshes a query belongs to.
+    pub fn bounds_contain(&self, point: Vec3) -> bool {
+        let (min, max) = self.aabb();
+        let margin = Vec3::splat(self.settings.search_radius);
+        let headroom = Vec3::Y * self.settings.agent_height;
+
+        point.cmpge(min - margin).all() && point.cmple(max + margin + headroom).all()
+    }
+
+    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<Path> {
+        self.find_path_with_options(start, end, AstarOptions::default())
+            .ok()
+    }
+
+    /// Finds the shortest path, smoothed according to the options
+    pub fn find_path_with_options(
+        &self,
+        start: Vec3,
+        end: Vec3,
+        options: AstarOptions,
+    ) -> Result<Path, PathError> {
+        astar_with_options(self, start, end, |a, b| a.distance(b), options)
+    }
+
+    /// Groups the edges of all walkable polygons by the vertical plane they lie in.
+    ///
+    /// Planes are clustered rather than discretized, so that planes within `epsilon` of each
+    /// other always share a group regardless of where they fall relative to a rounding boundary.
+    fn edge_planes(&self) -> Vec<EdgeLinkPlane> {
+        self.edge_planes_of(self.walkable_polygons.iter())
+    }
+
+    /// Groups the edges of the given polygons like [`Self::edge_planes`]
+    fn edge_planes_of<'a>(
+        &self,
+        polygons: impl IntoIterator<Item = (usize, &'a Face)>,
+    ) -> Vec<EdgeLinkPlane> {
+        let epsilon = self.settings.epsilon;
+
+        let mut edges = polygons
+            .into_iter()
+            .flat_map(|(id, face)| {
+                face.edges()
+                    .map(move |(p1, p2)| PolygonEdge::new(id, p1, p2))
+            })
+            .map(|edge| {
+                let mut plane = edge.as_vertical_plane().canonicalize(epsilon);
+
+                // Canonical angles lie within [-pi/2, pi/2], where both ends describe the same
+                // plane. Flip planes near the lower end so they meet their counterparts.
+                if plane.angle < -FRAC_PI_2 + epsilon {
+                    plane = VerticalPlane::new(-plane.normal, -plane.distance);
+                }
+
+                (plane, edge)
+            })
+            .collect_vec();
+
+        edges.sort_by(|a, b| a.0.angle.total_cmp(&b.0.angle));
+
+        let mut edgeplanes = Vec::new();
+
+        // Compare against the first plane of each cluster rather than the previous one, to avoid
+        // chaining many slightly different planes together
+        let mut edges = &mut edges[..];
+        while let Some(first) = edges.first() {
+            let angle = first.0.angle;
+            let len = edges
+                .iter()
+                .position(|v| v.0.angle - angle > epsilon)
+                .unwrap_or(edges.len());
+
+            let (group, rest) = edges.split_at_mut(len);
+            edges = rest;
+
+            group.sort_by(|a, b| a.0.distance.total_cmp(&b.0.distance));
+
+            let mut group = &group[..];
+            while let Some(first) = group.first() {
+                let distance = first.0.distance;
+                let len = group
+                    .iter()
+                    .position(|v| v.0.distance - distance > epsilon)
+                    .unwrap_or(group.len());
+
+                let mut plane = EdgeLinkPlane::new(VerticalPlane::new(first.0.normal, distance));
+                for (_, edge) in &group[..len] {
+                    if edge.as_vertical_plane().normal.dot(plane.normal) > 0.0 {
+                        plane.front.push(edge.clone());
+                    } else {
+                        plane.back.push(edge.clone());
+                    }
+                }
+
+                edgeplanes.push(plane);
+                group = &group[len..];
+            }
+        }
+
+        edgeplanes
     }
 
     pub fn generate_links(&mut self) {
-        let mut edgeplanes: BTreeMap<_, EdgeLinkPlane> = BTreeMap::new();
+        self.link_edge_planes();
+    }
+
+    /// Rebuilds the grid accelerating point queries such as [`Self::closest_polygon`] and
+    /// [`Self::project_point`] over the current walkable polygons.
+    ///
+    /// This is done when generating links, but is cheaper when only the queries need to be
+    /// restored, such as for a deserialized navmesh.
+    pub fn rebuild_spatial_index(&mut self) {
+        self.query_grid = Some(QueryGrid::new(
+            &self.walkable_polygons,
+            self.settings.query_grid_cell_size,
+            self.settings.epsilon,
+        ));
+    }
+
+    /// Generates the links, returning the number of distinct edge planes
+    fn link_edge_planes(&mut self) -> usize {
+        let _span = tracing::info_span!("navmesh::links").entered();
+        let edgeplanes = self.edge_planes();
 
         self.polygon_links.clear();
         self.links.clear();
+        self.clusters = None;
+        self.rebuild_spatial_index();
 
-        let mut create_link = |link: NavmeshLink| {
-            let index = self.links.insert(link);
-            self.polygon_links
-                .entry(link.from())
-                .or_default()
-                .push(index);
+        // Planes are matched independently, but the links are added in the order of the planes
+        // to keep the indices deterministic
+        let settings = self.settings;
+        let candidates = par_map(&edgeplanes, settings.parallel, |plane| {
+            plane_links(plane, &settings)
+        });
+        self.insert_links(candidates.into_iter().flatten());
 
-            let index = self.links.insert(link.reverse());
+        self.apply_offmesh_links();
+        self.apply_obstacle_links();
+        self.compute_regions();
 
-            self.polygon_links.entry(link.to()).or_default().push(index);
-        };
+        #[cfg(feature = "strict-validation")]
+        {
+            let report = self.validate();
+            debug_assert!(report.is_ok(), "{report}");
+        }
+
+        edgeplanes.len()
+    }
 
-        // Assign edge to vertplanes
-        for (id, face) in &self.walkable_polygons {
-            for (p1, p2) in face.edges() {
-                let edge = PolygonEdge::new(id, p1, p2);
+    /// Adds the links matched between edge planes in order, along with their reverse.
+    ///
+    /// Links which only touch at a point or span too large a gap are discarded, as are links
+    /// already emitted between the same edges.
+    fn insert_links(&mut self, candidates: impl IntoIterator<Item = NavmeshLink>) {
+        let epsilon = self.settings.epsilon;
+        let min_link_width = self.settings.min_link_width;
+        let max_step_height = self.settings.max_step_height;
+        let max_drop_height = self.settings.max_drop_height;
 
-                let plane = edge.as_vertical_plane();
+        // Overlapping edges of the same polygons may be matched more than once, such as in
+        // neighbouring planes, so keep track of the links emitted between each pair
+        let mut emitted: BTreeMap<(usize, usize), Vec<(Edge3D, Edge3D)>> = BTreeMap::new();
+        let mut emit = |link: &NavmeshLink| {
+            let same = |a: Edge3D, b: Edge3D| {
+                (a.p1.distance(b.p1) < epsilon && a.p2.distance(b.p2) < epsilon)
+                    || (a.p1.distance(b.p2) < epsilon && a.p2.distance(b.p1) < epsilon)
+            };
 
-                let canonical_plane = plane.canonicalize();
+            let edges = (link.source_edge(), link.destination_edge());
+            let existing = emitted.entry((link.from(), link.to())).or_default();
+            if existing
+                .iter()
+                .any(|v| same(v.0, edges.0) && same(v.1, edges.1))
+            {
+                return false;
+            }
 
-                let disc_angle = (((canonical_plane.angle + TAU) % TAU) * 1024.0).round() as u32;
-                let distance = (canonical_plane.distance * 256.0).round() as i32;
+            existing.push(edges);
+            true
+        };
 
-                if plane.normal.dot(canonical_plane.normal) > 0.0 {
-                    edgeplanes
-                        .entry((disc_angle, distance))
-                        .or_insert_with(|| EdgeLinkPlane::new(canonical_plane))
-                        .front
-                        .push(edge);
-                } else {
-                    edgeplanes
-                        .entry((disc_angle, distance))
-                        .or_insert_with(|| EdgeLinkPlane::new(canonical_plane))
-                        .back
-                        .push(edge);
+        let mut create_link = |link: NavmeshLink| {
+            // Discard links which only touch at a point, and edges of the same polygon which
+            // share a vertical plane
+            if link.from() == link.to()
+                || link.source_edge().length() < 2.0 * epsilon
+                || link.destination_edge().length() < 2.0 * epsilon
+                || link.width() < min_link_width
+            {
+                return;
+            }
+
+            // The edges may be clamped to their polygons away from where the heights were
+            // matched, so check the actual vertical gap at both ends. This keeps edges of
+            // stacked floors in the same vertical plane from being linked.
+            let (s, d) = (link.source_edge(), link.destination_edge());
+            let gap = (s.p1.y - d.p1.y).abs().max((s.p2.y - d.p2.y).abs());
+            let max_gap = match link.kind() {
+                LinkKind::Walk(_) | LinkKind::StepUp(..) | LinkKind::StepDown(..) => {
+                    max_step_height
+                }
+                LinkKind::Drop(..) => max_drop_height,
+                LinkKind::OffMesh { .. } => f32::INFINITY,
+            };
+
+            if gap > max_gap + epsilon {
+                return;
+            }
+
+            for link in [Some(link), link.reverse()].into_iter().flatten() {
+                if emit(&link) {
+                    let index = self.links.insert(link);
+                    self.polygon_links
+                        .entry(link.from())
+                        .or_default()
+                        .push(index);
                 }
             }
+        };
+
+        for link in candidates {
+            create_link(link);
         }
+    }
+
+    /// Returns the polygons adjacent to `polygon` and the links leading to them
+    pub fn neighbors(&self, polygon: usize) -> impl Iterator<Item = Neighbor<'_>> {
+        self.polygon_links(polygon).iter().map(|&index| Neighbor {
+            link_index: index,
+            link: &self.links[index],
+        })
+    }
 
-        for plane in edgeplanes.values() {
-            for back_edge in &plane.back {
-                let back_interval = plane.plane.coplanar_interval(back_edge);
+    /// Returns the polygons reachable through a link from `polygon`, each only once
+    pub fn neighbor_polygons(&self, polygon: usize) -> impl Iterator<Item = usize> + '_ {
+        self.neighbors(polygon).map(|v| v.polygon()).unique()
+    }
 
-                for front_edge in &plane.front {
-                    let front_interval = plane.plane.coplanar_interval(front_edge);
-                    let overlap = back_interval.intersect(front_interval);
+    /// Returns a link leading from polygon `a` to polygon `b`
+    pub fn link_between(&self, a: usize, b: usize) -> Option<&NavmeshLink> {
+        self.links_between(a, b).next().map(|v| v.1)
+    }
 
-                    if overlap.is_empty() {
-                        continue;
-                    }
+    /// Returns all links leading from polygon `a` to polygon `b` along with their indices
+    pub fn links_between(
+        &self,
+        a: usize,
+        b: usize,
+    ) -> impl Iterator<Item = (usize, &NavmeshLink)> + '_ {
+        self.neighbors(a)
+            .filter(move |v| v.polygon() == b)
+            .map(|v| (v.link_index(), v.link()))
+    }
+
+    /// Blocks all polygons covered by the obstacle and links intersecting it until it is
+    /// removed.
+    ///
+    /// Polygons are kept as is, but are excluded from queries and searches. Only polygons lying
+    /// entirely within the shape are blocked, so a small obstacle does not close off a large
+    /// polygon it stands on. Links are blocked separately whenever they pass through the shape,
+    /// so that partially covered polygons and off-mesh links are cut off as well.
+    pub fn add_obstacle(&mut self, shape: impl Into<ObstacleShape>) -> ObstacleId {
+        let shape = shape.into();
+        let polygons = self
+            .walkable_polygons
+            .iter()
+            .filter(|v| shape.covers_face(v.1))
+            .map(|v| v.0)
+            .collect_vec();
+
+        let links = self
+            .links
+            .iter()
+            .filter(|v| shape.intersects_link(v.1.kind()))
+            .map(|v| v.0)
+            .collect_vec();
+
+        let id = ObstacleId(self.obstacles.vacant_key());
+
+        for &polygon in &polygons {
+            self.blocked.entry(polygon).or_default().insert(id);
+        }
+
+        for &link in &links {
+            self.disabled_links.entry(link).or_default().insert(id);
+        }
+
+        self.obstacles.insert(Obstacle {
+            shape,
+            polygons,
+            links,
+        });
+
+        id
+    }
+
+    /// Removes an obstacle, restoring the polygons and links it blocked.
+    ///
+    /// Returns the shape of the removed obstacle
+    pub fn remove_obstacle(&mut self, id: ObstacleId) -> Option<ObstacleShape> {
+        let obstacle = self.obstacles.try_remove(id.0)?;
 
-                    let s = plane.plane.coplanar_edge(back_edge);
-                    let d = plane.plane.coplanar_edge(front_edge);
-
-                    // let e1_len = e1.1 - e1.0;
-                    // let e2_len = e2.1 - e2.0;
-
-                    // let m_s = e1_len.y / e1_len.x;
-                    // let m_d = e2_len.y / e2_len.x;
-
-                    let m_s = (s.1.y - s.0.y) / (s.1.x - s.0.x);
-                    let m_d = (d.1.y - d.0.y) / (d.1.x - d.0.x);
-
-                    let c_s = s.0.y - m_s * s.0.x;
-                    let c_d = d.0.y - m_d * d.0.x;
-
-                    let delta_m = m_d - m_s;
-                    let delta_c = c_d - c_s;
-
-                    if delta_m.abs() > TOLERANCE {
-                        let walk_x = -delta_c / delta_m;
-
-                        let step_up_x = (self.settings.max_step_height - delta_c) / delta_m;
-
-                        let step_down_x = (-self.settings.max_step_height - delta_c) / delta_m;
-
-                        let step_down = Span::new(walk_x.min(step_down_x), walk_x.max(step_down_x));
-                        let step_up = Span::new(walk_x.min(step_up_x), walk_x.max(step_up_x));
-
-                        let step_down = step_down.intersect(overlap);
-                        let step_up = step_up.intersect(overlap);
-
-                        if !step_down.is_empty() {
-                            let s_low = vec2(step_down.min, m_s * step_down.min + c_s);
-                            let s_high = vec2(step_down.max, m_s * step_down.max + c_s);
-
-                            let d_low = vec2(step_down.min, m_d * step_down.min + c_d);
-                            let d_high = vec2(step_down.max, m_d * step_down.max + c_d);
-
-                            create_link(NavmeshLink::new(
-                                front_edge.polygon(),
-                                back_edge.polygon(),
-                                LinkKind::StepUp(
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(s_low),
-                                        plane.coplanar_to_world(s_high),
-                                    ),
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(d_low),
-                                        plane.coplanar_to_world(d_high),
-                                    ),
-                                ),
-                            ));
-                        }
-
-                        if !step_up.is_empty() {
-                            let s_low = vec2(step_up.min, m_s * step_up.min + c_s);
-                            let s_high = vec2(step_up.max, m_s * step_up.max + c_s);
-
-                            let d_low = vec2(step_up.min, m_d * step_up.min + c_d);
-                            let d_high = vec2(step_up.max, m_d * step_up.max + c_d);
-
-                            create_link(NavmeshLink::new(
-                                front_edge.polygon(),
-                                back_edge.polygon(),
-                                LinkKind::StepUp(
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(d_low),
-                                        plane.coplanar_to_world(d_high),
-                                    ),
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(s_low),
-                                        plane.coplanar_to_world(s_high),
-                                    ),
-                                ),
-                            ));
-                        }
-                    } else if delta_c.abs() < self.settings.max_step_height {
-                        let s1 = vec2(overlap.min, m_s * overlap.min + c_s);
-                        let s2 = vec2(overlap.max, m_s * overlap.max + c_s);
-
-                        let d1 = vec2(overlap.min, m_d * overlap.min + c_d);
-                        let d2 = vec2(overlap.max, m_d * overlap.max + c_d);
-
-                        if delta_c > TOLERANCE {
-                            create_link(NavmeshLink::new(
-                                back_edge.polygon(),
-                                front_edge.polygon(),
-                                LinkKind::StepUp(
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(s1),
-                                        plane.coplanar_to_world(s2),
-                                    ),
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(d1),
-                                        plane.coplanar_to_world(d2),
-                                    ),
-                                ),
-                            ));
-                        } else if delta_c < -TOLERANCE {
-                            create_link(NavmeshLink::new(
-                                front_edge.polygon(),
-                                back_edge.polygon(),
-                                LinkKind::StepUp(
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(d1),
-                                        plane.coplanar_to_world(d2),
-                                    ),
-                                    Edge3D::new(
-                                        plane.coplanar_to_world(s1),
-                                        plane.coplanar_to_world(s2),
-                                    ),
-                                ),
-                            ));
-                        } else {
-                            create_link(NavmeshLink::new(
-                                front_edge.polygon(),
-                                back_edge.polygon(),
-                                LinkKind::Walk(Edge3D::new(
-                                    plane.coplanar_to_world(s1),
-                                    plane.coplanar_to_world(s2),
-                                )),
-                            ));
-                        }
+        let release = |blocked: &mut BTreeMap<usize, BTreeSet<ObstacleId>>, keys: Vec<usize>| {
+            for key in keys {
+                if let Some(obstacles) = blocked.get_mut(&key) {
+                    obstacles.remove(&id);
+                    if obstacles.is_empty() {
+                        blocked.remove(&key);
                     }
                 }
             }
-        }
+        };
+
+        release(&mut self.blocked, obstacle.polygons);
+        release(&mut self.disabled_links, obstacle.links);
+
+        Some(obstacle.shape)
+    }
+
+    /// Returns true if the polygon is covered by an obstacle
+    pub fn is_blocked(&self, polygon: usize) -> bool {
+        self.blocked.contains_key(&polygon)
+    }
+
+    /// Returns true if the link passes through an obstacle
+    pub fn is_link_disabled(&self, link: usize) -> bool {
+        self.disabled_links.contains_key(&link)
+    }
+
+    pub fn settings(&self) -> &NavmeshSettings {
+        &self.settings
     }
 
     pub fn polygons(&self) -> &Slab<Face> {
@@ -314,8 +985,21 @@ impl Navmesh {
         &self.links
     }
 
-    pub fn polygon_links(&self) -> &BTreeMap<usize, Vec<usize>> {
-        &self.polygon_links
+    /// Returns the area id of the brush the polygon originates from
+    pub fn polygon_area_id(&self, polygon: usize) -> Option<u8> {
+        self.walkable_polygons
+            .get(polygon)
+            .map(|v| v.flags().area_id)
+    }
+
+    /// Returns the indices of the links leading out of `polygon`
+    pub fn polygon_links(&self, polygon: usize) -> &[usize] {
+        self.polygon_links.get(&polygon).map_or(&[], |v| v)
+    }
+
+    /// Returns the link at `index`, or `None` if it does not exist
+    pub fn link(&self, index: usize) -> Option<&NavmeshLink> {
+        self.links.get(index)
     }
 
     pub fn brush_polygons(&self) -> &[Face] {
@@ -323,6 +1007,37 @@ impl Navmesh {
     }
 }
 
+/// A polygon adjacent to another through a link
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbor<'a> {
+    link_index: usize,
+    link: &'a NavmeshLink,
+}
+
+impl<'a> Neighbor<'a> {
+    /// The neighboring polygon
+    pub fn polygon(&self) -> usize {
+        self.link.to()
+    }
+
+    /// The edge shared with the neighbor, on the side of the originating polygon
+    pub fn edge(&self) -> Edge3D {
+        self.link.source_edge()
+    }
+
+    pub fn kind(&self) -> &'a LinkKind {
+        self.link.kind()
+    }
+
+    pub fn link_index(&self) -> usize {
+        self.link_index
+    }
+
+    pub fn link(&self) -> &'a NavmeshLink {
+        self.link
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct EdgeLinkPlane {
     plane: VerticalPlane,
@@ -347,3 +1062,1300 @@ impl EdgeLinkPlane {
         }
     }
 }
+
+/// Returns the links between the overlapping edges on either side of the plane.
+///
+/// The links are not yet filtered or deduplicated against the links of other planes, so that
+/// each plane can be matched independently.
+fn plane_links(plane: &EdgeLinkPlane, settings: &NavmeshSettings) -> Vec<NavmeshLink> {
+    let epsilon = settings.epsilon;
+    let max_step_height = settings.max_step_height;
+    let max_drop_height = settings.max_drop_height;
+
+    let mut links = Vec::new();
+    // Restrict the link edge to the extent of the polygon edge it was derived from
+    let clamp_edge = |edge: &PolygonEdge, a: Vec2, b: Vec2| {
+        let edge = edge.edge();
+        Edge3D::new(
+            edge.closest_point(plane.coplanar_to_world(a)),
+            edge.closest_point(plane.coplanar_to_world(b)),
+        )
+    };
+
+    for back_edge in &plane.back {
+        let back_interval = plane.plane.coplanar_interval(back_edge);
+
+        for front_edge in &plane.front {
+            let front_interval = plane.plane.coplanar_interval(front_edge);
+            let overlap = back_interval.intersect(front_interval);
+
+            if overlap.is_empty() {
+                continue;
+            }
+
+            let s = plane.plane.coplanar_edge(back_edge);
+            let d = plane.plane.coplanar_edge(front_edge);
+
+            // let e1_len = e1.1 - e1.0;
+            // let e2_len = e2.1 - e2.0;
+
+            // let m_s = e1_len.y / e1_len.x;
+            // let m_d = e2_len.y / e2_len.x;
+
+            // Edges without any extent along the plane would have an infinite slope,
+            // so treat them as flat and let the parallel case handle them
+            let slope = |(a, b): (Vec2, Vec2)| {
+                if (b.x - a.x).abs() < epsilon {
+                    0.0
+                } else {
+                    (b.y - a.y) / (b.x - a.x)
+                }
+            };
+
+            let m_s = slope(s);
+            let m_d = slope(d);
+
+            let c_s = s.0.y - m_s * s.0.x;
+            let c_d = d.0.y - m_d * d.0.x;
+
+            let delta_m = m_d - m_s;
+            let delta_c = c_d - c_s;
+
+            // Drop down from the higher edge to the lower edge along `span`
+            let drop_link = |span: Span, from_front: bool| {
+                let s = clamp_edge(
+                    back_edge,
+                    vec2(span.min, m_s * span.min + c_s),
+                    vec2(span.max, m_s * span.max + c_s),
+                );
+
+                let d = clamp_edge(
+                    front_edge,
+                    vec2(span.min, m_d * span.min + c_d),
+                    vec2(span.max, m_d * span.max + c_d),
+                );
+
+                if from_front {
+                    NavmeshLink::new(
+                        front_edge.polygon(),
+                        back_edge.polygon(),
+                        LinkKind::Drop(d, s),
+                    )
+                } else {
+                    NavmeshLink::new(
+                        back_edge.polygon(),
+                        front_edge.polygon(),
+                        LinkKind::Drop(s, d),
+                    )
+                }
+            };
+
+            if delta_m.abs() > epsilon {
+                let walk_x = -delta_c / delta_m;
+
+                let step_up_x = (max_step_height - delta_c) / delta_m;
+
+                let step_down_x = (-max_step_height - delta_c) / delta_m;
+
+                let step_down = Span::new(walk_x.min(step_down_x), walk_x.max(step_down_x));
+                let step_up = Span::new(walk_x.min(step_up_x), walk_x.max(step_up_x));
+
+                let step_down = step_down.intersect(overlap);
+                let step_up = step_up.intersect(overlap);
+
+                if !step_down.is_empty() {
+                    let s_low = vec2(step_down.min, m_s * step_down.min + c_s);
+                    let s_high = vec2(step_down.max, m_s * step_down.max + c_s);
+
+                    let d_low = vec2(step_down.min, m_d * step_down.min + c_d);
+                    let d_high = vec2(step_down.max, m_d * step_down.max + c_d);
+
+                    // The front edge is lower
+                    links.push(NavmeshLink::new(
+                        front_edge.polygon(),
+                        back_edge.polygon(),
+                        LinkKind::StepUp(
+                            clamp_edge(front_edge, d_low, d_high),
+                            clamp_edge(back_edge, s_low, s_high),
+                        ),
+                    ));
+                }
+
+                if !step_up.is_empty() {
+                    let s_low = vec2(step_up.min, m_s * step_up.min + c_s);
+                    let s_high = vec2(step_up.max, m_s * step_up.max + c_s);
+
+                    let d_low = vec2(step_up.min, m_d * step_up.min + c_d);
+                    let d_high = vec2(step_up.max, m_d * step_up.max + c_d);
+
+                    // The front edge is higher
+                    links.push(NavmeshLink::new(
+                        back_edge.polygon(),
+                        front_edge.polygon(),
+                        LinkKind::StepUp(
+                            clamp_edge(back_edge, s_low, s_high),
+                            clamp_edge(front_edge, d_low, d_high),
+                        ),
+                    ));
+                }
+
+                if max_drop_height > max_step_height {
+                    let drop_up_x = (max_drop_height - delta_c) / delta_m;
+                    let drop_down_x = (-max_drop_height - delta_c) / delta_m;
+
+                    // Where the front edge is higher than the back edge
+                    let front_drop = Span::new(step_up_x.min(drop_up_x), step_up_x.max(drop_up_x))
+                        .intersect(overlap);
+
+                    let back_drop =
+                        Span::new(step_down_x.min(drop_down_x), step_down_x.max(drop_down_x))
+                            .intersect(overlap);
+
+                    if !front_drop.is_empty() {
+                        links.push(drop_link(front_drop, true));
+                    }
+
+                    if !back_drop.is_empty() {
+                        links.push(drop_link(back_drop, false));
+                    }
+                }
+            } else if delta_c.abs() < max_step_height {
+                let s1 = vec2(overlap.min, m_s * overlap.min + c_s);
+                let s2 = vec2(overlap.max, m_s * overlap.max + c_s);
+
+                let d1 = vec2(overlap.min, m_d * overlap.min + c_d);
+                let d2 = vec2(overlap.max, m_d * overlap.max + c_d);
+
+                if delta_c > epsilon {
+                    links.push(NavmeshLink::new(
+                        back_edge.polygon(),
+                        front_edge.polygon(),
+                        LinkKind::StepUp(
+                            clamp_edge(back_edge, s1, s2),
+                            clamp_edge(front_edge, d1, d2),
+                        ),
+                    ));
+                } else if delta_c < -epsilon {
+                    links.push(NavmeshLink::new(
+                        front_edge.polygon(),
+                        back_edge.polygon(),
+                        LinkKind::StepUp(
+                            clamp_edge(front_edge, d1, d2),
+                            clamp_edge(back_edge, s1, s2),
+                        ),
+                    ));
+                } else {
+                    links.push(NavmeshLink::new(
+                        front_edge.polygon(),
+                        back_edge.polygon(),
+                        LinkKind::Walk(clamp_edge(back_edge, s1, s2)),
+                    ));
+                }
+            } else if delta_c.abs() <= max_drop_height {
+                links.push(drop_link(overlap, delta_c > 0.0));
+            }
+        }
+    }
+
+    links
+}
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4};
+
+    use crate::{
+        astar::astar,
+        brush::{Brush, BrushFlags, PositionedBrush},
+        obstacle::{Cylinder, Obb},
+        test_util::{cube, slab},
+    };
+
+    use super::*;
+
+    #[test]
+    fn neighbors() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-4.0, 0.0), slab(0.0, 0.0), slab(4.0, 0.0)],
+        );
+
+        // Separated slabs have no neighbours
+        assert_eq!(navmesh.polygons().len(), 3);
+        for (id, _) in navmesh.polygons() {
+            assert_eq!(navmesh.neighbors(id).count(), 0);
+        }
+
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3), slab(2.0, 0.0)],
+        );
+
+        let left = navmesh.closest_polygon(vec3(-2.5, 0.5, 0.0)).unwrap().0;
+        let middle = navmesh.closest_polygon(vec3(0.0, 0.8, 0.0)).unwrap().0;
+        let right = navmesh.closest_polygon(vec3(2.5, 0.5, 0.0)).unwrap().0;
+        assert_eq!(navmesh.polygons().len(), 3);
+
+        // The ends only lead onto the middle, which leads onto both ends
+        let neighbors = |id| navmesh.neighbor_polygons(id).sorted().collect_vec();
+        assert_eq!(neighbors(left), [middle]);
+        assert_eq!(neighbors(right), [middle]);
+        assert_eq!(
+            neighbors(middle),
+            [left, right].into_iter().sorted().collect_vec()
+        );
+
+        // The raised middle slab steps down onto both sides
+        for neighbor in navmesh.neighbors(middle) {
+            assert_eq!(neighbor.link().from(), middle);
+            assert!(matches!(neighbor.kind(), LinkKind::StepDown(..)));
+        }
+
+        for end in [left, right] {
+            let neighbor = navmesh.neighbors(end).exactly_one().ok().unwrap();
+            assert_eq!(neighbor.link().from(), end);
+            assert!(matches!(neighbor.kind(), LinkKind::StepUp(..)));
+        }
+    }
+
+    fn grid(settings: NavmeshSettings) -> Navmesh {
+        let cells = (0..3).flat_map(|x| (0..3).map(move |z| (x, z)));
+        Navmesh::new(
+            settings,
+            cells.map(|(x, z)| {
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(x as f32 * 2.0, 0.0, z as f32 * 2.0))
+                        * Mat4::from_scale(vec3(1.0, 0.2, 1.0)),
+                    Arc::new(Brush::cube()),
+                )
+            }),
+        )
+    }
+
+    #[test]
+    fn obstacle_reroute() {
+        let mut navmesh = grid(NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        });
+
+        let start = vec3(0.0, 0.3, 0.0);
+        let end = vec3(4.0, 0.3, 0.0);
+
+        let points = |navmesh: &Navmesh| {
+            navmesh
+                .find_path(start, end)
+                .unwrap()
+                .iter()
+                .map(|v| v.point())
+                .collect_vec()
+        };
+
+        let original = points(&navmesh);
+        assert!(original.iter().all(|v| v.z.abs() < 0.1));
+
+        // Covers the middle cell of the first row
+        let obstacle = Obb::from_aabb(vec3(1.0, -1.0, -1.0), vec3(3.0, 1.0, 1.0));
+        let id = navmesh.add_obstacle(obstacle);
+        assert!(navmesh.walkable_polygons().count() < navmesh.polygons().len());
+
+        // Rounds the corners of the obstacle without crossing it
+        let rerouted = points(&navmesh);
+        let interior = Obb::from_aabb(vec3(1.1, -1.0, -0.9), vec3(2.9, 1.0, 0.9));
+        assert!(rerouted.iter().all(|&v| !interior.contains_point(v)));
+        assert!(rerouted.iter().any(|v| v.z > 0.9));
+
+        // Overlapping obstacles compose
+        let other = navmesh.add_obstacle(obstacle);
+        navmesh.remove_obstacle(id).unwrap();
+        assert_eq!(points(&navmesh), rerouted);
+
+        navmesh.remove_obstacle(other).unwrap();
+        assert_eq!(points(&navmesh), original);
+        assert!(navmesh.remove_obstacle(other).is_none());
+    }
+
+    #[test]
+    fn obstacle_partial_cover() {
+        let mut navmesh = Navmesh::new(
+            NavmeshSettings {
+                agent_radius: 0.0,
+                ..Default::default()
+            },
+            [slab(0.0, 0.0), slab(2.0, 0.0)],
+        );
+
+        let (start, end) = (vec3(-0.5, 0.3, 0.0), vec3(2.5, 0.3, 0.0));
+        let polygon = navmesh.closest_polygon(start).unwrap().0;
+
+        // A small crate on a large polygon leaves the polygon walkable
+        let small =
+            navmesh.add_obstacle(Obb::from_aabb(vec3(-0.6, 0.0, -0.1), vec3(-0.4, 0.5, 0.1)));
+        assert!(!navmesh.is_blocked(polygon));
+        assert_eq!(navmesh.closest_polygon(start).unwrap().0, polygon);
+        assert!(navmesh.find_path(start, end).is_some());
+        navmesh.remove_obstacle(small).unwrap();
+
+        // Straddling the seam cuts the link, without blocking either polygon
+        let seam = navmesh.add_obstacle(Obb::from_aabb(vec3(0.8, 0.0, -1.1), vec3(1.2, 0.5, 1.1)));
+        assert!(navmesh.polygons().iter().all(|v| !navmesh.is_blocked(v.0)));
+        assert!(navmesh
+            .links()
+            .iter()
+            .any(|v| navmesh.is_link_disabled(v.0)));
+        assert!(navmesh.find_path(start, end).is_none());
+
+        navmesh.remove_obstacle(seam).unwrap();
+        assert!(navmesh.find_path(start, end).is_some());
+    }
+
+    #[test]
+    fn rebuild() {
+        let steps = || [slab(-2.0, 0.0), slab(0.0, 0.3), slab(2.0, 0.0)];
+
+        let mut navmesh = grid(NavmeshSettings::default());
+        // Covers the walkable surface of the first step, grown by the agent radius
+        let obstacle = Obb::from_aabb(vec3(-3.3, -1.0, -1.3), vec3(-1.1, 1.0, 1.3));
+        navmesh.add_obstacle(obstacle);
+
+        navmesh.rebuild(steps());
+
+        let mut expected = Navmesh::new(NavmeshSettings::default(), steps());
+        expected.add_obstacle(obstacle);
+
+        assert_eq!(navmesh.polygons().len(), expected.polygons().len());
+        assert_eq!(navmesh.links().len(), expected.links().len());
+        assert_eq!(
+            navmesh.walkable_polygons().count(),
+            expected.walkable_polygons().count()
+        );
+        assert!(navmesh.walkable_polygons().count() < navmesh.polygons().len());
+
+        for ((a, face), (b, other)) in navmesh.polygons().iter().zip(expected.polygons()) {
+            assert_eq!(a, b);
+            assert_eq!(face, other);
+        }
+    }
+
+    #[test]
+    fn closest_polygon_stacked() {
+        let floor = |y: f32| {
+            PositionedBrush::new(
+                Mat4::from_translation(vec3(0.0, y, 0.0)) * Mat4::from_scale(vec3(4.0, 0.2, 4.0)),
+                Arc::new(Brush::cube()),
+            )
+        };
+
+        let navmesh = Navmesh::new(NavmeshSettings::default(), [floor(0.0), floor(3.0)]);
+
+        let height = |point| navmesh.closest_polygon(point).unwrap().1.points()[0].y;
+
+        assert!(height(vec3(1.0, 0.5, 1.0)) < 1.0);
+        assert!(height(vec3(1.0, 2.9, 1.0)) < 1.0);
+        assert!(height(vec3(1.0, 3.5, 1.0)) > 3.0);
+        assert!(height(vec3(1.0, 13.0, 1.0)) > 3.0);
+        assert!(height(vec3(1.0, 3.45, 1.0)) > 3.0);
+
+        assert!(navmesh
+            .closest_polygon_within(vec3(1.0, 13.0, 1.0), 0.0, 1.0)
+            .is_none());
+
+        // Just above the lower floor, with the upper floor within stepping distance above
+        let navmesh = Navmesh::new(NavmeshSettings::default(), [floor(0.0), floor(1.0)]);
+        let lower = 0.2 + navmesh.settings().agent_radius;
+        let (_, face, _) = navmesh
+            .closest_polygon(vec3(1.0, lower + 0.1, 1.0))
+            .unwrap();
+        assert!((face.points()[0].y - lower).abs() < 1e-4);
+    }
+
+    #[test]
+    fn closest_polygon_pillar() {
+        // A tall pillar standing on the floor, as in the example scene
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [
+                cube(Vec3::ZERO, vec3(6.0, 0.2, 6.0)),
+                cube(vec3(0.0, 1.5, 0.0), vec3(0.5, 1.5, 0.5)),
+            ],
+        );
+
+        let radius = navmesh.settings().agent_radius;
+        let floor = 0.2 + radius;
+        let top = 3.0 + radius;
+
+        // Next to the pillar, at the height of its top and just above the floor
+        for y in [floor + 0.2, top, top + 0.5] {
+            let point = vec3(0.5 + radius + 0.05, y, 0.0);
+            let (_, face, distance) = navmesh.closest_polygon(point).unwrap();
+            assert!((face.points()[0].y - floor).abs() < 1e-4, "{y}");
+            assert!((distance - (y - floor)).abs() < 1e-4, "{distance}");
+        }
+
+        // On top of the pillar
+        let (_, face, distance) = navmesh.closest_polygon(vec3(0.0, top + 0.1, 0.0)).unwrap();
+        assert!((face.points()[0].y - top).abs() < 1e-4);
+        assert!((distance - 0.1).abs() < 1e-4);
+
+        // Off the navmesh, within the search radius of the floor edge
+        let (_, _, distance) = navmesh.closest_polygon(vec3(7.0, floor, 0.0)).unwrap();
+        assert!((distance - (1.0 - radius)).abs() < 1e-4, "{distance}");
+    }
+
+    #[test]
+    fn inflate_rotated() {
+        let brush = PositionedBrush::new(
+            Mat4::from_translation(vec3(10.0, 0.0, 10.0))
+                * Mat4::from_rotation_y(0.5)
+                * Mat4::from_scale(vec3(2.0, 0.5, 2.0)),
+            Arc::new(Brush::cube()),
+        );
+
+        let navmesh = Navmesh::new(NavmeshSettings::default(), [brush]);
+        let radius = navmesh.settings().agent_radius;
+
+        // The top face grows evenly by the agent radius on each side
+        let area: f32 = navmesh.walkable_polygons().map(|v| v.1.area()).sum();
+        let expected = (4.0 + 2.0 * radius).powi(2);
+        assert!((area - expected).abs() < 0.01, "{area} {expected}");
+
+        let (_, top, _) = navmesh.closest_polygon(vec3(10.0, 1.0, 10.0)).unwrap();
+        assert!((top.points()[0].y - (0.5 + radius)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn inflate_diagonal_pillar() {
+        // A pillar turned by 45 degrees standing on the floor
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [
+                cube(Vec3::ZERO, vec3(6.0, 0.2, 6.0)),
+                cube(Vec3::ZERO, vec3(1.0, 1.5, 1.0)).transformed(
+                    Mat4::from_translation(vec3(0.0, 1.5, 0.0))
+                        * Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4),
+                ),
+            ],
+        );
+
+        let radius = navmesh.settings().agent_radius;
+        let floor = 0.2 + radius;
+
+        // The floor keeps the same distance from every side of the pillar
+        for i in 0..4 {
+            let angle = std::f32::consts::FRAC_PI_4 + i as f32 * std::f32::consts::FRAC_PI_2;
+            let normal = vec3(angle.cos(), 0.0, angle.sin());
+            let at = |distance: f32| {
+                let point = normal * distance + Vec3::Y * floor;
+                navmesh.closest_polygon_within(point, 0.1, 0.1)
+            };
+
+            assert!(at(1.0 + radius - 0.05).is_none(), "{normal}");
+            assert!(at(1.0 + radius + 0.05).is_some(), "{normal}");
+        }
+    }
+
+    #[test]
+    fn link_edges_within_polygons() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        for height in [0.0, 0.3] {
+            let navmesh = Navmesh::new(
+                settings,
+                [
+                    cube(Vec3::ZERO, vec3(4.0, 0.2, 4.0)),
+                    cube(vec3(5.0, height, 1.0), vec3(1.0, 0.2, 0.5)),
+                ],
+            );
+
+            // Horizontal distance from a point to the boundary of a polygon
+            let boundary_distance = |polygon: usize, p: Vec3| {
+                navmesh.polygons()[polygon]
+                    .edges()
+                    .map(|(a, b)| {
+                        let flat = |v: Vec3| vec3(v.x, 0.0, v.z);
+                        Edge3D::new(flat(a), flat(b))
+                            .closest_point(flat(p))
+                            .distance(flat(p))
+                    })
+                    .fold(f32::MAX, f32::min)
+            };
+
+            assert!(!navmesh.links().is_empty());
+            for (_, link) in navmesh.links() {
+                for edge in [link.source_edge(), link.destination_edge()] {
+                    assert!(edge.length() >= 2.0 * TOLERANCE);
+
+                    for p in [edge.p1, edge.p2] {
+                        assert!(boundary_distance(link.from(), p) < TOLERANCE);
+                        assert!(boundary_distance(link.to(), p) < TOLERANCE);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn drop_links() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            max_drop_height: 2.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(settings, [slab(0.0, 0.0), slab(2.0, 1.5)]);
+
+        let low = vec3(0.0, 0.3, 0.0);
+        let high = vec3(2.0, 1.8, 0.0);
+        let low_polygon = navmesh.closest_polygon(low).unwrap().0;
+        let high_polygon = navmesh.closest_polygon(high).unwrap().0;
+
+        let drops = navmesh
+            .links()
+            .iter()
+            .filter(|v| matches!(v.1.kind(), LinkKind::Drop(..)))
+            .map(|v| v.1)
+            .collect_vec();
+
+        assert_eq!(drops.len(), 1);
+        assert_eq!(drops[0].from(), high_polygon);
+        assert_eq!(drops[0].to(), low_polygon);
+        assert!(drops[0].source_edge().p1.y > drops[0].destination_edge().p1.y);
+
+        // Drops are one-way
+        assert!(navmesh.link_between(low_polygon, high_polygon).is_none());
+        assert!(navmesh.find_path(high, low).is_some());
+        assert!(navmesh.find_path(low, high).is_none());
+
+        // Too deep to drop down
+        let navmesh = Navmesh::new(
+            NavmeshSettings {
+                max_drop_height: 1.0,
+                ..settings
+            },
+            [slab(0.0, 0.0), slab(2.0, 1.5)],
+        );
+
+        assert!(navmesh.find_path(high, low).is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3), slab(2.0, 0.0)],
+        );
+
+        let mut navmesh = navmesh;
+        navmesh.add_obstacle(Obb::new(
+            vec3(0.0, 0.5, 2.0),
+            Vec3::splat(0.1),
+            glam::Quat::IDENTITY,
+        ));
+
+        // Vacant keys must be kept for the indices of the links to stay valid
+        let vacant = navmesh
+            .walkable_polygons
+            .insert(Brush::cube().faces()[0].clone());
+        navmesh.walkable_polygons.remove(vacant);
+        let removed = navmesh.links.insert(navmesh.links[0]);
+        navmesh.links.remove(removed);
+
+        let json = serde_json::to_string(&navmesh).unwrap();
+        let loaded: Navmesh = serde_json::from_str(&json).unwrap();
+
+        assert!(loaded.query_grid.is_none());
+        assert_eq!(loaded.obstacles.len(), 1);
+        assert_eq!(
+            navmesh.links().iter().map(|v| v.0).collect_vec(),
+            loaded.links().iter().map(|v| v.0).collect_vec()
+        );
+        assert_eq!(
+            navmesh.polygons().iter().map(|v| v.0).collect_vec(),
+            loaded.polygons().iter().map(|v| v.0).collect_vec()
+        );
+        for (index, _) in navmesh.polygons() {
+            assert_eq!(navmesh.polygon_links(index), loaded.polygon_links(index));
+        }
+
+        let start = vec3(-2.5, 0.3, 0.5);
+        let end = vec3(2.5, 0.3, -0.5);
+
+        let path = |navmesh: &Navmesh| {
+            astar(navmesh, start, end, |a, b| a.distance(b))
+                .unwrap()
+                .iter()
+                .map(|v| (v.target_polygon(), v.point()))
+                .collect_vec()
+        };
+
+        assert_eq!(path(&navmesh), path(&loaded));
+    }
+
+    #[test]
+    fn order_independent() {
+        let ramp = PositionedBrush::new(
+            Mat4::from_translation(vec3(0.0, 0.0, 4.0))
+                * Mat4::from_rotation_x(0.3)
+                * Mat4::from_scale(vec3(1.0, 0.2, 2.0)),
+            Arc::new(Brush::cube()),
+        );
+
+        let brushes = [
+            cube(Vec3::ZERO, vec3(6.0, 0.2, 6.0)),
+            cube(vec3(2.0, 0.3, 2.0), vec3(1.0, 0.2, 1.0)),
+            cube(vec3(-3.0, 1.0, 0.0), vec3(0.25, 1.0, 2.0)),
+            cube(vec3(3.0, 1.0, -3.0), vec3(0.3, 1.0, 0.3)),
+            cube(vec3(-3.0, 0.6, 4.0), vec3(1.0, 0.2, 1.0)),
+            ramp,
+        ];
+
+        let mut reordered = brushes.clone();
+        reordered.swap(0, 5);
+        reordered.swap(1, 3);
+        reordered[2..5].rotate_left(1);
+
+        let a = Navmesh::new(NavmeshSettings::default(), brushes);
+        let b = Navmesh::new(NavmeshSettings::default(), reordered);
+
+        assert_eq!(a.polygons().len(), b.polygons().len());
+        for ((i, face), (j, other)) in a.polygons().iter().zip(b.polygons()) {
+            assert_eq!(i, j);
+            assert_eq!(face, other);
+        }
+
+        // The same links are generated in the same order
+        let links = |navmesh: &Navmesh| {
+            navmesh
+                .links()
+                .iter()
+                .map(|(index, link)| {
+                    (
+                        index,
+                        link.from(),
+                        link.to(),
+                        link.source_edge(),
+                        link.destination_edge(),
+                    )
+                })
+                .collect_vec()
+        };
+
+        assert!(!a.links().is_empty());
+        assert_eq!(links(&a), links(&b));
+
+        let path = |navmesh: &Navmesh| {
+            navmesh
+                .find_path(vec3(-5.0, 0.4, -5.0), vec3(2.0, 0.7, 2.0))
+                .unwrap()
+                .iter()
+                .map(|v| (v.target_polygon(), v.point()))
+                .collect_vec()
+        };
+
+        assert_eq!(path(&a), path(&b));
+    }
+
+    #[test]
+    fn links_between() {
+        let mut navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3)],
+        );
+
+        let (index, &step) = navmesh
+            .links()
+            .iter()
+            .find(|v| matches!(v.1.kind(), LinkKind::StepUp(..)))
+            .unwrap();
+
+        let (a, b) = (step.from(), step.to());
+        assert_eq!(navmesh.link(index).unwrap().to(), b);
+
+        // Connect the same polygons by walking as well
+        let walk = navmesh
+            .links
+            .insert(NavmeshLink::new(a, b, LinkKind::Walk(step.source_edge())));
+        navmesh.polygon_links.entry(a).or_default().push(walk);
+
+        let links = navmesh.links_between(a, b).collect_vec();
+        assert_eq!(links.len(), 2);
+        assert!(links.iter().any(|v| v.0 == index));
+        assert!(links
+            .iter()
+            .any(|v| v.0 == walk && matches!(v.1.kind(), LinkKind::Walk(_))));
+
+        assert_eq!(navmesh.neighbor_polygons(a).filter(|&v| v == b).count(), 1);
+        assert!(navmesh.polygon_links(a).contains(&walk));
+        assert!(navmesh.polygon_links(usize::MAX).is_empty());
+
+        navmesh.links.remove(walk);
+        assert!(navmesh.link(walk).is_none());
+    }
+
+    #[test]
+    fn non_walkable_brush() {
+        let water = BrushFlags {
+            walkable: false,
+            area_id: 1,
+        };
+
+        let mud = BrushFlags {
+            walkable: true,
+            area_id: 2,
+        };
+
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [
+                cube(Vec3::ZERO, vec3(4.0, 0.2, 4.0)),
+                cube(Vec3::ZERO, vec3(1.0, 0.5, 1.0)).with_flags(water),
+                cube(vec3(3.0, 0.1, 3.0), vec3(0.5, 0.2, 0.5)).with_flags(mud),
+            ],
+        );
+
+        // The water still carves away the floor beneath it
+        assert!(!navmesh.polygons().is_empty());
+        for (_, face) in navmesh.polygons() {
+            let projection = face.vertical_projection(Vec3::ZERO).unwrap();
+            assert!(projection.horizontal_distance >= 1.0, "{face:?}");
+        }
+
+        let (floor, ..) = navmesh.closest_polygon(vec3(-3.0, 0.5, -3.0)).unwrap();
+        let (raised, ..) = navmesh.closest_polygon(vec3(3.0, 0.6, 3.0)).unwrap();
+        assert_eq!(navmesh.polygon_area_id(floor), Some(0));
+        assert_eq!(navmesh.polygon_area_id(raised), Some(2));
+        assert_eq!(navmesh.polygon_area_id(usize::MAX), None);
+    }
+
+    #[test]
+    fn scaled_epsilon() {
+        let build = |scale: f32| {
+            let scaled = |translation: Vec3, size: Vec3| {
+                cube(translation, size).transformed(Mat4::from_scale(Vec3::splat(scale)))
+            };
+
+            let settings = NavmeshSettings::default();
+            let settings = NavmeshSettings {
+                max_step_height: settings.max_step_height * scale,
+                agent_radius: settings.agent_radius * scale,
+                min_polygon_area: settings.min_polygon_area * scale * scale,
+                search_radius: settings.search_radius * scale,
+                epsilon: settings.epsilon * scale,
+                ..settings
+            };
+
+            Navmesh::new(
+                settings,
+                [
+                    scaled(Vec3::ZERO, vec3(6.0, 0.2, 6.0)),
+                    scaled(vec3(2.0, 0.3, 2.0), vec3(1.0, 0.2, 1.0)),
+                    scaled(vec3(-3.0, 1.0, 0.0), vec3(0.25, 1.0, 2.0)),
+                    scaled(vec3(-3.0, 0.6, 4.0), vec3(1.0, 0.2, 1.0)),
+                ],
+            )
+        };
+
+        let small = build(1.0);
+        let large = build(100.0);
+
+        assert_eq!(small.polygons().len(), large.polygons().len());
+        assert_eq!(small.links().len(), large.links().len());
+        assert_eq!(small.region_count(), large.region_count());
+    }
+
+    #[test]
+    fn stacked_floors() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let floor = |transform: Mat4| {
+            PositionedBrush::new(
+                transform * Mat4::from_scale(vec3(2.0, 0.2, 4.0)),
+                Arc::new(Brush::cube()),
+            )
+        };
+
+        // A walkway and a bridge 3m above it, flat and sloped along the shared edge plane
+        for bridge in [
+            Mat4::from_translation(vec3(2.0, 3.0, 0.0)),
+            Mat4::from_translation(vec3(2.0, 3.0, 0.0)) * Mat4::from_rotation_x(0.2),
+        ] {
+            let navmesh = Navmesh::new(
+                settings,
+                [
+                    floor(Mat4::from_translation(vec3(-2.0, 0.0, 0.0))),
+                    floor(bridge),
+                ],
+            );
+
+            assert_eq!(navmesh.region_count(), 2);
+            for (_, link) in navmesh.links() {
+                let (s, d) = (link.source_edge(), link.destination_edge());
+                assert!(
+                    (s.p1.y - d.p1.y).abs() <= settings.max_step_height,
+                    "{link:?}"
+                );
+            }
+
+            assert!(navmesh
+                .find_path(vec3(-2.0, 0.2, 0.0), vec3(2.0, 3.2, 0.0))
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn low_ceiling() {
+        let settings = NavmeshSettings {
+            agent_height: 1.8,
+            ..Default::default()
+        };
+
+        // The slab leaves 0.5m of headroom above the floor for x in -1..1
+        let brushes = || {
+            [
+                cube(Vec3::ZERO, vec3(4.0, 0.2, 4.0)),
+                cube(vec3(0.0, 0.9, 0.0), vec3(1.0, 0.2, 4.0)),
+            ]
+        };
+
+        let navmesh = Navmesh::new(settings, brushes());
+        let radius = settings.agent_radius;
+
+        let floor = navmesh
+            .walkable_polygons()
+            .filter(|v| v.1.points()[0].y < 0.5)
+            .collect_vec();
+
+        assert!(!floor.is_empty());
+        for (_, face) in floor {
+            let (min, max) = face
+                .points()
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), p| {
+                    (min.min(p.x), max.max(p.x))
+                });
+
+            assert!(
+                max <= -1.0 - radius + 0.01 || min >= 1.0 + radius - 0.01,
+                "{face:?}"
+            );
+        }
+
+        // The top of the slab is still walkable
+        assert!(navmesh.closest_polygon(vec3(0.0, 1.3, 0.0)).unwrap().2 < 0.1);
+
+        // Without a height, the floor below the slab is walkable
+        let navmesh = Navmesh::new(NavmeshSettings::default(), brushes());
+        let (_, face, _) = navmesh.closest_polygon(vec3(0.0, 0.4, 0.0)).unwrap();
+        assert!(face.points()[0].y < 0.5);
+    }
+
+    #[test]
+    fn invalid_links() {
+        let mut navmesh = grid(NavmeshSettings::default());
+        let start = vec3(0.0, 0.5, 0.0);
+        let end = vec3(4.0, 0.5, 4.0);
+
+        assert!(navmesh
+            .links()
+            .iter()
+            .all(|(_, link)| link.from() != link.to()));
+
+        let (polygon, face, _) = navmesh.closest_polygon(start).unwrap();
+        let (other, ..) = navmesh.closest_polygon(end).unwrap();
+        let (a, b) = face.edges().next().unwrap();
+
+        // A self link, a link from another polygon, a link to a missing polygon, and a removed
+        // link
+        let self_link = navmesh.links.insert(NavmeshLink::new(
+            polygon,
+            polygon,
+            LinkKind::Walk(Edge3D::new(a, b)),
+        ));
+        let foreign = navmesh.links.insert(NavmeshLink::new(
+            other,
+            polygon,
+            LinkKind::Walk(Edge3D::new(a, b)),
+        ));
+
+        let dangling = navmesh.links.insert(NavmeshLink::new(
+            polygon,
+            navmesh.walkable_polygons.capacity() + 10,
+            LinkKind::Walk(Edge3D::new(a, b)),
+        ));
+
+        let links = navmesh.polygon_links.entry(polygon).or_default();
+        links.splice(0..0, [self_link, foreign, dangling, usize::MAX]);
+
+        let path = astar(&navmesh, start, end, |a, b| a.distance(b)).unwrap();
+        assert!(path.last().unwrap().point().distance(end) < 1e-3);
+
+        let paths = crate::astar::dijkstra_multi(&navmesh, start, &[end]);
+        assert!(paths[0].is_some());
+    }
+
+    #[test]
+    fn plane_clustering() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        // Two floors rotated about the origin, with a gap between them at `seam`
+        let floors = |rotation: f32, seam: f32, gap: f32| {
+            let floor = |x: f32| {
+                PositionedBrush::new(
+                    Mat4::from_rotation_y(rotation)
+                        * Mat4::from_translation(vec3(x, 0.0, 0.0))
+                        * Mat4::from_scale(vec3(1.0, 0.2, 1.0)),
+                    Arc::new(Brush::cube()),
+                )
+            };
+
+            Navmesh::new(settings, [floor(seam - 1.0), floor(seam + gap + 1.0)])
+        };
+
+        // Seams which straddled the rounding boundaries of the angle and the distance
+        for (rotation, seam) in [
+            (0.0, 0.5 / 256.0),
+            (0.5 / 1024.0, 0.0),
+            (1.5 / 1024.0, 3.5 / 256.0),
+        ] {
+            let navmesh = floors(rotation, seam - 0.5e-4, 1e-4);
+            let point =
+                |x: f32| Mat4::from_rotation_y(rotation).transform_point3(vec3(x, 0.2, 0.0));
+
+            assert_eq!(navmesh.region_count(), 1, "{rotation} {seam}");
+            assert!(navmesh
+                .find_path(point(seam - 1.0), point(seam + 1.0))
+                .is_some());
+        }
+
+        // Distinct planes 2mm apart which rounded to the same distance
+        let navmesh = floors(0.0, -1e-3, 2e-3);
+        assert_eq!(navmesh.links().len(), 0);
+        assert_eq!(navmesh.region_count(), 2);
+    }
+
+    #[test]
+    fn obstacle_corridor() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        // A corridor of slabs along x, and an island connected by an off-mesh link
+        let mut navmesh = Navmesh::new(
+            settings,
+            (0..4)
+                .map(|x| slab(x as f32 * 2.0, 0.0))
+                .chain([slab(10.0, 0.0)]),
+        );
+
+        navmesh
+            .add_offmesh_link(vec3(6.5, 0.2, 0.0), vec3(9.5, 0.2, 0.0), true, 0)
+            .unwrap();
+
+        let (start, end) = (vec3(-0.5, 0.2, 0.0), vec3(6.5, 0.2, 0.0));
+        let points = |navmesh: &Navmesh, end: Vec3| {
+            navmesh
+                .find_path(start, end)
+                .map(|v| v.iter().map(|v| v.point()).collect_vec())
+        };
+
+        let original = points(&navmesh, end).unwrap();
+
+        let pillar = navmesh.add_obstacle(Cylinder::new(vec3(2.0, 0.5, 0.0), 1.5, 1.0));
+        assert!(navmesh.polygons().iter().any(|v| navmesh.is_blocked(v.0)));
+        assert!(points(&navmesh, end).is_none());
+
+        assert!(matches!(
+            navmesh.remove_obstacle(pillar),
+            Some(ObstacleShape::Cylinder(_))
+        ));
+        assert_eq!(points(&navmesh, end).unwrap(), original);
+
+        // Blocks the off-mesh link over the gap, without covering any polygon
+        let island = vec3(10.0, 0.2, 0.0);
+        let crossing = points(&navmesh, island).unwrap();
+
+        let gap = navmesh.add_obstacle(Obb::from_aabb(vec3(7.5, -1.0, -1.0), vec3(8.5, 1.0, 1.0)));
+        assert!(navmesh.polygons().iter().all(|v| !navmesh.is_blocked(v.0)));
+        assert!(points(&navmesh, island).is_none());
+
+        // Kept across regenerated links
+        navmesh.generate_links();
+        assert!(points(&navmesh, island).is_none());
+
+        navmesh.remove_obstacle(gap).unwrap();
+        assert_eq!(points(&navmesh, island).unwrap(), crossing);
+        assert!(navmesh.disabled_links.is_empty() && navmesh.blocked.is_empty());
+    }
+
+    #[test]
+    fn shared_corner() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        // Boxes stacked and touching at a single corner, with edges meeting in a point
+        for translation in [
+            vec3(2.0, 1.2, 2.0),
+            vec3(2.0, 0.2, 2.0),
+            vec3(2.0, 0.0, 0.0),
+        ] {
+            let navmesh = Navmesh::new(
+                settings,
+                [
+                    cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+                    cube(translation, vec3(1.0, 1.0, 1.0)),
+                ],
+            );
+
+            for (_, link) in navmesh.links() {
+                let (s, d) = (link.source_edge(), link.destination_edge());
+                assert!(
+                    [s.p1, s.p2, d.p1, d.p2].iter().all(|v| v.is_finite()),
+                    "{link:?}"
+                );
+            }
+
+            assert!(navmesh.validate().is_ok(), "{}", navmesh.validate());
+        }
+    }
+
+    #[test]
+    fn disjoint_navmeshes() {
+        // Two interiors built separately, each in their own local space
+        let interiors = [
+            Navmesh::new(NavmeshSettings::default(), [slab(0.0, 0.0), slab(2.0, 0.0)]),
+            Navmesh::new(
+                NavmeshSettings::default(),
+                [slab(20.0, 5.0), slab(22.0, 5.0)],
+            ),
+        ];
+
+        let select = |point: Vec3| {
+            interiors
+                .iter()
+                .position(|v| v.bounds_contain(point))
+                .unwrap()
+        };
+
+        let (a, b) = (vec3(-0.5, 0.2, 0.0), vec3(2.5, 0.2, 0.0));
+        assert_eq!(select(a), 0);
+        assert!(interiors[select(a)].find_path(a, b).is_some());
+
+        let (a, b) = (vec3(19.5, 5.2, 0.0), vec3(22.5, 5.2, 0.0));
+        assert_eq!(select(a), 1);
+        assert!(interiors[select(a)].find_path(a, b).is_some());
+        assert!(interiors[0].find_path(a, b).is_none());
+
+        assert!(interiors
+            .iter()
+            .all(|v| !v.bounds_contain(vec3(10.0, 0.2, 0.0))));
+
+        let (min, max) =
+            Navmesh::new(NavmeshSettings::default(), [] as [PositionedBrush; 0]).aabb();
+        assert!(min.cmpgt(max).all());
+    }
+
+    #[test]
+    fn subtract_hole() {
+        let floor = || cube(Vec3::ZERO, vec3(6.0, 0.2, 6.0));
+        let hole = || cube(Vec3::ZERO, vec3(2.0, 1.0, 2.0));
+
+        // Subtracting before anything is added has no effect
+        let navmesh = Navmesh::from_csg(
+            NavmeshSettings::default(),
+            [
+                (CsgOp::Subtract, hole()),
+                (CsgOp::Union, floor()),
+                (CsgOp::Subtract, hole()),
+            ],
+        );
+
+        let radius = navmesh.settings().agent_radius;
+        let y = 0.2 + radius;
+
+        // The hole shrinks by the agent radius like a doorway would, just as the outer edges of
+        // the floor grow by it
+        for point in [Vec3::ZERO, vec3(2.0 - radius - 0.05, y, 0.0)] {
+            assert!(navmesh.closest_polygon_within(point, 1.0, 1.0).is_none());
+        }
+
+        assert!(navmesh
+            .closest_polygon_within(vec3(2.0 - radius + 0.05, y, 0.0), 1.0, 1.0)
+            .is_some());
+
+        let (start, end) = (vec3(-5.0, y, 0.0), vec3(5.0, y, 0.0));
+        let path = navmesh.find_path(start, end).unwrap();
+        assert!(path.length() > 10.5);
+        assert!(path
+            .windows(2)
+            .all(|v| navmesh.raycast(v[0].point(), v[1].point()).is_reached()));
+        assert!(!navmesh.raycast(start, end).is_reached());
+
+        // Without the hole there is a straight path
+        let filled = Navmesh::from_csg(
+            NavmeshSettings::default(),
+            [(CsgOp::Subtract, hole()), (CsgOp::Union, floor())],
+        );
+        assert_eq!(filled.find_path(start, end).unwrap().len(), 2);
+
+        // A hole barely larger than the agent is eroded down to a small square, but kept
+        let settings = NavmeshSettings {
+            agent_radius: 3.7,
+            ..Default::default()
+        };
+
+        let y = 0.2 + settings.agent_radius;
+        let navmesh = Navmesh::from_csg(
+            settings,
+            [
+                (CsgOp::Union, cube(Vec3::ZERO, vec3(12.0, 0.2, 12.0))),
+                (CsgOp::Subtract, cube(vec3(0.0, y, 0.0), Vec3::splat(4.0))),
+            ],
+        );
+
+        for point in [vec3(0.0, y, 0.0), vec3(0.25, y, 0.25)] {
+            assert!(navmesh.closest_polygon_within(point, 0.01, 0.1).is_none());
+        }
+
+        assert!(navmesh
+            .closest_polygon_within(vec3(0.35, y, 0.0), 0.01, 0.1)
+            .is_some());
+    }
+
+    #[test]
+    fn step_links_once() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3)],
+        );
+
+        let low = navmesh.closest_polygon(vec3(-2.0, 0.5, 0.0)).unwrap().0;
+        let high = navmesh.closest_polygon(vec3(0.0, 0.8, 0.0)).unwrap().0;
+
+        let up = navmesh.links_between(low, high).collect_vec();
+        let down = navmesh.links_between(high, low).collect_vec();
+        assert_eq!(up.len(), 1, "{up:?}");
+        assert_eq!(down.len(), 1, "{down:?}");
+
+        let (up, down) = (up[0].1, down[0].1);
+        assert!(matches!(up.kind(), LinkKind::StepUp(..)));
+        assert!(matches!(down.kind(), LinkKind::StepDown(..)));
+        assert!(up.destination_edge().p1.y > up.source_edge().p1.y);
+        assert!(down.destination_edge().p1.y < down.source_edge().p1.y);
+
+        // Each is the reverse of the other
+        let reverse = up.reverse().unwrap();
+        assert_eq!(reverse.from(), down.from());
+        assert_eq!(reverse.source_edge(), down.source_edge());
+        assert_eq!(reverse.destination_edge(), down.destination_edge());
+    }
+
+    #[test]
+    fn project_off_edge() {
+        let navmesh = Navmesh::new(NavmeshSettings::default(), [slab(0.0, 0.0), slab(2.0, 0.0)]);
+        let (min, max) = navmesh.aabb();
+
+        // Hovering just past the edge of the platform, where no polygon is below the point
+        let start = vec3(min.x - 0.05, max.y + 0.1, 0.0);
+        assert!(navmesh
+            .closest_polygon_within(start, f32::INFINITY, f32::INFINITY)
+            .is_none());
+
+        let (polygon, projected) = navmesh.project_point(start, 0.5).unwrap();
+        assert!(navmesh.polygons()[polygon].contains_point(projected));
+        assert!(
+            (projected - vec3(min.x, max.y, 0.0)).length() < 1e-4,
+            "{projected}"
+        );
+        assert!(navmesh.project_point(start, 0.05).is_none());
+
+        // The path starts from the projected point
+        let path = navmesh.find_path(start, vec3(2.5, max.y, 0.0)).unwrap();
+        assert!(path.waypoints()[0].point().distance(projected) < 1e-4);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_matches_serial() {
+        let mut seed = 0x9e37_79b9_u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32
+        };
+
+        // Overlapping platforms and walls, with some doorways carved out of them
+        let ops = (0..60)
+            .map(|i| {
+                let translation = vec3(random() * 20.0, random() * 2.0, random() * 20.0);
+                let scale = vec3(0.2 + random() * 2.0, 0.2 + random(), 0.2 + random() * 2.0);
+                let op = if i % 7 == 6 {
+                    CsgOp::Subtract
+                } else {
+                    CsgOp::Union
+                };
+
+                (
+                    op,
+                    PositionedBrush::new(
+                        Mat4::from_translation(translation) * Mat4::from_scale(scale),
+                        Arc::new(Brush::cube()),
+                    ),
+                )
+            })
+            .collect_vec();
+
+        let build = |parallel| {
+            let settings = NavmeshSettings {
+                parallel,
+                ..Default::default()
+            };
+
+            Navmesh::from_csg_with_report(settings, ops.clone())
+        };
+
+        // Several threads even on a single core, so that work is actually split up
+        let (parallel, parallel_report) = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap()
+            .install(|| build(true));
+        let (serial, serial_report) = build(false);
+
+        assert_eq!(parallel_report, serial_report);
+        assert!(!parallel.links().is_empty());
+
+        let polygons = |navmesh: &Navmesh| {
+            navmesh
+                .polygons()
+                .iter()
+                .map(|(index, face)| (index, face.clone()))
+                .collect_vec()
+        };
+
+        let links = |navmesh: &Navmesh| {
+            navmesh
+                .links()
+                .iter()
+                .map(|(index, link)| (index, link.from(), link.to(), *link.kind()))
+                .collect_vec()
+        };
+
+        assert_eq!(polygons(&parallel), polygons(&serial));
+        assert_eq!(links(&parallel), links(&serial));
+    }
+}
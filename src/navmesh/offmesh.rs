@@ -0,0 +1,210 @@
+use glam::Vec3;
+
+use crate::link::{LinkKind, NavmeshLink};
+
+use super::Navmesh;
+
+/// A connection authored with [`Navmesh::add_offmesh_link`]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct OffMeshLink {
+    from: Vec3,
+    to: Vec3,
+    bidirectional: bool,
+    tag: u32,
+    cost: f32,
+}
+
+impl Navmesh {
+    /// Connects two points which can not be discovered from the geometry, such as the ends of a
+    /// ladder, jump pad or teleporter.
+    ///
+    /// Both points are resolved to the polygon they are standing on. The connection is kept
+    /// and reapplied when the links are regenerated, as long as both points remain on the
+    /// navmesh.
+    ///
+    /// Returns the index of the link from `from` to `to`, or `None` if either point is not on
+    /// the navmesh.
+    pub fn add_offmesh_link(
+        &mut self,
+        from: Vec3,
+        to: Vec3,
+        bidirectional: bool,
+        tag: u32,
+    ) -> Option<usize> {
+        self.add_offmesh_link_with_cost(from, to, bidirectional, tag, 1.0)
+    }
+
+    /// Same as [`Self::add_offmesh_link`], but multiplies the cost of traversing the connection
+    /// by `cost`, such as to make slow ladders less attractive than walking around.
+    pub fn add_offmesh_link_with_cost(
+        &mut self,
+        from: Vec3,
+        to: Vec3,
+        bidirectional: bool,
+        tag: u32,
+        cost: f32,
+    ) -> Option<usize> {
+        let link = OffMeshLink {
+            from,
+            to,
+            bidirectional,
+            tag,
+            cost,
+        };
+
+        let index = self.insert_offmesh_link(link)?;
+        self.offmesh_links.push(link);
+        self.apply_obstacle_links();
+        self.compute_regions();
+        self.clusters = None;
+
+        Some(index)
+    }
+
+    pub(super) fn apply_offmesh_links(&mut self) {
+        for link in std::mem::take(&mut self.offmesh_links) {
+            self.insert_offmesh_link(link);
+            self.offmesh_links.push(link);
+        }
+    }
+
+    fn insert_offmesh_link(&mut self, link: OffMeshLink) -> Option<usize> {
+        let (from, ..) = self.closest_polygon(link.from)?;
+        let (to, ..) = self.closest_polygon(link.to)?;
+
+        let link_kind = LinkKind::OffMesh {
+            from: link.from,
+            to: link.to,
+            tag: link.tag,
+        };
+
+        let forward = NavmeshLink::new(from, to, link_kind).with_cost(link.cost);
+        let index = self.links.insert(forward);
+        self.polygon_links.entry(from).or_default().push(index);
+
+        if let Some(reverse) = forward.reverse().filter(|_| link.bidirectional) {
+            let index = self.links.insert(reverse);
+            self.polygon_links.entry(to).or_default().push(index);
+        }
+
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::vec3;
+
+    use crate::{
+        astar::{astar_with_options, AstarOptions, PathSmoothing},
+        brush::PositionedBrush,
+        navmesh::NavmeshSettings,
+        test_util::cube,
+    };
+
+    use super::*;
+
+    fn island(x: f32, y: f32) -> PositionedBrush {
+        cube(vec3(x, y, 0.0), vec3(2.0, 0.2, 2.0))
+    }
+
+    #[test]
+    fn offmesh_islands() {
+        let mut navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [island(-4.0, 0.0), island(4.0, 3.0)],
+        );
+
+        let start = vec3(-5.0, 0.4, 0.0);
+        let end = vec3(5.0, 3.4, 0.0);
+        assert!(navmesh.find_path(start, end).is_none());
+
+        let from = vec3(-3.0, 0.4, 1.0);
+        let to = vec3(3.0, 3.4, 1.0);
+        let index = navmesh.add_offmesh_link(from, to, false, 7).unwrap();
+        assert!(navmesh.is_reachable(start, end));
+
+        // Reapplied when the links are regenerated
+        navmesh.generate_links();
+        assert!(matches!(
+            navmesh.link(index).unwrap().kind(),
+            LinkKind::OffMesh { tag: 7, .. }
+        ));
+
+        for smoothing in [PathSmoothing::Funnel, PathSmoothing::Shorten] {
+            let options = AstarOptions {
+                smoothing,
+                ..Default::default()
+            };
+
+            let path = astar_with_options(&navmesh, start, end, |a, b| a.distance(b), options);
+            let path = path.unwrap();
+            let points = path.iter().map(|v| v.point()).collect::<Vec<_>>();
+
+            let entry = points.iter().position(|&v| v.distance(from) < 1e-4);
+            let exit = points.iter().position(|&v| v.distance(to) < 1e-4);
+            assert_eq!(exit, entry.map(|v| v + 1), "{smoothing:?} {points:?}");
+
+            let waypoint = path.waypoints()[exit.unwrap()];
+            assert!(matches!(
+                waypoint.link_kind(),
+                Some(LinkKind::OffMesh { tag: 7, .. })
+            ));
+        }
+
+        // The link only leads one way
+        assert!(navmesh.find_path(end, start).is_none());
+
+        assert!(navmesh
+            .add_offmesh_link(from, vec3(20.0, 0.0, 0.0), true, 0)
+            .is_none());
+    }
+
+    #[test]
+    fn offmesh_cost() {
+        // Two pads joined by a bridge at their far end
+        let scene = || {
+            [
+                cube(vec3(-4.0, 0.0, 2.0), vec3(2.0, 0.2, 4.0)),
+                cube(vec3(4.0, 0.0, 2.0), vec3(2.0, 0.2, 4.0)),
+                cube(vec3(0.0, 0.0, 5.0), vec3(6.0, 0.2, 1.0)),
+            ]
+        };
+
+        let start = vec3(-4.0, 0.4, 0.0);
+        let end = vec3(4.0, 0.4, 0.0);
+        let (from, to) = (vec3(-3.0, 0.4, 0.0), vec3(3.0, 0.4, 0.0));
+
+        let uses_link = |navmesh: &Navmesh| {
+            let path = navmesh.find_path(start, end).unwrap();
+            path.iter()
+                .any(|v| matches!(v.link_kind(), Some(LinkKind::OffMesh { .. })))
+        };
+
+        // Jumping across the gap is shorter than walking around
+        let mut navmesh = Navmesh::new(NavmeshSettings::default(), scene());
+        let around = navmesh.find_path(start, end).unwrap().length();
+        navmesh.add_offmesh_link(from, to, true, 0).unwrap();
+        assert!(uses_link(&navmesh));
+
+        // Unless the jump is expensive enough
+        let mut navmesh = Navmesh::new(NavmeshSettings::default(), scene());
+        let index = navmesh
+            .add_offmesh_link_with_cost(from, to, true, 0, around)
+            .unwrap();
+        assert_eq!(navmesh.link(index).unwrap().cost(), around);
+        assert!(!uses_link(&navmesh));
+
+        // The cost is kept when the links are regenerated
+        navmesh.generate_links();
+        let costs = navmesh
+            .links()
+            .iter()
+            .filter(|v| matches!(v.1.kind(), LinkKind::OffMesh { .. }))
+            .map(|v| v.1.cost())
+            .collect::<Vec<_>>();
+        assert_eq!(costs, [around, around]);
+        assert!(!uses_link(&navmesh));
+    }
+}
@@ -0,0 +1,244 @@
+use glam::{Vec3, Vec3Swizzles};
+
+use crate::{edge::Edge3D, link::LinkKind};
+
+use super::Navmesh;
+
+/// Outcome of [`Navmesh::raycast`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaycastResult {
+    /// The target can be walked to in a straight line
+    Reached { polygon: usize },
+    /// The ray left the walkable surface through `edge`.
+    ///
+    /// `point` is the last traversable point on the edge, and `polygon` the polygon it belongs
+    /// to.
+    Blocked {
+        edge: Edge3D,
+        point: Vec3,
+        polygon: usize,
+    },
+    /// There is no walkable polygon at the start of the ray
+    OffMesh,
+}
+
+impl RaycastResult {
+    /// Returns true if the target was reached
+    pub fn is_reached(&self) -> bool {
+        matches!(self, Self::Reached { .. })
+    }
+}
+
+impl Navmesh {
+    /// Walks along the surface of the navmesh in a straight line from `from` towards `to`, as
+    /// seen from above.
+    ///
+    /// Walk and step links are crossed as long as the step is within `max_step_height`. Drops
+    /// are not followed, as the agent could not walk back, and neither are off-mesh links.
+    pub fn raycast(&self, from: Vec3, to: Vec3) -> RaycastResult {
+        let Some((mut polygon, ..)) = self.closest_polygon(from) else {
+            return RaycastResult::OffMesh;
+        };
+
+        let epsilon = self.settings.epsilon;
+        let origin = from.xz();
+        let dir = to.xz() - origin;
+
+        if dir.length_squared() < epsilon * epsilon {
+            return RaycastResult::Reached { polygon };
+        }
+
+        let mut t = 0.0;
+
+        // Each polygon is entered at most once, as the ray always moves forward through convex
+        // polygons
+        let max_steps = self.walkable_polygons.len();
+        for step in 0..=max_steps {
+            let face = &self.walkable_polygons[polygon];
+
+            // Find where the ray leaves the polygon
+            let exit = face
+                .edges()
+                .filter_map(|(a, b)| {
+                    let normal = (b - a).cross(Vec3::Y).xz();
+                    let denom = dir.dot(normal);
+                    if denom <= f32::EPSILON {
+                        return None;
+                    }
+
+                    let t = (a.xz() - origin).dot(normal) / denom;
+                    Some((t, Edge3D::new(a, b)))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+
+            let Some((exit_t, edge)) = exit else {
+                return RaycastResult::Reached { polygon };
+            };
+
+            if exit_t >= 1.0 {
+                return RaycastResult::Reached { polygon };
+            }
+
+            t = exit_t.max(t);
+
+            let horizontal = origin + dir * t;
+            let edge_dir = (edge.p2 - edge.p1).xz();
+            let s = ((horizontal - edge.p1.xz()).dot(edge_dir) / edge_dir.length_squared())
+                .clamp(0.0, 1.0);
+            let point = edge.p1 + (edge.p2 - edge.p1) * s;
+
+            let on_edge = |edge: Edge3D| {
+                let closest = edge.closest_point(point);
+                closest.xz().distance(point.xz()) < epsilon
+            };
+
+            let next = self
+                .neighbors(polygon)
+                .filter(|v| !self.is_blocked(v.polygon()) && !self.is_link_disabled(v.link_index()))
+                .filter(|v| match v.kind() {
+                    LinkKind::Walk(_) | LinkKind::StepUp(..) | LinkKind::StepDown(..) => true,
+                    LinkKind::Drop(_, _) | LinkKind::OffMesh { .. } => false,
+                })
+                .filter(|v| on_edge(v.link().source_edge()) && on_edge(v.link().destination_edge()))
+                .find(|v| {
+                    let height = v.link().destination_edge().closest_point(point).y - point.y;
+                    height.abs() <= self.settings.max_step_height + epsilon
+                });
+
+            match next {
+                Some(next) if step < max_steps => polygon = next.polygon(),
+                _ => {
+                    return RaycastResult::Blocked {
+                        edge,
+                        point,
+                        polygon,
+                    }
+                }
+            }
+        }
+
+        unreachable!("the last step always returns")
+    }
+
+    /// Walks along the surface of the navmesh from `start` for up to `max_distance` in the
+    /// horizontal direction of `direction`.
+    ///
+    /// Useful for steering, to find how far an agent can move before leaving the navmesh. See
+    /// [`Self::raycast`].
+    pub fn raycast_direction(
+        &self,
+        start: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> RaycastResult {
+        let direction = (direction * Vec3::new(1.0, 0.0, 1.0)).normalize_or_zero();
+        self.raycast(start, start + direction * max_distance)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::vec3;
+
+    use crate::{navmesh::NavmeshSettings, test_util::cube};
+
+    use super::*;
+
+    #[test]
+    fn raycast_wall() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [
+                cube(Vec3::ZERO, vec3(4.0, 0.2, 4.0)),
+                cube(vec3(0.0, 1.0, 0.0), vec3(0.2, 1.0, 4.0)),
+            ],
+        );
+
+        let radius = navmesh.settings().agent_radius;
+
+        let result = navmesh.raycast(vec3(-2.0, 0.5, 0.0), vec3(2.0, 0.5, 0.0));
+        let RaycastResult::Blocked { point, polygon, .. } = result else {
+            panic!("{result:?}");
+        };
+
+        assert!((point.x - (-0.2 - radius)).abs() < 0.01, "{point}");
+        assert!(point.z.abs() < 0.01);
+        assert!(navmesh.polygons()[polygon]
+            .points()
+            .iter()
+            .all(|v| v.x < 0.0));
+
+        // Along the same side of the wall
+        let result = navmesh.raycast(vec3(-2.0, 0.5, -3.0), vec3(-3.0, 0.5, 3.0));
+        assert!(result.is_reached(), "{result:?}");
+    }
+
+    #[test]
+    fn raycast_step() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(
+            settings,
+            [
+                cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+                cube(vec3(2.0, 0.3, 0.0), vec3(1.0, 0.2, 1.0)),
+                cube(vec3(4.0, 1.5, 0.0), vec3(1.0, 0.2, 1.0)),
+            ],
+        );
+
+        let result = navmesh.raycast(vec3(-0.5, 0.3, 0.2), vec3(2.5, 0.6, -0.2));
+        assert!(result.is_reached(), "{result:?}");
+
+        let result = navmesh.raycast(vec3(-0.5, 0.3, 0.2), vec3(4.5, 1.8, -0.2));
+        let RaycastResult::Blocked { point, .. } = result else {
+            panic!("{result:?}");
+        };
+
+        assert!((point.x - 3.0).abs() < 0.01, "{point}");
+        assert!((point.y - 0.5).abs() < 0.01, "{point}");
+
+        assert_eq!(
+            navmesh.raycast(vec3(20.0, 0.0, 0.0), Vec3::ZERO),
+            RaycastResult::OffMesh
+        );
+
+        // The same rays by direction, which ignores the vertical component
+        let start = vec3(-0.5, 0.3, 0.0);
+        let result = navmesh.raycast_direction(start, vec3(1.0, 5.0, 0.0), 3.0);
+        assert!(result.is_reached(), "{result:?}");
+
+        let result = navmesh.raycast_direction(start, Vec3::X, 5.0);
+        let RaycastResult::Blocked { point, .. } = result else {
+            panic!("{result:?}");
+        };
+
+        assert!((point.x - 3.0).abs() < 0.01, "{point}");
+    }
+
+    #[test]
+    fn raycast_epsilon() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            epsilon: 0.01,
+            ..Default::default()
+        };
+
+        // The slabs are linked across a gap narrower than the configured epsilon
+        let navmesh = Navmesh::new(
+            settings,
+            [
+                cube(Vec3::ZERO, vec3(1.0, 0.2, 1.0)),
+                cube(vec3(2.005, 0.0, 0.0), vec3(1.0, 0.2, 1.0)),
+            ],
+        );
+
+        let (start, end) = (vec3(-0.5, 0.2, 0.2), vec3(2.5, 0.2, -0.2));
+        assert!(navmesh.find_path(start, end).is_some());
+
+        let result = navmesh.raycast(start, end);
+        assert!(result.is_reached(), "{result:?}");
+    }
+}
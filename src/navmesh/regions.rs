@@ -0,0 +1,134 @@
+use glam::Vec3;
+
+use super::Navmesh;
+
+/// Marks keys without a polygon in [`Navmesh::regions`]
+pub const NO_REGION: usize = usize::MAX;
+
+fn find(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+
+    index
+}
+
+impl Navmesh {
+    /// Assigns each polygon the id of the connected region it belongs to
+    pub(super) fn compute_regions(&mut self) {
+        let len = self
+            .walkable_polygons
+            .iter()
+            .map(|v| v.0 + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut parents = (0..len).collect::<Vec<_>>();
+        for (_, link) in &self.links {
+            let a = find(&mut parents, link.from());
+            let b = find(&mut parents, link.to());
+            parents[a.max(b)] = a.min(b);
+        }
+
+        // Number the regions in order of their lowest polygon
+        self.regions.clear();
+        self.regions.resize(len, NO_REGION);
+        self.region_count = 0;
+
+        for (index, _) in &self.walkable_polygons {
+            let root = find(&mut parents, index);
+            if root == index {
+                self.regions[index] = self.region_count;
+                self.region_count += 1;
+            } else {
+                self.regions[index] = self.regions[root];
+            }
+        }
+    }
+
+    /// Returns the region id of each polygon, indexed by polygon.
+    ///
+    /// Polygons in the same region are connected through links, ignoring their direction.
+    /// Keys without a polygon are [`NO_REGION`].
+    pub fn regions(&self) -> &[usize] {
+        &self.regions
+    }
+
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    pub fn region(&self, polygon: usize) -> Option<usize> {
+        self.regions
+            .get(polygon)
+            .copied()
+            .filter(|&v| v != NO_REGION)
+    }
+
+    /// Returns true if the points are on polygons in the same region.
+    ///
+    /// This is much cheaper than searching for a path, but does not consider obstacles or that
+    /// drops can only be traversed downwards.
+    pub fn is_reachable(&self, from: Vec3, to: Vec3) -> bool {
+        let region = |point| {
+            self.closest_polygon(point)
+                .and_then(|(polygon, ..)| self.region(polygon))
+        };
+
+        match (region(from), region(to)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::vec3;
+
+    use crate::{
+        astar::{AstarOptions, PathError, PathfindingContext},
+        navmesh::NavmeshSettings,
+        test_util::slab,
+    };
+
+    use super::*;
+
+    #[test]
+    fn separated_regions() {
+        let navmesh = Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3), slab(6.0, 0.0)],
+        );
+
+        assert_eq!(navmesh.region_count(), 2);
+
+        let left = vec3(-2.0, 0.5, 0.0);
+        let middle = vec3(0.0, 0.8, 0.0);
+        let right = vec3(6.0, 0.5, 0.0);
+
+        assert!(navmesh.is_reachable(left, middle));
+        assert!(!navmesh.is_reachable(left, right));
+        assert!(!navmesh.is_reachable(left, vec3(50.0, 0.0, 0.0)));
+        assert!(navmesh.find_path(left, right).is_none());
+
+        // Without expanding any polygon of the start region
+        let mut context = PathfindingContext::new();
+        let result = context.find_path_with_options(
+            &navmesh,
+            left,
+            right,
+            |a, b| a.distance(b),
+            &AstarOptions::default(),
+        );
+
+        assert_eq!(result.err(), Some(PathError::Unreachable));
+        assert_eq!(context.expansions(), 0);
+
+        // A region is assigned to every polygon
+        for (index, _) in navmesh.polygons() {
+            assert!(navmesh.regions()[index] < navmesh.region_count());
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::{self, Display};
+
+use crate::link::LinkKind;
+
+use super::Navmesh;
+
+/// Number of buckets in [`NavmeshBuildReport::slope_histogram`]
+const SLOPE_BUCKETS: usize = 10;
+
+/// Statistics of each stage of navmesh generation, as returned by
+/// [`Navmesh::new_with_report`].
+///
+/// Useful for finding out why a navmesh came out empty or sparse.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NavmeshBuildReport {
+    pub brush_count: usize,
+    /// Brushes which enclosed no volume, and were left out of the union
+    pub rejected_brushes: usize,
+    /// Polygons on the surface of the union of all brushes
+    pub polygon_count: usize,
+    /// Walkable polygons rejected for being too steep, bucketed by `normal.dot(Y)` into ten even
+    /// buckets from `-1` to `1`
+    pub slope_histogram: [usize; SLOPE_BUCKETS],
+    /// Walkable polygons after the slope, clearance and merge passes
+    pub walkable_count: usize,
+    /// Distinct vertical planes the polygon edges were grouped by when linking
+    pub edge_plane_count: usize,
+    /// Links of each kind, counting both directions of two way links
+    pub walk_links: usize,
+    pub step_links: usize,
+    pub drop_links: usize,
+    pub offmesh_links: usize,
+}
+
+impl NavmeshBuildReport {
+    /// Returns the number of polygons rejected for being too steep
+    pub fn rejected_slopes(&self) -> usize {
+        self.slope_histogram.iter().sum()
+    }
+
+    pub fn link_count(&self) -> usize {
+        self.walk_links + self.step_links + self.drop_links + self.offmesh_links
+    }
+
+    pub(super) fn add_rejected_slope(&mut self, cosine: f32) {
+        let bucket = ((cosine + 1.0) * 0.5 * SLOPE_BUCKETS as f32).floor();
+        self.slope_histogram[(bucket.max(0.0) as usize).min(SLOPE_BUCKETS - 1)] += 1;
+    }
+
+    pub(super) fn count_links(&mut self, navmesh: &Navmesh) {
+        for (_, link) in navmesh.links() {
+            match link.kind() {
+                LinkKind::Walk(_) => self.walk_links += 1,
+                LinkKind::StepUp(..) | LinkKind::StepDown(..) => self.step_links += 1,
+                LinkKind::Drop(..) => self.drop_links += 1,
+                LinkKind::OffMesh { .. } => self.offmesh_links += 1,
+            }
+        }
+    }
+}
+
+impl Display for NavmeshBuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} brushes ({} rejected), {} polygons, {} too steep, {} walkable, {} edge planes, \
+             {} links ({} walk, {} step, {} drop, {} off-mesh)",
+            self.brush_count,
+            self.rejected_brushes,
+            self.polygon_count,
+            self.rejected_slopes(),
+            self.walkable_count,
+            self.edge_plane_count,
+            self.link_count(),
+            self.walk_links,
+            self.step_links,
+            self.drop_links,
+            self.offmesh_links,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::{vec3, Mat4, Vec3};
+
+    use crate::{
+        brush::{Brush, PositionedBrush},
+        navmesh::NavmeshSettings,
+        test_util::cube,
+    };
+
+    use super::*;
+
+    #[test]
+    fn build_report() {
+        let settings = NavmeshSettings {
+            merge_polygons: false,
+            ..Default::default()
+        };
+
+        // A floor with a wall standing on it, and a brush without any volume
+        let (navmesh, report) = Navmesh::new_with_report(
+            settings,
+            [
+                cube(Vec3::ZERO, vec3(4.0, 0.2, 4.0)),
+                cube(vec3(0.0, 1.0, 0.0), vec3(0.2, 1.0, 4.0)),
+                PositionedBrush::new(Mat4::IDENTITY, Arc::new(Brush::new(Vec::new()))),
+            ],
+        );
+
+        assert_eq!(report.brush_count, 3);
+        assert_eq!(report.rejected_brushes, 1);
+        assert_eq!(report.walkable_count, navmesh.polygons().len());
+        assert_eq!(report.link_count(), navmesh.links().len());
+        assert_eq!(report.walk_links, navmesh.links().len());
+        assert_eq!(
+            report.polygon_count,
+            report.rejected_slopes() + report.walkable_count
+        );
+
+        // Only the bottom of the floor faces down, and the sides are vertical
+        assert_eq!(report.slope_histogram[0], 1);
+        assert_eq!(
+            report.slope_histogram[SLOPE_BUCKETS / 2],
+            report.rejected_slopes() - 1
+        );
+        assert!(report.edge_plane_count > 0);
+
+        // Either side of the wall, and the top of it
+        assert_eq!(navmesh.polygons().len(), 3);
+        assert!(report.to_string().starts_with("3 brushes (1 rejected)"));
+    }
+}
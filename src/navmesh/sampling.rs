@@ -0,0 +1,86 @@
+use glam::Vec3;
+use rand::Rng;
+
+use super::Navmesh;
+
+impl Navmesh {
+    /// Returns a uniformly distributed random point on the walkable polygons.
+    ///
+    /// Returns `None` if there is no walkable area.
+    pub fn sample_point(&self, rng: &mut impl Rng) -> Option<Vec3> {
+        let total = self.total_walkable_area();
+        if total <= 0.0 {
+            return None;
+        }
+
+        // Pick a triangle weighted by its area
+        let mut remaining = rng.random_range(0.0..total);
+        let mut last = None;
+        for (_, face) in self.walkable_polygons() {
+            for [a, b, c] in face.triangulate() {
+                let area = (b - a).cross(c - a).length() * 0.5;
+                last = Some([a, b, c]);
+
+                if remaining < area {
+                    return Some(sample_triangle(rng, a, b, c));
+                }
+
+                remaining -= area;
+            }
+        }
+
+        // Rounding errors may leave a sliver of the total area unaccounted for
+        last.map(|[a, b, c]| sample_triangle(rng, a, b, c))
+    }
+}
+
+fn sample_triangle(rng: &mut impl Rng, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let r1 = rng.random::<f32>().sqrt();
+    let r2 = rng.random::<f32>();
+
+    a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::Mat4;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        brush::{Brush, PositionedBrush},
+        navmesh::NavmeshSettings,
+    };
+
+    use super::*;
+
+    #[test]
+    fn sample_plane() {
+        let settings = NavmeshSettings {
+            agent_radius: 0.0,
+            ..Default::default()
+        };
+
+        let navmesh = Navmesh::new(
+            settings,
+            [PositionedBrush::new(
+                Mat4::from_scale(Vec3::splat(10.0)),
+                Arc::new(Brush::cube()),
+            )],
+        );
+
+        let area = navmesh.total_walkable_area();
+        assert!((area - 400.0).abs() < 0.1, "{area}");
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..10_000 {
+            let point = navmesh.sample_point(&mut rng).unwrap();
+            assert!(navmesh.closest_polygon(point).is_some(), "{point}");
+            assert!(point.x.abs() <= 10.0 && point.z.abs() <= 10.0);
+        }
+
+        let empty = Navmesh::new(settings, Vec::<PositionedBrush>::new());
+        assert_eq!(empty.sample_point(&mut rng), None);
+    }
+}
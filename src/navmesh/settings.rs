@@ -0,0 +1,241 @@
+use super::NavmeshSettings;
+
+/// An invalid combination of [`NavmeshSettings`]
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum SettingsError {
+    #[error("agent radius {0} must not be negative")]
+    NegativeRadius(f32),
+    #[error("agent height {0} must not be negative")]
+    NegativeHeight(f32),
+    #[error("max slope of {0} degrees must be within 0 and 90 degrees")]
+    InvalidSlope(f32),
+    #[error("max slope cosine {0} must be within 0 and 1")]
+    InvalidSlopeCosine(f32),
+    #[error("max step height {step} must not exceed the max drop height {drop}")]
+    StepAboveDrop { step: f32, drop: f32 },
+}
+
+impl NavmeshSettings {
+    /// Returns a builder starting from the default settings
+    pub fn builder() -> NavmeshSettingsBuilder {
+        NavmeshSettingsBuilder {
+            settings: Self::new(),
+            max_slope_degrees: None,
+        }
+    }
+
+    /// Returns the steepest walkable slope in degrees
+    pub fn max_slope_degrees(&self) -> f32 {
+        self.max_slope_cosine.clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    pub fn set_max_slope_degrees(&mut self, degrees: f32) {
+        self.max_slope_cosine = degrees.to_radians().cos();
+    }
+
+    /// Checks that the settings are within their valid ranges.
+    ///
+    /// A max drop height of `0` disables drops, and is not compared to the step height.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.agent_radius < 0.0 {
+            return Err(SettingsError::NegativeRadius(self.agent_radius));
+        }
+
+        if self.agent_height < 0.0 {
+            return Err(SettingsError::NegativeHeight(self.agent_height));
+        }
+
+        if !(0.0..=1.0).contains(&self.max_slope_cosine) {
+            return Err(SettingsError::InvalidSlopeCosine(self.max_slope_cosine));
+        }
+
+        if self.max_drop_height > 0.0 && self.max_step_height > self.max_drop_height {
+            return Err(SettingsError::StepAboveDrop {
+                step: self.max_step_height,
+                drop: self.max_drop_height,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds validated [`NavmeshSettings`].
+///
+/// Created by [`NavmeshSettings::builder`].
+#[derive(Debug, Clone, Copy)]
+pub struct NavmeshSettingsBuilder {
+    settings: NavmeshSettings,
+    /// Kept as is until built, so that out of range angles are reported rather than wrapped
+    max_slope_degrees: Option<f32>,
+}
+
+impl NavmeshSettingsBuilder {
+    pub fn max_step_height(mut self, max_step_height: f32) -> Self {
+        self.settings.max_step_height = max_step_height;
+        self
+    }
+
+    pub fn max_drop_height(mut self, max_drop_height: f32) -> Self {
+        self.settings.max_drop_height = max_drop_height;
+        self
+    }
+
+    /// Sets the steepest walkable slope, in degrees from the horizontal
+    pub fn max_slope_degrees(mut self, degrees: f32) -> Self {
+        self.max_slope_degrees = Some(degrees);
+        self
+    }
+
+    pub fn agent_radius(mut self, agent_radius: f32) -> Self {
+        self.settings.agent_radius = agent_radius;
+        self
+    }
+
+    pub fn agent_height(mut self, agent_height: f32) -> Self {
+        self.settings.agent_height = agent_height;
+        self
+    }
+
+    pub fn min_polygon_area(mut self, min_polygon_area: f32) -> Self {
+        self.settings.min_polygon_area = min_polygon_area;
+        self
+    }
+
+    pub fn slope_penalty(mut self, slope_penalty: f32) -> Self {
+        self.settings.slope_penalty = slope_penalty;
+        self
+    }
+
+    pub fn step_cost(mut self, step_cost: f32) -> Self {
+        self.settings.step_cost = step_cost;
+        self
+    }
+
+    pub fn search_radius(mut self, search_radius: f32) -> Self {
+        self.settings.search_radius = search_radius;
+        self
+    }
+
+    pub fn min_link_width(mut self, min_link_width: f32) -> Self {
+        self.settings.min_link_width = min_link_width;
+        self
+    }
+
+    pub fn merge_polygons(mut self, merge_polygons: bool) -> Self {
+        self.settings.merge_polygons = merge_polygons;
+        self
+    }
+
+    pub fn epsilon(mut self, epsilon: f32) -> Self {
+        self.settings.epsilon = epsilon;
+        self
+    }
+
+    pub fn query_grid_cell_size(mut self, cell_size: f32) -> Self {
+        self.settings.query_grid_cell_size = Some(cell_size);
+        self
+    }
+
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.settings.parallel = parallel;
+        self
+    }
+
+    pub fn build(self) -> Result<NavmeshSettings, SettingsError> {
+        let mut settings = self.settings;
+
+        if let Some(degrees) = self.max_slope_degrees {
+            if !(0.0..=90.0).contains(&degrees) {
+                return Err(SettingsError::InvalidSlope(degrees));
+            }
+
+            settings.set_max_slope_degrees(degrees);
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_settings() {
+        let settings = NavmeshSettings::builder()
+            .max_slope_degrees(45.0)
+            .max_step_height(0.4)
+            .agent_radius(0.3)
+            .max_drop_height(2.0)
+            .build()
+            .unwrap();
+
+        assert!((settings.max_slope_cosine - 0.5f32.sqrt()).abs() < 1e-5);
+        assert!((settings.max_slope_degrees() - 45.0).abs() < 1e-3);
+        assert_eq!(settings.max_step_height, 0.4);
+        assert_eq!(settings.agent_radius, 0.3);
+        assert_eq!(settings.max_drop_height, 2.0);
+
+        assert!(NavmeshSettings::builder().build().is_ok());
+        assert!(
+            !NavmeshSettings::builder()
+                .parallel(false)
+                .build()
+                .unwrap()
+                .parallel
+        );
+    }
+
+    #[test]
+    fn reject_settings() {
+        assert_eq!(
+            NavmeshSettings::builder()
+                .agent_radius(-0.1)
+                .build()
+                .unwrap_err(),
+            SettingsError::NegativeRadius(-0.1)
+        );
+
+        assert_eq!(
+            NavmeshSettings::builder()
+                .agent_height(-1.0)
+                .build()
+                .unwrap_err(),
+            SettingsError::NegativeHeight(-1.0)
+        );
+
+        for degrees in [-1.0, 91.0, f32::NAN] {
+            assert!(matches!(
+                NavmeshSettings::builder()
+                    .max_slope_degrees(degrees)
+                    .build(),
+                Err(SettingsError::InvalidSlope(_))
+            ));
+        }
+
+        assert_eq!(
+            NavmeshSettings::builder()
+                .max_step_height(1.0)
+                .max_drop_height(0.5)
+                .build()
+                .unwrap_err(),
+            SettingsError::StepAboveDrop {
+                step: 1.0,
+                drop: 0.5
+            }
+        );
+
+        // Degrees passed as a cosine
+        let settings = NavmeshSettings {
+            max_slope_cosine: 45.0,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            settings.validate(),
+            Err(SettingsError::InvalidSlopeCosine(45.0))
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt::{self, Display};
+
+use glam::Vec3;
+
+use crate::{brush::Face, edge::Edge3D, link::LinkKind, util::TOLERANCE};
+
+use super::Navmesh;
+
+/// Invariant violations and statistics of a navmesh, as returned by [`Navmesh::validate`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    pub polygon_count: usize,
+    pub link_count: usize,
+    /// Links referencing a polygon which does not exist
+    pub missing_polygon_links: Vec<usize>,
+    /// Links with an edge endpoint further than the tolerance from both polygons
+    pub detached_links: Vec<usize>,
+    /// Polygons with a non-finite normal or a slope which is not walkable
+    pub invalid_normals: Vec<usize>,
+    /// Links connecting the same polygons along the same edges as an earlier link
+    pub duplicate_links: Vec<usize>,
+    /// Polygons without any links.
+    ///
+    /// These are not an error, as a lone floor has nothing to link to.
+    pub isolated_polygons: Vec<usize>,
+}
+
+impl ValidationReport {
+    /// Returns true if no invariants are violated
+    pub fn is_ok(&self) -> bool {
+        self.missing_polygon_links.is_empty()
+            && self.detached_links.is_empty()
+            && self.invalid_normals.is_empty()
+            && self.duplicate_links.is_empty()
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "navmesh with {} polygons and {} links",
+            self.polygon_count, self.link_count
+        )?;
+
+        let sections = [
+            (
+                "links referencing missing polygons",
+                &self.missing_polygon_links,
+            ),
+            ("detached links", &self.detached_links),
+            ("polygons with invalid normals", &self.invalid_normals),
+            ("duplicate links", &self.duplicate_links),
+            ("isolated polygons", &self.isolated_polygons),
+        ];
+
+        for (name, indices) in sections {
+            writeln!(f, "  {} {name}: {indices:?}", indices.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Distance from a point to the closest edge of the polygon
+fn boundary_distance(face: &Face, point: Vec3) -> f32 {
+    face.edges()
+        .map(|(a, b)| Edge3D::new(a, b).closest_point(point).distance(point))
+        .fold(f32::MAX, f32::min)
+}
+
+fn same_edge(a: Edge3D, b: Edge3D) -> bool {
+    a.p1.distance(b.p1) < TOLERANCE && a.p2.distance(b.p2) < TOLERANCE
+}
+
+impl Navmesh {
+    /// Checks the invariants of the generated polygons and links
+    pub fn validate(&self) -> ValidationReport {
+        let polygons = &self.walkable_polygons;
+
+        let mut report = ValidationReport {
+            polygon_count: polygons.len(),
+            link_count: self.links.len(),
+            ..Default::default()
+        };
+
+        for (index, face) in polygons {
+            let normal = face.normal();
+            if !normal.is_finite() || normal.dot(Vec3::Y) <= self.settings.max_slope_cosine {
+                report.invalid_normals.push(index);
+            }
+
+            if self.polygon_links.get(&index).is_none_or(|v| v.is_empty()) {
+                report.isolated_polygons.push(index);
+            }
+        }
+
+        for (index, link) in &self.links {
+            let (Some(from), Some(to)) = (polygons.get(link.from()), polygons.get(link.to()))
+            else {
+                report.missing_polygon_links.push(index);
+                continue;
+            };
+
+            // Off-mesh links may connect any points on the polygons
+            let detached = !matches!(link.kind(), LinkKind::OffMesh { .. })
+                && [link.source_edge(), link.destination_edge()]
+                    .into_iter()
+                    .flat_map(|edge| [edge.p1, edge.p2])
+                    .any(|p| boundary_distance(from, p).min(boundary_distance(to, p)) > TOLERANCE);
+
+            if detached {
+                report.detached_links.push(index);
+            }
+
+            let duplicate = self
+                .links
+                .iter()
+                .take_while(|v| v.0 != index)
+                .any(|(_, v)| {
+                    v.from() == link.from()
+                        && v.to() == link.to()
+                        && same_edge(v.source_edge(), link.source_edge())
+                        && same_edge(v.destination_edge(), link.destination_edge())
+                });
+
+            if duplicate {
+                report.duplicate_links.push(index);
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{navmesh::NavmeshSettings, test_util::slab};
+
+    use super::*;
+
+    fn steps() -> Navmesh {
+        Navmesh::new(
+            NavmeshSettings::default(),
+            [slab(-2.0, 0.0), slab(0.0, 0.3), slab(2.0, 0.0)],
+        )
+    }
+
+    #[test]
+    fn validate_generated() {
+        let navmesh = steps();
+        let report = navmesh.validate();
+
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.polygon_count, navmesh.polygons().len());
+        assert_eq!(report.link_count, navmesh.links().len());
+    }
+
+    #[test]
+    fn validate_removed_polygon() {
+        let mut navmesh = steps();
+
+        let (removed, _) = navmesh.links().iter().next().unwrap();
+        let polygon = navmesh.links()[removed].from();
+        navmesh.walkable_polygons.remove(polygon);
+
+        let report = navmesh.validate();
+        assert!(!report.is_ok());
+
+        let expected = navmesh
+            .links()
+            .iter()
+            .filter(|v| v.1.from() == polygon || v.1.to() == polygon)
+            .map(|v| v.0)
+            .collect::<Vec<_>>();
+
+        assert!(!expected.is_empty());
+        assert_eq!(report.missing_polygon_links, expected);
+        assert!(report.to_string().contains(&format!("{:?}", expected)));
+    }
+
+    #[test]
+    fn validate_duplicate_link() {
+        let mut navmesh = steps();
+
+        let (_, &link) = navmesh.links().iter().next().unwrap();
+        let index = navmesh.links.insert(link);
+        navmesh
+            .polygon_links
+            .entry(link.from())
+            .or_default()
+            .push(index);
+
+        let report = navmesh.validate();
+        assert_eq!(report.duplicate_links, [index]);
+        assert!(!report.is_ok());
+    }
+}
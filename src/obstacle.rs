@@ -0,0 +1,307 @@
+use glam::{Quat, Vec3, Vec3Swizzles};
+use itertools::Itertools;
+use smallvec::SmallVec;
+
+use crate::{brush::Face, link::LinkKind};
+
+/// Identifies an obstacle placed on a navmesh
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObstacleId(pub(crate) usize);
+
+/// An oriented bounding box
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obb {
+    pub center: Vec3,
+    pub half_extents: Vec3,
+    pub rotation: Quat,
+}
+
+impl Obb {
+    pub fn new(center: Vec3, half_extents: Vec3, rotation: Quat) -> Self {
+        Self {
+            center,
+            half_extents,
+            rotation,
+        }
+    }
+
+    /// Constructs an axis aligned box from its corners
+    pub fn from_aabb(min: Vec3, max: Vec3) -> Self {
+        Self::new((min + max) * 0.5, (max - min) * 0.5, Quat::IDENTITY)
+    }
+
+    pub fn to_local(&self, point: Vec3) -> Vec3 {
+        self.rotation.inverse() * (point - self.center)
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        let p = self.to_local(point);
+        p.abs().cmple(self.half_extents).all()
+    }
+
+    /// Separating axis test between the box and a convex polygon
+    pub fn intersects_face(&self, face: &Face) -> bool {
+        let points: SmallVec<[Vec3; 8]> = face.points().iter().map(|&p| self.to_local(p)).collect();
+        let h = self.half_extents;
+
+        let separated = |axis: Vec3| {
+            if axis.length_squared() < f32::EPSILON {
+                return false;
+            }
+
+            let r = h.dot(axis.abs());
+            let (min, max) = points
+                .iter()
+                .map(|p| p.dot(axis))
+                .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                    (min.min(v), max.max(v))
+                });
+
+            min > r || max < -r
+        };
+
+        let edges: SmallVec<[Vec3; 8]> = points
+            .iter()
+            .circular_tuple_windows()
+            .map(|(&a, &b)| b - a)
+            .collect();
+
+        if [Vec3::X, Vec3::Y, Vec3::Z].into_iter().any(separated) {
+            return false;
+        }
+
+        if separated(self.rotation.inverse() * face.normal()) {
+            return false;
+        }
+
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            for &edge in &edges {
+                if separated(axis.cross(edge)) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Returns true if the segment from `a` to `b` passes through the box.
+    ///
+    /// Segments which only touch the box at a single point, such as the edges of neighboring
+    /// polygons meeting at one of its corners, do not pass through it.
+    pub fn intersects_segment(&self, a: Vec3, b: Vec3) -> bool {
+        let origin = self.to_local(a);
+        let dir = self.rotation.inverse() * (b - a);
+
+        let (mut min, mut max) = (0.0f32, 1.0f32);
+        for axis in 0..3 {
+            let (o, d, h) = (origin[axis], dir[axis], self.half_extents[axis]);
+            if d.abs() < f32::EPSILON {
+                if o.abs() > h {
+                    return false;
+                }
+
+                continue;
+            }
+
+            let (t1, t2) = ((-h - o) / d, (h - o) / d);
+            min = min.max(t1.min(t2));
+            max = max.min(t1.max(t2));
+        }
+
+        min < max || (min == max && a == b)
+    }
+}
+
+/// An upright cylinder, such as the footprint of a character or pillar
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cylinder {
+    pub center: Vec3,
+    pub radius: f32,
+    pub half_height: f32,
+}
+
+impl Cylinder {
+    pub fn new(center: Vec3, radius: f32, half_height: f32) -> Self {
+        Self {
+            center,
+            radius,
+            half_height,
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        let p = point - self.center;
+        p.y.abs() <= self.half_height && p.xz().length() <= self.radius
+    }
+
+    /// Returns true if the face overlaps the height range of the cylinder and its footprint
+    /// comes within the radius of the center
+    pub fn intersects_face(&self, face: &Face) -> bool {
+        let (min, max) = face
+            .points()
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), p| {
+                (min.min(p.y), max.max(p.y))
+            });
+
+        if min > self.center.y + self.half_height || max < self.center.y - self.half_height {
+            return false;
+        }
+
+        face.vertical_projection(self.center)
+            .is_some_and(|v| v.horizontal_distance <= self.radius)
+    }
+
+    /// Returns true if the segment from `a` to `b` passes through the cylinder
+    pub fn intersects_segment(&self, a: Vec3, b: Vec3) -> bool {
+        let (a, b) = (a - self.center, b - self.center);
+        let dir = b - a;
+
+        // Clip the segment to the height range
+        let (mut min, mut max) = (0.0f32, 1.0f32);
+        if dir.y.abs() < f32::EPSILON {
+            if a.y.abs() > self.half_height {
+                return false;
+            }
+        } else {
+            let t1 = (-self.half_height - a.y) / dir.y;
+            let t2 = (self.half_height - a.y) / dir.y;
+            min = t1.min(t2).max(0.0);
+            max = t1.max(t2).min(1.0);
+        }
+
+        if min > max {
+            return false;
+        }
+
+        let (a, dir) = (a.xz(), dir.xz());
+        let t = (-a.dot(dir) / dir.length_squared().max(f32::EPSILON)).clamp(min, max);
+        (a + dir * t).length() <= self.radius
+    }
+}
+
+/// The volume an obstacle blocks
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObstacleShape {
+    Box(Obb),
+    Cylinder(Cylinder),
+}
+
+impl ObstacleShape {
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        match self {
+            Self::Box(v) => v.contains_point(point),
+            Self::Cylinder(v) => v.contains_point(point),
+        }
+    }
+
+    pub fn intersects_face(&self, face: &Face) -> bool {
+        match self {
+            Self::Box(v) => v.intersects_face(face),
+            Self::Cylinder(v) => v.intersects_face(face),
+        }
+    }
+
+    /// Returns true if the face lies entirely within the obstacle.
+    ///
+    /// Both the face and the shape are convex, so it is enough for every vertex to be inside.
+    pub fn covers_face(&self, face: &Face) -> bool {
+        face.points().iter().all(|&p| self.contains_point(p))
+    }
+
+    pub fn intersects_segment(&self, a: Vec3, b: Vec3) -> bool {
+        match self {
+            Self::Box(v) => v.intersects_segment(a, b),
+            Self::Cylinder(v) => v.intersects_segment(a, b),
+        }
+    }
+
+    /// Returns true if the link can not be crossed with the obstacle in place.
+    ///
+    /// Links are blocked when either edge passes through the obstacle, and off-mesh links when
+    /// the straight line between their ends does.
+    pub(crate) fn intersects_link(&self, kind: &LinkKind) -> bool {
+        match *kind {
+            LinkKind::Walk(e) => self.intersects_segment(e.p1, e.p2),
+            LinkKind::StepUp(a, b) | LinkKind::StepDown(a, b) | LinkKind::Drop(a, b) => {
+                self.intersects_segment(a.p1, a.p2) || self.intersects_segment(b.p1, b.p2)
+            }
+            LinkKind::OffMesh { from, to, .. } => self.intersects_segment(from, to),
+        }
+    }
+}
+
+impl From<Obb> for ObstacleShape {
+    fn from(v: Obb) -> Self {
+        Self::Box(v)
+    }
+}
+
+impl From<Cylinder> for ObstacleShape {
+    fn from(v: Cylinder) -> Self {
+        Self::Cylinder(v)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{vec3, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn obb_face() {
+        let face = Face::triangle(
+            vec3(-1.0, 0.0, -1.0),
+            vec3(-1.0, 0.0, 1.0),
+            vec3(1.0, 0.0, -1.0),
+        );
+
+        assert!(Obb::from_aabb(vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5)).intersects_face(&face));
+        assert!(!Obb::from_aabb(vec3(-0.5, 0.5, -0.5), vec3(0.5, 1.0, 0.5)).intersects_face(&face));
+
+        // Beyond the hypotenuse
+        assert!(!Obb::from_aabb(vec3(0.6, -0.5, 0.6), vec3(1.0, 0.5, 1.0)).intersects_face(&face));
+
+        let rotated = Obb::new(
+            vec3(0.05, 0.0, 0.05),
+            vec3(0.1, 0.5, 0.5),
+            Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_4),
+        );
+        assert!(rotated.intersects_face(&face));
+    }
+
+    #[test]
+    fn segments() {
+        let obb = Obb::from_aabb(vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5));
+        assert!(obb.intersects_segment(vec3(-2.0, 0.0, 0.0), vec3(2.0, 0.0, 0.0)));
+        assert!(!obb.intersects_segment(vec3(-2.0, 1.0, 0.0), vec3(2.0, 1.0, 0.0)));
+        assert!(!obb.intersects_segment(vec3(-2.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0)));
+
+        // Along a face, and ending on a corner
+        assert!(obb.intersects_segment(vec3(-2.0, 0.5, 0.5), vec3(2.0, 0.5, 0.5)));
+        assert!(!obb.intersects_segment(vec3(-2.0, 0.5, 0.5), vec3(-0.5, 0.5, 0.5)));
+
+        let cylinder = Cylinder::new(Vec3::ZERO, 1.0, 0.5);
+        assert!(cylinder.intersects_segment(vec3(-2.0, 0.0, 0.9), vec3(2.0, 0.0, 0.9)));
+        assert!(!cylinder.intersects_segment(vec3(-2.0, 0.0, 1.1), vec3(2.0, 0.0, 1.1)));
+        assert!(!cylinder.intersects_segment(vec3(0.0, 1.0, -2.0), vec3(0.0, 1.0, 2.0)));
+
+        // Descending through the top
+        assert!(cylinder.intersects_segment(vec3(0.0, 2.0, 0.0), vec3(1.0, -1.0, 0.0)));
+        assert!(!cylinder.intersects_segment(vec3(0.0, 2.0, 0.0), vec3(3.0, -1.0, 0.0)));
+
+        let face = Face::triangle(
+            vec3(1.5, 0.0, -1.0),
+            vec3(1.5, 0.0, 1.0),
+            vec3(3.0, 0.0, -1.0),
+        );
+        assert!(!cylinder.intersects_face(&face));
+        assert!(Cylinder::new(Vec3::ZERO, 1.6, 0.5).intersects_face(&face));
+    }
+}
@@ -1,4 +1,6 @@
-use glam::{Vec3, Vec4, Vec4Swizzles};
+use glam::Vec3;
+use itertools::Itertools;
+use smallvec::SmallVec;
 
 use crate::{
     brush::{Face, FaceIntersect},
@@ -16,10 +18,10 @@ impl Plane {
         Self { normal, distance }
     }
 
-    pub fn from_face(face: Face) -> Self {
+    pub fn from_face(face: &Face) -> Self {
         let normal = face.normal();
         assert!(normal.is_finite());
-        let distance = face.p1.dot(normal);
+        let distance = face.points()[0].dot(normal);
 
         Self { normal, distance }
     }
@@ -40,104 +42,152 @@ impl Plane {
         None
     }
 
-    pub fn classify_face(&self, face: Face) -> FaceIntersect {
-        let d1 = self.distance_to_point(face.p1);
-        let d2 = self.distance_to_point(face.p2);
-        let d3 = self.distance_to_point(face.p3);
+    /// Returns the distance along the ray at which it crosses the plane.
+    ///
+    /// Rays with `|normal . dir| < epsilon` are treated as parallel and never cross. Origins up
+    /// to `epsilon` behind the plane are considered to lie on it and return `0`, while rays
+    /// pointing away from the plane return `None`.
+    pub fn intersect_ray_with_tolerance(
+        &self,
+        ray_origin: Vec3,
+        ray_direction: Vec3,
+        epsilon: f32,
+    ) -> Option<f32> {
+        let denom = self.normal.dot(ray_direction);
+        if denom.abs() < epsilon {
+            return None;
+        }
 
-        if d1.abs() <= TOLERANCE && d2.abs() <= TOLERANCE && d3.abs() <= TOLERANCE {
-            if face.normal().dot(self.normal) > 0.0 {
-                return FaceIntersect::CoplanarFront;
-            } else {
-                return FaceIntersect::CoplanarBack;
-            }
+        let t = -self.distance_to_point(ray_origin) / denom;
+        if t < -epsilon {
+            return None;
         }
 
-        if d1 >= -TOLERANCE && d2 >= -TOLERANCE && d3 >= -TOLERANCE {
-            FaceIntersect::Front
-        } else if d1 <= TOLERANCE && d2 <= TOLERANCE && d3 <= TOLERANCE {
-            FaceIntersect::Back
-        } else {
-            FaceIntersect::Intersect
+        Some(t.max(0.0))
+    }
+
+    pub fn classify_face(&self, face: &Face) -> FaceIntersect {
+        self.classify_face_with_epsilon(face, TOLERANCE)
+    }
+
+    /// Classifies the face, treating points within `epsilon` of the plane as on the plane
+    pub fn classify_face_with_epsilon(&self, face: &Face, epsilon: f32) -> FaceIntersect {
+        let mut front = false;
+        let mut back = false;
+
+        for &p in face.points() {
+            let distance = self.distance_to_point(p);
+            front |= distance > epsilon;
+            back |= distance < -epsilon;
+        }
+
+        match (front, back) {
+            (false, false) if face.normal().dot(self.normal) > 0.0 => FaceIntersect::CoplanarFront,
+            (false, false) => FaceIntersect::CoplanarBack,
+            (true, false) => FaceIntersect::Front,
+            (false, true) => FaceIntersect::Back,
+            (true, true) => FaceIntersect::Intersect,
         }
     }
 
     pub fn split_face(
         &self,
-        face: Face,
+        face: &Face,
         front_result: &mut Vec<Face>,
         back_result: &mut Vec<Face>,
     ) {
-        let mut front_count = 0;
-        let mut back_count = 0;
-        let mut coplanar_count = 0;
+        self.split_face_with_epsilon(face, front_result, back_result, TOLERANCE)
+    }
 
-        let mut front = [Vec4::NAN; 3];
-        let mut back = [Vec4::NAN; 3];
-        let mut coplanar = [Vec3::NAN; 3];
+    /// Splits the face along the plane, treating points within `epsilon` of the plane as on the
+    /// plane
+    pub fn split_face_with_epsilon(
+        &self,
+        face: &Face,
+        front_result: &mut Vec<Face>,
+        back_result: &mut Vec<Face>,
+        epsilon: f32,
+    ) {
+        let distances: SmallVec<[f32; 8]> = face
+            .points()
+            .iter()
+            .map(|&p| self.distance_to_point(p))
+            .collect();
 
-        for p in face.points() {
-            let distance = self.distance_to_point(p);
-            if distance >= TOLERANCE {
-                front[front_count] = p.extend(distance);
-                front_count += 1;
-            } else if distance <= -TOLERANCE {
-                back[back_count] = p.extend(distance);
-                back_count += 1;
+        let front_count = distances.iter().filter(|&&d| d >= epsilon).count();
+        let back_count = distances.iter().filter(|&&d| d <= -epsilon).count();
+
+        #[cfg(debug_assertions)]
+        let (front_len, back_len) = (front_result.len(), back_result.len());
+
+        if back_count == 0 && front_count == 0 {
+            // Degenerate, all points lie on the plane
+            if face.normal().dot(self.normal) >= 0.0 {
+                front_result.push(face.clone());
             } else {
-                coplanar[coplanar_count] = p;
-                coplanar_count += 1;
+                back_result.push(face.clone());
             }
-        }
+        } else if back_count == 0 {
+            // Remaining points are coplanar, no split is needed
+            front_result.push(face.clone());
+        } else if front_count == 0 {
+            back_result.push(face.clone());
+        } else {
+            let mut front = SmallVec::<[Vec3; 8]>::new();
+            let mut back = SmallVec::<[Vec3; 8]>::new();
 
-        let normal = face.normal();
-        let orient = |face: Face| {
-            if face.normal().dot(normal) < 0.0 {
-                Face::new(face.p3, face.p2, face.p1)
-            } else {
-                face
+            let points = face.points().iter().copied().zip(distances.iter().copied());
+            for ((a, da), (b, db)) in points.circular_tuple_windows() {
+                if da > -epsilon {
+                    front.push(a);
+                }
+                if da < epsilon {
+                    back.push(a);
+                }
+
+                if (da >= epsilon && db <= -epsilon) || (da <= -epsilon && db >= epsilon) {
+                    let i = a.lerp(b, da / (da - db));
+                    front.push(i);
+                    back.push(i);
+                }
+            }
+
+            // Intersections landing next to an existing vertex produce slivers, which are
+            // discarded rather than emitted with an unusable normal
+            let mut emitted = false;
+            for (points, result) in [(front, &mut *front_result), (back, &mut *back_result)] {
+                if let Some(split) = Face::try_new(points).filter(|v| !v.is_degenerate(epsilon)) {
+                    result.push(split.with_flags(face.flags()));
+                    emitted = true;
+                }
+            }
+
+            // Both halves of a face barely larger than a sliver may be slivers, so keep the face
+            // whole on the side it extends furthest into rather than leaving a hole
+            if !emitted {
+                let front_extent = distances.iter().copied().fold(0.0, f32::max);
+                let back_extent = -distances.iter().copied().fold(0.0, f32::min);
+                if front_extent >= back_extent {
+                    front_result.push(face.clone());
+                } else {
+                    back_result.push(face.clone());
+                }
             }
-        };
-
-        if coplanar_count == 1 {
-            assert_eq!(back_count, 1);
-            assert_eq!(front_count, 1);
-            let back = back[0];
-            let front = front[0];
-            let coplanar = coplanar[0];
-
-            let i1 = back.xyz().lerp(front.xyz(), back.w / (back.w - front.w));
-
-            front_result.push(orient(Face::new(coplanar, front.xyz(), i1)));
-            back_result.push(orient(Face::new(coplanar, i1, back.xyz())));
-        } else if front_count == 1 && back_count == 2 {
-            // One point in front, two in back
-            let f = front[0].xyz();
-
-            let back1 = back[0].xyz();
-            let back2 = back[1].xyz();
-
-            let i1 = f.lerp(back1, front[0].w / (front[0].w - back[0].w));
-            let i2 = f.lerp(back2, front[0].w / (front[0].w - back[1].w));
-
-            front_result.push(orient(Face::new(f, i1, i2)));
-            back_result.push(orient(Face::new(back1, back2, i1)));
-            back_result.push(orient(Face::new(i1, back2, i2)));
-        } else if front_count == 2 && back_count == 1 {
-            let b = back[0].xyz();
-            let front1 = front[0].xyz();
-            let front2 = front[1].xyz();
-
-            // Two points in front, one in back
-            let t1 = back[0].w / (back[0].w - front[0].w);
-            let t2 = back[0].w / (back[0].w - front[1].w);
-
-            let i1 = b.lerp(front1, t1);
-            let i2 = b.lerp(front2, t2);
-
-            back_result.push(orient(Face::new(b, i1, i2)));
-            front_result.push(orient(Face::new(front1, front2, i1)));
-            front_result.push(orient(Face::new(i1, front2, i2)));
+        }
+
+        #[cfg(debug_assertions)]
+        {
+            let split_area: f32 = front_result[front_len..]
+                .iter()
+                .chain(&back_result[back_len..])
+                .map(|v| v.area())
+                .sum();
+
+            debug_assert!(
+                face.area() - split_area <= TOLERANCE * face.area().max(1.0),
+                "split lost area: {} -> {split_area}",
+                face.area()
+            );
         }
     }
 
@@ -148,3 +198,111 @@ impl Plane {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn intersect_ray_tolerance() {
+        let plane = Plane::new(Vec3::Y, 1.0);
+
+        let t = plane.intersect_ray_with_tolerance(Vec3::ZERO, Vec3::Y * 2.0, TOLERANCE);
+        assert_eq!(t, Some(0.5));
+
+        // Pointing away
+        assert_eq!(
+            plane.intersect_ray_with_tolerance(Vec3::ZERO, -Vec3::Y, TOLERANCE),
+            None
+        );
+
+        // Nearly parallel
+        let dir = vec3(1.0, 1e-5, 0.0).normalize();
+        assert_eq!(
+            plane.intersect_ray_with_tolerance(Vec3::ZERO, dir, TOLERANCE),
+            None
+        );
+        assert!(plane.intersect_ray(Vec3::ZERO, dir).unwrap() > 1e4);
+
+        // Starting on the plane, slightly past it
+        let origin = vec3(0.0, 1.0 + TOLERANCE * 0.5, 0.0);
+        assert_eq!(
+            plane.intersect_ray_with_tolerance(origin, Vec3::Y, TOLERANCE),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn split_through_vertex() {
+        let face = Face::triangle(
+            vec3(0.0, 0.0, 0.0),
+            vec3(2.0, 0.0, 0.0),
+            vec3(1.0, 0.0, -2.0),
+        );
+
+        // Passes through the last vertex and the middle of the opposite edge
+        let plane = Plane::new(Vec3::X, 1.0);
+        assert!(matches!(
+            plane.classify_face(&face),
+            FaceIntersect::Intersect
+        ));
+
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        plane.split_face(&face, &mut front, &mut back);
+
+        assert_eq!(front.len(), 1);
+        assert_eq!(back.len(), 1);
+
+        for split in front.iter().chain(&back) {
+            assert_eq!(split.points().len(), 3);
+            assert!(split.normal().is_finite());
+            assert!((split.area() - 1.0).abs() < 1e-5);
+        }
+
+        // The intersections land within two tolerances of the far vertex, leaving a sliver in
+        // front of the plane
+        let face = Face::triangle(
+            vec3(0.0, 0.0, 0.0),
+            vec3(2.0, 0.0, 0.0),
+            vec3(1.0, 0.0, -0.0015),
+        );
+
+        let plane = Plane::new(Vec3::X, 2.0 - 2.0 * TOLERANCE);
+        assert!(matches!(
+            plane.classify_face(&face),
+            FaceIntersect::Intersect
+        ));
+
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        plane.split_face(&face, &mut front, &mut back);
+
+        assert!(front.is_empty());
+        assert_eq!(back.len(), 1);
+        assert!(!back[0].is_degenerate(TOLERANCE));
+
+        // A thin face straddling the plane, which would be cut into two slivers
+        let face = Face::triangle(
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.004, 0.0, 0.0),
+            vec3(0.002, 0.0, -0.0006),
+        );
+        assert!(!face.is_degenerate(TOLERANCE));
+
+        let plane = Plane::new(Vec3::X, 0.0021);
+        assert!(matches!(
+            plane.classify_face(&face),
+            FaceIntersect::Intersect
+        ));
+
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        plane.split_face(&face, &mut front, &mut back);
+
+        assert!(front.is_empty());
+        assert_eq!(back, [face]);
+    }
+}
@@ -1,3 +1,7 @@
+/// A closed interval along a line.
+///
+/// Spans with a `NaN` bound are considered empty, so that degenerate geometry does not produce
+/// links or gaps.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Span {
     pub min: f32,
@@ -14,14 +18,91 @@ impl Span {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.min >= self.max
+        self.min >= self.max || self.min.is_nan() || self.max.is_nan()
+    }
+
+    /// Returns true if both bounds are finite and in order
+    pub fn is_valid(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite() && self.min <= self.max
+    }
+
+    pub fn length(&self) -> f32 {
+        if self.is_empty() {
+            0.0
+        } else {
+            self.max - self.min
+        }
+    }
+
+    pub fn contains(&self, value: f32) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    /// Clamps the value to within the span.
+    ///
+    /// Unlike [`f32::clamp`], this does not panic for empty spans, but returns the closest
+    /// bound.
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.max(self.min).min(self.max)
     }
 
     pub fn intersect(&self, other: Self) -> Self {
-        if self.is_empty() || other.is_empty() {
+        let result = Self::new(self.min.max(other.min), self.max.min(other.max));
+        if self.is_empty() || other.is_empty() || result.is_empty() {
             Self::empty()
         } else {
-            Self::new(self.min.max(other.min), self.max.min(other.max))
+            result
         }
     }
+
+    /// Returns the smallest span containing both spans, ignoring empty spans
+    pub fn union(&self, other: Self) -> Self {
+        match (self.is_empty(), other.is_empty()) {
+            (true, true) => Self::empty(),
+            (true, false) => other,
+            (false, true) => *self,
+            (false, false) => Self::new(self.min.min(other.min), self.max.max(other.max)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn span_operations() {
+        let a = Span::new(0.0, 2.0);
+        let b = Span::new(1.0, 4.0);
+
+        assert_eq!(a.intersect(b), Span::new(1.0, 2.0));
+        assert_eq!(a.union(b), Span::new(0.0, 4.0));
+        assert_eq!(a.length(), 2.0);
+        assert!(a.contains(2.0) && !a.contains(2.5));
+        assert_eq!(b.clamp(-1.0), 1.0);
+        assert_eq!(b.clamp(5.0), 4.0);
+
+        // Disjoint
+        assert!(a.intersect(Span::new(3.0, 4.0)).is_empty());
+        assert_eq!(a.union(Span::empty()), a);
+    }
+
+    #[test]
+    fn span_nan() {
+        let nan = Span::new(f32::NAN, 1.0);
+        assert!(nan.is_empty());
+        assert!(!nan.is_valid());
+        assert_eq!(nan.length(), 0.0);
+        assert!(!nan.contains(0.5));
+
+        let a = Span::new(0.0, 2.0);
+        assert!(a.intersect(nan).is_empty());
+        assert!(nan.intersect(a).is_empty());
+        assert!(a.intersect(Span::new(0.0, f32::NAN)).is_empty());
+        assert_eq!(a.union(nan), a);
+
+        assert!(a.is_valid());
+        assert!(!Span::new(0.0, f32::INFINITY).is_valid());
+        assert!(!Span::new(1.0, 0.0).is_valid());
+    }
 }
@@ -0,0 +1,31 @@
+//! Brushes shared by the tests
+
+use std::sync::Arc;
+
+use glam::{vec3, Mat4, Vec3};
+
+use crate::{
+    brush::{Brush, PositionedBrush},
+    navmesh::{Navmesh, NavmeshSettings},
+};
+
+/// A 2x2 slab of floor centered at `x`, `y`
+pub(crate) fn slab(x: f32, y: f32) -> PositionedBrush {
+    PositionedBrush::new(
+        Mat4::from_translation(vec3(x, y, 0.0)) * Mat4::from_scale(vec3(1.0, 0.2, 1.0)),
+        Arc::new(Brush::cube()),
+    )
+}
+
+/// A cube scaled by `scale` and moved to `translation`
+pub(crate) fn cube(translation: Vec3, scale: Vec3) -> PositionedBrush {
+    PositionedBrush::new(
+        Mat4::from_translation(translation) * Mat4::from_scale(scale),
+        Arc::new(Brush::cube()),
+    )
+}
+
+/// Generates a navmesh from `brushes` with the default settings
+pub(crate) fn navmesh_from(brushes: impl IntoIterator<Item = PositionedBrush>) -> Navmesh {
+    Navmesh::new(NavmeshSettings::default(), brushes)
+}
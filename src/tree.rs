@@ -1,92 +1,190 @@
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+};
+
+use glam::{IVec3, Vec3};
 use itertools::Itertools;
 use slab::Slab;
 
 use crate::{
     brush::{Face, FaceIntersect},
     plane::Plane,
+    util::TOLERANCE,
 };
 
 #[derive(Clone)]
 pub struct BspTree {
     root: usize,
     nodes: Slab<Node>,
+    /// Distance within which points are considered to lie on a plane
+    epsilon: f32,
+    /// Large subtrees are built on the rayon thread pool
+    parallel: bool,
 }
 
 impl BspTree {
     pub fn new(root: usize, nodes: Slab<Node>) -> Self {
-        Self { root, nodes }
+        Self {
+            root,
+            nodes,
+            epsilon: TOLERANCE,
+            parallel: true,
+        }
     }
 
     /// Constructs a new bsp tree from a list of polygons
     pub fn build(polygons: &[Face]) -> Option<Self> {
+        Self::build_with_epsilon(polygons, TOLERANCE)
+    }
+
+    /// Constructs a new bsp tree, treating points within `epsilon` of a plane as on the plane.
+    ///
+    /// The epsilon is kept for all later operations on the tree, and should be scaled with the
+    /// units of the geometry.
+    pub fn build_with_epsilon(polygons: &[Face], epsilon: f32) -> Option<Self> {
+        Self::build_with_parallelism(polygons, epsilon, true)
+    }
+
+    /// Constructs a new bsp tree, building the halves of large nodes in parallel only if
+    /// `parallel` is set and the `rayon` feature is enabled.
+    ///
+    /// The choice is kept for all later operations on the tree.
+    pub(crate) fn build_with_parallelism(
+        polygons: &[Face],
+        epsilon: f32,
+        parallel: bool,
+    ) -> Option<Self> {
         let mut nodes = Slab::new();
 
-        let root = Self::build_subtree(&mut nodes, polygons)?;
+        let root = Self::build_subtree(&mut nodes, polygons, epsilon, parallel)?;
 
-        Some(Self { root, nodes })
+        Some(Self {
+            root,
+            nodes,
+            epsilon,
+            parallel,
+        })
     }
 
-    fn build_subtree(nodes: &mut Slab<Node>, polygons: &[Face]) -> Option<usize> {
-        let (&face, polygons) = polygons.split_first()?;
+    pub fn epsilon(&self) -> f32 {
+        self.epsilon
+    }
 
-        let plane = Plane::from_face(face);
+    fn build_subtree(
+        nodes: &mut Slab<Node>,
+        polygons: &[Face],
+        epsilon: f32,
+        parallel: bool,
+    ) -> Option<usize> {
+        // Degenerate faces have no plane to split by
+        let polygons = polygons
+            .iter()
+            .filter(|v| !v.is_degenerate(epsilon))
+            .cloned()
+            .collect_vec();
 
-        let mut coplanar = vec![face];
+        Self::build_nodes(nodes, polygons, epsilon, parallel)
+    }
 
-        let mut front = Vec::new();
-        let mut back = Vec::new();
+    #[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+    fn build_nodes(
+        nodes: &mut Slab<Node>,
+        polygons: Vec<Face>,
+        epsilon: f32,
+        parallel: bool,
+    ) -> Option<usize> {
+        let mut root = None;
 
-        for &face in polygons {
-            match plane.classify_face(face) {
-                FaceIntersect::Front => front.push(face),
-                FaceIntersect::Back => back.push(face),
-                FaceIntersect::CoplanarFront | FaceIntersect::CoplanarBack => coplanar.push(face),
-                FaceIntersect::Intersect => {
-                    plane.split_face(face, &mut front, &mut back);
-                }
+        // Polygons left to build, and the parent node and side they are attached to
+        let mut stack = vec![(None, polygons)];
+
+        while let Some((parent, polygons)) = stack.pop() {
+            if polygons.is_empty() {
+                continue;
             }
-        }
 
-        assert!(!coplanar.is_empty());
+            let plane = Plane::from_face(&polygons[choose_splitter(&polygons, epsilon)]);
+
+            let mut coplanar = Vec::new();
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+            partition(
+                &plane,
+                &polygons,
+                epsilon,
+                &mut coplanar,
+                &mut front,
+                &mut back,
+            );
 
-        let front = Self::build_subtree(nodes, &front);
-        let back = Self::build_subtree(nodes, &back);
+            assert!(!coplanar.is_empty());
 
-        let node = Node::new(plane, coplanar, front, back);
-        Some(nodes.insert(node))
-    }
+            let index = nodes.insert(Node::new(plane, coplanar, None, None));
+            match parent {
+                None => root = Some(index),
+                Some((parent, true)) => nodes[parent].front = Some(index),
+                Some((parent, false)) => nodes[parent].back = Some(index),
+            }
 
-    fn append_subtree(&mut self, index: usize, polygons: &[Face]) {
-        let node = &mut self.nodes[index];
-        let mut front = Vec::new();
-        let mut back = Vec::new();
-
-        for &face in polygons {
-            match node.plane.classify_face(face) {
-                FaceIntersect::Front => front.push(face),
-                FaceIntersect::Back => back.push(face),
-                FaceIntersect::CoplanarFront | FaceIntersect::CoplanarBack => {
-                    node.polygons.push(face)
-                }
-                FaceIntersect::Intersect => {
-                    node.plane.split_face(face, &mut front, &mut back);
-                }
+            // Large halves are built into separate slabs in parallel, and spliced in after. Nodes
+            // which split off only a few faces, such as the faces of a convex brush, continue
+            // on this thread rather than recursing for every face.
+            #[cfg(feature = "rayon")]
+            if parallel && front.len().min(back.len()) >= PARALLEL_BUILD_THRESHOLD {
+                let build = |polygons| {
+                    let mut nodes = Slab::new();
+                    let root = Self::build_nodes(&mut nodes, polygons, epsilon, true);
+                    (nodes, root)
+                };
+
+                let (front, back) = rayon::join(|| build(front), || build(back));
+                nodes[index].front = splice(nodes, front);
+                nodes[index].back = splice(nodes, back);
+                continue;
             }
+
+            stack.push((Some((index, false)), back));
+            stack.push((Some((index, true)), front));
         }
 
-        let front_node = node.front;
-        let back_node = node.back;
+        root
+    }
 
-        if let Some(node) = front_node {
-            self.append_subtree(node, &front);
-        } else {
-            self.nodes[index].front = Self::build_subtree(&mut self.nodes, &front)
-        }
+    fn append_subtree(&mut self, index: usize, polygons: &[Face]) {
+        let (epsilon, parallel) = (self.epsilon, self.parallel);
+        let mut stack = vec![(index, polygons.to_vec())];
+
+        while let Some((index, polygons)) = stack.pop() {
+            let node = &mut self.nodes[index];
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+
+            partition(
+                &node.plane,
+                &polygons,
+                epsilon,
+                &mut node.polygons,
+                &mut front,
+                &mut back,
+            );
 
-        if let Some(node) = back_node {
-            self.append_subtree(node, &back);
-        } else {
-            self.nodes[index].back = Self::build_subtree(&mut self.nodes, &back)
+            let front_node = node.front;
+            let back_node = node.back;
+
+            if let Some(node) = front_node {
+                stack.push((node, front));
+            } else {
+                self.nodes[index].front =
+                    Self::build_subtree(&mut self.nodes, &front, epsilon, parallel)
+            }
+
+            if let Some(node) = back_node {
+                stack.push((node, back));
+            } else {
+                self.nodes[index].back =
+                    Self::build_subtree(&mut self.nodes, &back, epsilon, parallel)
+            }
         }
     }
 
@@ -99,64 +197,79 @@ impl BspTree {
         self.clip_node_to_tree(self.root, other);
     }
 
+    /// Returns the parts of the faces outside the solid of the tree
+    pub fn clip_faces(&self, faces: &[Face]) -> Vec<Face> {
+        self.clip_polygons(self.root, faces)
+    }
+
     pub fn invert(&mut self) {
         self.invert_subtree(self.root);
     }
 
     fn invert_subtree(&mut self, node: usize) {
-        let node = &mut self.nodes[node];
-        for face in &mut node.polygons {
-            *face = face.flip();
-        }
+        let mut stack = vec![node];
 
-        node.plane.invert();
+        while let Some(node) = stack.pop() {
+            let node = &mut self.nodes[node];
+            for face in &mut node.polygons {
+                *face = face.flip();
+            }
 
-        std::mem::swap(&mut node.front, &mut node.back);
+            node.plane = node.plane.invert();
 
-        let front = node.front;
-        let back = node.back;
-        if let Some(front) = front {
-            self.invert_subtree(front);
-        }
+            std::mem::swap(&mut node.front, &mut node.back);
 
-        if let Some(back) = back {
-            self.invert_subtree(back);
+            stack.extend(node.front);
+            stack.extend(node.back);
         }
     }
 
     /// Clips all provided polygons to the provided subtree
     fn clip_polygons(&self, node: usize, polygons: &[Face]) -> Vec<Face> {
-        let node = &self.nodes[node];
-
-        let mut front = Vec::new();
-        let mut back = Vec::new();
-
-        for &face in polygons {
-            match node.plane.classify_face(face) {
-                FaceIntersect::Front => front.push(face),
-                FaceIntersect::Back => back.push(face),
-                FaceIntersect::CoplanarFront => front.push(face),
-                FaceIntersect::CoplanarBack => back.push(face),
-                FaceIntersect::Intersect => {
-                    node.plane.split_face(face, &mut front, &mut back);
+        let mut result = Vec::new();
+
+        // The front side is visited first to keep the polygons in tree order
+        let mut stack = vec![(node, polygons.to_vec())];
+
+        while let Some((node, polygons)) = stack.pop() {
+            let node = &self.nodes[node];
+
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+
+            for face in &polygons {
+                match node.plane.classify_face_with_epsilon(face, self.epsilon) {
+                    FaceIntersect::Front => front.push(face.clone()),
+                    FaceIntersect::Back => back.push(face.clone()),
+                    FaceIntersect::CoplanarFront => front.push(face.clone()),
+                    FaceIntersect::CoplanarBack => back.push(face.clone()),
+                    FaceIntersect::Intersect => {
+                        node.plane.split_face_with_epsilon(
+                            face,
+                            &mut front,
+                            &mut back,
+                            self.epsilon,
+                        );
+                    }
                 }
             }
-        }
 
-        let mut front = if let Some(front_node) = node.front {
-            self.clip_polygons(front_node, &front)
-        } else {
-            front
-        };
+            // Polygons behind a leaf are inside the solid and discarded. Empty sides are not
+            // descended into, which would otherwise walk the whole subtree for nothing
+            if let Some(back_node) = node.back.filter(|_| !back.is_empty()) {
+                stack.push((back_node, back));
+            }
 
-        let mut back = if let Some(back_node) = node.back {
-            self.clip_polygons(back_node, &back)
-        } else {
-            vec![]
-        };
+            if let Some(front_node) = node.front {
+                if !front.is_empty() {
+                    stack.push((front_node, front));
+                }
+            } else {
+                result.append(&mut front);
+            }
+        }
 
-        front.append(&mut back);
-        front
+        result
     }
 
     pub fn union(&mut self, mut other: BspTree) {
@@ -170,20 +283,42 @@ impl BspTree {
         self.append(&other.polygons());
     }
 
-    fn clip_node_to_tree(&mut self, node: usize, other: &Self) {
-        let node = &mut self.nodes[node];
-        let polygons = other.clip_polygons(other.root, &node.polygons);
+    /// Removes the volume of `other` from the tree
+    pub fn subtract(&mut self, mut other: BspTree) {
+        self.invert();
+        self.clip_to(&other);
+        other.clip_to(self);
 
-        node.polygons = polygons;
+        other.invert();
+        other.clip_to(self);
+        other.invert();
 
-        let front = node.front;
-        let back = node.back;
-        if let Some(front) = front {
-            self.clip_node_to_tree(front, other);
-        }
+        self.append(&other.polygons());
+        self.invert();
+    }
 
-        if let Some(back) = back {
-            self.clip_node_to_tree(back, other);
+    /// Keeps only the volume shared by both trees
+    pub fn intersect(&mut self, mut other: BspTree) {
+        self.invert();
+        other.clip_to(self);
+
+        other.invert();
+        self.clip_to(&other);
+        other.clip_to(self);
+
+        self.append(&other.polygons());
+        self.invert();
+    }
+
+    fn clip_node_to_tree(&mut self, node: usize, other: &Self) {
+        let mut stack = vec![node];
+
+        while let Some(node) = stack.pop() {
+            let node = &mut self.nodes[node];
+            node.polygons = other.clip_polygons(other.root, &node.polygons);
+
+            stack.extend(node.back);
+            stack.extend(node.front);
         }
     }
 
@@ -200,15 +335,313 @@ impl BspTree {
     //     Brush::new(result)
     // }
 
+    /// Returns the number of faces in the tree
+    pub fn face_count(&self) -> usize {
+        self.nodes.iter().map(|v| v.1.polygons.len()).sum()
+    }
+
+    /// Iterates all faces in the tree without allocating
+    pub fn iter_faces(&self) -> impl Iterator<Item = &Face> {
+        self.nodes.iter().flat_map(|v| &v.1.polygons)
+    }
+
+    /// Iterates the splitting plane and the faces lying in it for each node
+    pub fn iter_nodes(&self) -> impl Iterator<Item = (Plane, &[Face])> {
+        self.nodes.iter().map(|v| (v.1.plane, &v.1.polygons[..]))
+    }
+
+    /// Returns the first face hit by the ray.
+    ///
+    /// The tree is traversed front-to-back from the ray origin so that only the nodes along
+    /// the ray are visited.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3) -> Option<RayHit> {
+        self.raycast_node(self.root, origin, dir, 0.0, f32::INFINITY)
+    }
+
+    fn raycast_node(
+        &self,
+        node: usize,
+        origin: Vec3,
+        dir: Vec3,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<RayHit> {
+        let node = &self.nodes[node];
+        let plane = &node.plane;
+
+        let start_distance = plane.distance_to_point(origin + dir * t_min);
+        let denom = plane.normal.dot(dir);
+
+        // The side of the plane the ray starts in is visited first
+        let near_front = if start_distance.abs() > self.epsilon {
+            start_distance > 0.0
+        } else {
+            denom > 0.0
+        };
+
+        let (near, far) = if near_front {
+            (node.front, node.back)
+        } else {
+            (node.back, node.front)
+        };
+
+        let visit = |child: Option<usize>, t_min: f32, t_max: f32| {
+            child.and_then(|child| self.raycast_node(child, origin, dir, t_min, t_max))
+        };
+
+        let t_plane = if denom.abs() > f32::EPSILON {
+            -plane.distance_to_point(origin) / denom
+        } else {
+            f32::NAN
+        };
+
+        // The plane is not crossed within the interval
+        if !(t_plane >= t_min && t_plane <= t_max) {
+            return visit(near, t_min, t_max);
+        }
+
+        let hit_polygons = || {
+            let point = origin + dir * t_plane;
+            node.polygons
+                .iter()
+                .find(|v| v.contains_point(point))
+                .map(|face| RayHit {
+                    face: face.clone(),
+                    point,
+                    t: t_plane,
+                    entering: face.normal().dot(dir) < 0.0,
+                })
+        };
+
+        // The interval starts on the plane and then stays on the near side
+        if start_distance.abs() <= self.epsilon {
+            return hit_polygons().or_else(|| visit(near, t_min, t_max));
+        }
+
+        // Faces touching the plane may be hit marginally on the other side
+        let margin = self.epsilon / dir.length();
+
+        if let Some(hit) = visit(near, t_min, t_plane + margin) {
+            return Some(hit);
+        }
+
+        if let Some(hit) = hit_polygons() {
+            return Some(hit);
+        }
+
+        visit(far, (t_plane - margin).max(t_min), t_max)
+    }
+
+    /// Returns true if the point is inside the solid described by the tree.
+    ///
+    /// Points within the epsilon of a face are considered inside.
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        let mut stack = vec![self.root];
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let distance = node.plane.distance_to_point(point);
+
+            // Leaves behind a plane are solid, and leaves in front of it are empty
+            if distance <= self.epsilon {
+                match node.back {
+                    Some(back) => stack.push(back),
+                    None => return true,
+                }
+            }
+
+            if distance >= -self.epsilon {
+                stack.extend(node.front);
+            }
+        }
+
+        false
+    }
+
     pub fn polygons(&self) -> Vec<Face> {
+        self.iter_faces().cloned().collect_vec()
+    }
+
+    /// Consumes the tree and returns its faces without copying them
+    pub fn into_polygons(self) -> Vec<Face> {
         self.nodes
+            .into_iter()
+            .flat_map(|v| v.1.polygons)
+            .collect_vec()
+    }
+
+    /// Returns the polygons of the tree with artifacts of the boolean operations removed.
+    ///
+    /// Faces with an area below `min_area` or a normal which deviates from their node's plane
+    /// are dropped. Duplicated faces are only kept once, and faces which appear with both
+    /// windings are interior seams and removed entirely.
+    ///
+    /// Faces facing away from their node's plane are kept as they are. A node stores the
+    /// coplanar faces of both sides of its plane, such as the top of one brush and the bottom
+    /// of another resting on it, and flipping them would turn the surface inside out.
+    pub fn polygons_cleaned(&self, min_area: f32) -> Vec<Face> {
+        let faces = self.nodes.iter().flat_map(|(_, node)| {
+            node.polygons
+                .iter()
+                .map(|face| (node.plane, Cow::Borrowed(face)))
+        });
+
+        clean_polygons(faces, min_area, self.epsilon)
+    }
+
+    /// Consuming variant of [`Self::polygons_cleaned`], which moves the kept faces out of the
+    /// tree
+    pub fn into_polygons_cleaned(self, min_area: f32) -> Vec<Face> {
+        let epsilon = self.epsilon;
+        let faces = self.nodes.into_iter().flat_map(|(_, node)| {
+            let plane = node.plane;
+            node.polygons
+                .into_iter()
+                .map(move |face| (plane, Cow::Owned(face)))
+        });
+
+        clean_polygons(faces, min_area, epsilon)
+    }
+}
+
+fn clean_polygons<'a>(
+    faces: impl Iterator<Item = (Plane, Cow<'a, Face>)>,
+    min_area: f32,
+    epsilon: f32,
+) -> Vec<Face> {
+    let quantize = |face: &Face| {
+        face.points()
             .iter()
-            .flat_map(|v| &v.1.polygons)
-            .copied()
+            .map(|p| (p / epsilon).round().as_ivec3())
             .collect_vec()
+    };
+
+    // Rotate the points so that the smallest comes first, preserving winding
+    let canonical = |mut points: Vec<IVec3>| {
+        let min = points.iter().position_min_by_key(|p| p.to_array()).unwrap();
+        points.rotate_left(min);
+        points
+    };
+
+    let mut seen: HashMap<Vec<IVec3>, usize> = HashMap::new();
+    let mut result: Vec<Option<Face>> = Vec::new();
+
+    for (plane, face) in faces {
+        // Coplanar faces pointing against the node's plane are valid, only tilted ones are not
+        if face.area() < min_area || face.normal().dot(plane.normal).abs() < 0.99 {
+            continue;
+        }
+
+        let points = quantize(&face);
+        let mut mirrored = points.clone();
+        mirrored.reverse();
+
+        let key = canonical(points);
+
+        if let Some(&mirror) = seen.get(&canonical(mirrored)) {
+            result[mirror] = None;
+            continue;
+        }
+
+        if let Entry::Vacant(slot) = seen.entry(key) {
+            slot.insert(result.len());
+            result.push(Some(face.into_owned()));
+        }
+    }
+
+    result.into_iter().flatten().collect_vec()
+}
+
+/// Number of faces considered as the splitting plane of a node
+const SPLITTER_CANDIDATES: usize = 8;
+
+/// Number of faces both halves of a node must have for them to be built in parallel
+#[cfg(feature = "rayon")]
+const PARALLEL_BUILD_THRESHOLD: usize = 256;
+
+/// Moves the nodes of a separately built subtree into `nodes`, returning the new index of its
+/// root.
+///
+/// The subtree is built by inserting only, so its nodes are indexed densely in order.
+#[cfg(feature = "rayon")]
+fn splice(nodes: &mut Slab<Node>, (subtree, root): (Slab<Node>, Option<usize>)) -> Option<usize> {
+    let indices = subtree
+        .into_iter()
+        .map(|(_, node)| nodes.insert(node))
+        .collect_vec();
+
+    for &index in &indices {
+        let node = &mut nodes[index];
+        node.front = node.front.map(|v| indices[v]);
+        node.back = node.back.map(|v| indices[v]);
+    }
+
+    root.map(|v| indices[v])
+}
+
+/// Picks the face whose plane splits the fewest faces while dividing the rest evenly
+fn choose_splitter(polygons: &[Face], epsilon: f32) -> usize {
+    if polygons.len() <= 2 {
+        return 0;
+    }
+
+    let step = polygons.len().div_ceil(SPLITTER_CANDIDATES);
+
+    (0..polygons.len())
+        .step_by(step)
+        .min_by_key(|&candidate| {
+            let plane = Plane::from_face(&polygons[candidate]);
+
+            let (mut front, mut back, mut splits) = (0i64, 0i64, 0i64);
+            for face in polygons {
+                match plane.classify_face_with_epsilon(face, epsilon) {
+                    FaceIntersect::Front => front += 1,
+                    FaceIntersect::Back => back += 1,
+                    FaceIntersect::CoplanarFront | FaceIntersect::CoplanarBack => {}
+                    FaceIntersect::Intersect => splits += 1,
+                }
+            }
+
+            // Splits add faces to both sides, and are weighted more heavily than imbalance
+            splits * 8 + (front - back).abs()
+        })
+        .unwrap_or(0)
+}
+
+/// Sorts the polygons into the sides of the plane, splitting those which straddle it
+fn partition(
+    plane: &Plane,
+    polygons: &[Face],
+    epsilon: f32,
+    coplanar: &mut Vec<Face>,
+    front: &mut Vec<Face>,
+    back: &mut Vec<Face>,
+) {
+    for face in polygons {
+        match plane.classify_face_with_epsilon(face, epsilon) {
+            FaceIntersect::Front => front.push(face.clone()),
+            FaceIntersect::Back => back.push(face.clone()),
+            FaceIntersect::CoplanarFront | FaceIntersect::CoplanarBack => {
+                coplanar.push(face.clone())
+            }
+            FaceIntersect::Intersect => {
+                plane.split_face_with_epsilon(face, front, back, epsilon);
+            }
+        }
     }
 }
 
+/// The result of a ray intersecting a face of a [`BspTree`]
+#[derive(Debug, Clone)]
+pub struct RayHit {
+    pub face: Face,
+    pub point: Vec3,
+    /// Distance along the ray, in units of the ray direction
+    pub t: f32,
+    /// The ray is entering the solid through the front of the face
+    pub entering: bool,
+}
+
 #[derive(Clone)]
 pub struct Node {
     front: Option<usize>,
@@ -280,3 +713,308 @@ impl std::fmt::Debug for BspTree {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use glam::{vec3, Mat4};
+
+    use crate::brush::{Brush, PositionedBrush};
+
+    use super::*;
+
+    #[test]
+    fn union_identical() {
+        let mut tree = BspTree::build(Brush::cube().faces()).unwrap();
+        tree.union(BspTree::build(Brush::cube().faces()).unwrap());
+
+        let polygons = tree.polygons_cleaned(TOLERANCE * TOLERANCE);
+        assert_eq!(polygons.len(), Brush::cube().faces().len());
+
+        let area: f32 = polygons.iter().map(|v| v.area()).sum();
+        assert!((area - 24.0).abs() < 0.01, "{area}");
+    }
+
+    #[test]
+    fn degenerate_faces() {
+        let collinear = [Vec3::ZERO, Vec3::X, Vec3::X * 2.0];
+        assert!(Face::try_new(collinear).is_none());
+
+        let mut faces = Brush::cube().faces().to_vec();
+        faces.insert(0, Face::new(collinear));
+
+        let tree = BspTree::build(&faces).unwrap();
+        assert_eq!(tree.face_count(), 6);
+        assert!(tree.iter_faces().all(|v| v.normal().is_finite()));
+
+        assert!(BspTree::build(&[Face::new(collinear)]).is_none());
+    }
+
+    #[test]
+    fn contains_point() {
+        let tree = BspTree::build(Brush::cube().faces()).unwrap();
+
+        assert!(tree.contains_point(Vec3::ZERO));
+        assert!(tree.contains_point(vec3(0.9, -0.9, 0.5)));
+        assert!(!tree.contains_point(vec3(1.5, 0.0, 0.0)));
+        assert!(!tree.contains_point(vec3(0.0, 0.0, -3.0)));
+
+        // On a face, an edge and a corner
+        assert!(tree.contains_point(vec3(1.0, 0.0, 0.0)));
+        assert!(tree.contains_point(vec3(1.0, 1.0, 0.0)));
+        assert!(tree.contains_point(Vec3::ONE));
+        assert!(!tree.contains_point(vec3(1.0, 1.1, 0.0)));
+
+        let mut tree = cube(Vec3::ZERO, 2.0);
+        tree.subtract(cube(Vec3::ZERO, 1.0));
+        assert!(!tree.contains_point(Vec3::ZERO));
+        assert!(tree.contains_point(vec3(1.5, 0.0, 0.0)));
+
+        // Overlapping and disjoint unions
+        let mut tree = cube(Vec3::ZERO, 1.0);
+        tree.union(cube(vec3(1.5, 0.0, 0.0), 1.0));
+        tree.union(cube(vec3(6.0, 0.0, 0.0), 1.0));
+
+        let inside = [
+            Vec3::ZERO,
+            vec3(1.25, 0.0, 0.0),
+            vec3(2.0, 0.5, 0.5),
+            vec3(6.0, 0.0, 0.0),
+        ];
+        let outside = [
+            vec3(4.0, 0.0, 0.0),
+            vec3(1.25, 1.5, 0.0),
+            vec3(-2.0, 0.0, 0.0),
+        ];
+
+        for point in inside {
+            assert!(tree.contains_point(point), "{point}");
+        }
+
+        for point in outside {
+            assert!(!tree.contains_point(point), "{point}");
+        }
+
+        // Inverting turns the solid inside out
+        tree.invert();
+        for point in inside {
+            assert!(!tree.contains_point(point), "{point}");
+        }
+
+        for point in outside {
+            assert!(tree.contains_point(point), "{point}");
+        }
+    }
+
+    #[test]
+    fn into_polygons() {
+        let mut tree = cube(Vec3::ZERO, 1.0);
+        tree.union(cube(vec3(1.0, 0.0, 0.0), 1.0));
+
+        let node_faces: usize = tree.iter_nodes().map(|v| v.1.len()).sum();
+        assert_eq!(node_faces, tree.face_count());
+        for (plane, faces) in tree.iter_nodes() {
+            assert!(faces
+                .iter()
+                .all(|v| v.normal().dot(plane.normal).abs() > 0.99));
+        }
+
+        let cleaned = tree.polygons_cleaned(0.0);
+        assert_eq!(tree.polygons(), tree.clone().into_polygons());
+        assert_eq!(cleaned, tree.into_polygons_cleaned(0.0));
+    }
+
+    #[test]
+    fn raycast_sphere() {
+        let tree = BspTree::build(Brush::uv_sphere(1.0, 16, 12).faces()).unwrap();
+        assert_eq!(tree.face_count(), tree.iter_faces().count());
+
+        let brute_force = |origin: Vec3, dir: Vec3| {
+            tree.iter_faces()
+                .filter_map(|face| {
+                    let t = Plane::from_face(face).intersect_ray(origin, dir)?;
+                    face.contains_point(origin + dir * t).then_some((t, face))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+        };
+
+        // Deterministic pseudo random directions
+        let mut seed = 12345u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        };
+
+        let mut hits = 0;
+        for _ in 0..200 {
+            let origin = Vec3::new(random(), random(), random()).normalize() * 3.0;
+            let target = Vec3::new(random(), random(), random()) * 0.9;
+            let dir = target - origin;
+
+            let expected = brute_force(origin, dir);
+            let hit = tree.raycast(origin, dir);
+
+            match (hit, expected) {
+                (Some(hit), Some((t, _))) => {
+                    assert!((hit.t - t).abs() < 1e-3, "{} {t}", hit.t);
+                    assert!(hit.entering);
+                    hits += 1;
+                }
+                (None, None) => {}
+                (hit, expected) => panic!("{hit:?} != {expected:?}"),
+            }
+        }
+
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn raycast_cube() {
+        let tree = BspTree::build(Brush::cube().faces()).unwrap();
+
+        let hit = tree.raycast(Vec3::new(-3.0, 0.2, 0.1), Vec3::X).unwrap();
+        assert!(hit.entering);
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.point.distance(Vec3::new(-1.0, 0.2, 0.1)) < 1e-5);
+
+        let hit = tree.raycast(Vec3::ZERO, Vec3::Y * 2.0).unwrap();
+        assert!(!hit.entering);
+        assert!((hit.t - 0.5).abs() < 1e-5);
+
+        assert!(tree.raycast(Vec3::new(-3.0, 0.2, 0.1), -Vec3::X).is_none());
+        assert!(tree.raycast(Vec3::new(-3.0, 2.0, 0.0), Vec3::X).is_none());
+    }
+
+    fn cube(translation: Vec3, scale: f32) -> BspTree {
+        let faces = PositionedBrush::new(
+            Mat4::from_translation(translation) * Mat4::from_scale(Vec3::splat(scale)),
+            Brush::cube(),
+        )
+        .world_faces()
+        .collect_vec();
+
+        BspTree::build(&faces).unwrap()
+    }
+
+    #[test]
+    fn subtract_cavity() {
+        let mut tree = cube(Vec3::ZERO, 2.0);
+        tree.subtract(cube(Vec3::ZERO, 0.5));
+
+        let polygons = tree.polygons();
+        let area: f32 = polygons.iter().map(|v| v.area()).sum();
+        assert!((area - (96.0 + 6.0)).abs() < 0.01, "{area}");
+
+        let center = |face: &Face| face.points().iter().sum::<Vec3>() / face.points().len() as f32;
+
+        // The cavity faces point inwards, towards the removed volume
+        let cavity = polygons
+            .iter()
+            .filter(|v| center(v).abs().max_element() < 1.0)
+            .collect_vec();
+
+        assert!(!cavity.is_empty());
+        assert!(cavity.iter().all(|v| v.normal().dot(center(v)) < 0.0));
+
+        let cavity_area: f32 = cavity.iter().map(|v| v.area()).sum();
+        assert!((cavity_area - 6.0).abs() < 0.01, "{cavity_area}");
+    }
+
+    #[test]
+    fn intersect() {
+        let mut tree = cube(Vec3::ZERO, 1.0);
+        tree.intersect(cube(Vec3::new(1.0, 0.0, 0.0), 1.0));
+
+        // The shared volume is a 1x2x2 box
+        let area: f32 = tree.polygons().iter().map(|v| v.area()).sum();
+        assert!((area - 16.0).abs() < 0.01, "{area}");
+
+        let mut tree = cube(Vec3::ZERO, 1.0);
+        tree.intersect(cube(Vec3::new(5.0, 0.0, 0.0), 1.0));
+        assert!(tree.polygons().is_empty());
+    }
+
+    #[test]
+    fn mirrored_seam() {
+        let face = Brush::plane().faces()[0].clone();
+
+        let mut tree = BspTree::build(std::slice::from_ref(&face)).unwrap();
+        tree.append(&[face.clone(), face.flip()]);
+
+        assert!(tree.polygons_cleaned(0.0).is_empty());
+    }
+
+    #[test]
+    fn coplanar_back_faces() {
+        let face = Brush::plane().faces()[0].clone();
+        let beside = face
+            .transform(Mat4::from_translation(vec3(4.0, 0.0, 0.0)))
+            .flip();
+
+        let tree = BspTree::build(&[face.clone(), beside.clone()]).unwrap();
+        let (plane, faces) = tree.iter_nodes().next().unwrap();
+        assert_eq!(faces.len(), 2);
+        assert!(faces.iter().any(|v| v.normal().dot(plane.normal) < -0.99));
+
+        // The face pointing against the node plane keeps its winding
+        let polygons = tree.polygons_cleaned(0.0);
+        assert_eq!(polygons.len(), 2);
+        assert!(polygons.contains(&face));
+        assert!(polygons.contains(&beside));
+    }
+
+    #[test]
+    fn union_dense_sphere() {
+        let sphere = BspTree::build(Brush::uv_sphere(2.0, 64, 48).faces()).unwrap();
+
+        let floor = PositionedBrush::new(
+            Mat4::from_translation(vec3(0.0, -1.0, 0.0)) * Mat4::from_scale(vec3(20.0, 1.0, 20.0)),
+            Brush::cube(),
+        )
+        .world_faces()
+        .collect_vec();
+
+        let mut tree = BspTree::build(&floor).unwrap();
+        tree.union(sphere);
+
+        // The upper half of the sphere sticks out of the floor
+        let hit = tree.raycast(vec3(0.0, 10.0, 0.0), -Vec3::Y).unwrap();
+        assert!((hit.point.y - 2.0).abs() < 0.01, "{}", hit.point);
+
+        let hit = tree.raycast(vec3(10.0, 10.0, 10.0), -Vec3::Y).unwrap();
+        assert!(hit.point.y.abs() < 1e-4, "{}", hit.point);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_build() {
+        // Spheres on a grid, so that splitting planes divide them into large halves
+        let faces = (0..4)
+            .flat_map(|x| (0..4).map(move |z| (x, z)))
+            .flat_map(|(x, z)| {
+                PositionedBrush::new(
+                    Mat4::from_translation(vec3(x as f32 * 5.0, 0.0, z as f32 * 5.0)),
+                    Brush::uv_sphere(2.0, 16, 12),
+                )
+                .world_faces()
+                .collect_vec()
+            })
+            .collect_vec();
+        assert!(faces.len() > 4 * PARALLEL_BUILD_THRESHOLD);
+
+        let build = |parallel| BspTree::build_with_parallelism(&faces, TOLERANCE, parallel);
+        let (mut parallel, mut serial) = (build(true).unwrap(), build(false).unwrap());
+
+        // Spliced in the same order as the nodes are built serially
+        assert_eq!(parallel.nodes.len(), serial.nodes.len());
+        assert_eq!(parallel.polygons(), serial.polygons());
+        for ((a, node), (b, other)) in parallel.nodes.iter().zip(&serial.nodes) {
+            assert_eq!(a, b);
+            assert_eq!((node.front, node.back), (other.front, other.back));
+        }
+
+        let floor = cube(vec3(0.0, -1.0, 0.0), 3.0).polygons();
+        parallel.union(BspTree::build_with_parallelism(&floor, TOLERANCE, true).unwrap());
+        serial.union(BspTree::build_with_parallelism(&floor, TOLERANCE, false).unwrap());
+        assert_eq!(parallel.polygons(), serial.polygons());
+    }
+}
@@ -1 +1,18 @@
 pub const TOLERANCE: f32 = 0.001;
+
+/// Maps each item in order, on the rayon thread pool if `parallel` is set and the `rayon`
+/// feature is enabled
+#[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+pub(crate) fn par_map<T: Sync, U: Send>(
+    items: &[T],
+    parallel: bool,
+    f: impl Fn(&T) -> U + Sync + Send,
+) -> Vec<U> {
+    #[cfg(feature = "rayon")]
+    if parallel {
+        use rayon::prelude::*;
+        return items.par_iter().map(f).collect();
+    }
+
+    items.iter().map(f).collect()
+}